@@ -0,0 +1,543 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::Instant,
+};
+
+use dns::{
+    dnsmsg::{parent, substitute_dname},
+    header::ResultCode,
+    question::QueryType,
+    record::DnsRecord,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    pub name: String,
+    pub qtype: QueryType,
+    pub class: u16,
+}
+
+impl CacheKey {
+    pub fn new(name: &str, qtype: QueryType, class: u16) -> Self {
+        Self {
+            name: name.trim_end_matches('.').to_lowercase(),
+            qtype,
+            class,
+        }
+    }
+
+    /// key for "this name does not exist". Type 0 is reserved and never asked for,
+    /// so it can stand for every type at the name
+    pub fn nxdomain(name: &str, class: u16) -> Self {
+        Self::new(name, QueryType::Unknown(0), class)
+    }
+}
+
+/// what a cache entry says about its key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EntryKind {
+    /// the records themselves
+    Positive,
+    /// the name exists but has no records of this type, we hold the SOA
+    NoData,
+    /// the name does not exist at all, we hold the SOA
+    NxDomain,
+}
+
+/// how far a record set can be trusted, going by the section it came from (RFC 2181 5.4.1).
+/// A set is never replaced by one that ranks lower
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Trust {
+    /// the additional section, glue addresses for the servers of a referral
+    Additional,
+    /// the authority section, the NS records of a referral
+    Authority,
+    /// the answer section, or a negative answer backed by the zone's SOA
+    Answer,
+}
+
+/// an answer assembled from the cache
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedAnswer {
+    pub rescode: ResultCode,
+    pub answers: Vec<DnsRecord>,
+    /// the SOA of negative answers, with the NSEC records proving them
+    pub authorities: Vec<DnsRecord>,
+}
+
+#[derive(Debug, Clone)]
+struct CacheEntry {
+    kind: EntryKind,
+    records: Vec<DnsRecord>,
+    trust: Trust,
+    stored_at: Instant,
+    /// seconds after `stored_at` at which the whole set is stale
+    lifetime: u32,
+    /// position in the LRU order
+    last_used: u64,
+}
+
+impl CacheEntry {
+    fn age(&self, now: Instant) -> u32 {
+        now.saturating_duration_since(self.stored_at).as_secs() as u32
+    }
+
+    fn expired(&self, now: Instant) -> bool {
+        self.age(now) >= self.lifetime
+    }
+
+    /// the records with their TTLs counted down to what is left of them
+    fn records(&self, now: Instant) -> Vec<DnsRecord> {
+        let age = self.age(now);
+        self.records
+            .iter()
+            .cloned()
+            .map(|mut rec| {
+                rec.set_ttl(rec.ttl().saturating_sub(age));
+                rec
+            })
+            .collect()
+    }
+}
+
+/// A TTL aware record cache, keyed by (name, type, class).
+/// Once `max_entries` sets are stored the least recently used one is evicted.
+#[derive(Debug)]
+pub struct Cache {
+    entries: HashMap<CacheKey, CacheEntry>,
+    /// last_used tick -> key, oldest first
+    lru: BTreeMap<u64, CacheKey>,
+    tick: u64,
+    max_entries: usize,
+}
+
+impl Cache {
+    pub fn new(max_entries: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            lru: BTreeMap::new(),
+            tick: 0,
+            max_entries,
+        }
+    }
+
+    pub fn set_max_entries(&mut self, max_entries: usize) {
+        self.max_entries = max_entries;
+        while self.entries.len() > self.max_entries {
+            self.evict_one();
+        }
+    }
+
+    /// the record set stored for `key` with decremented TTLs, if it is still fresh
+    pub fn get(&mut self, key: &CacheKey) -> Option<Vec<DnsRecord>> {
+        match self.lookup(key)? {
+            (EntryKind::Positive, records) => Some(records),
+            _ => None,
+        }
+    }
+
+    fn lookup(&mut self, key: &CacheKey) -> Option<(EntryKind, Vec<DnsRecord>)> {
+        let now = Instant::now();
+        let expired = self.entries.get(key)?.expired(now);
+        if expired {
+            self.remove(key);
+            return None;
+        }
+
+        self.tick += 1;
+        let entry = self.entries.get_mut(key)?;
+        self.lru.remove(&entry.last_used);
+        entry.last_used = self.tick;
+        self.lru.insert(self.tick, key.clone());
+
+        Some((entry.kind, entry.records(now)))
+    }
+
+    /// looks up an answer for `name`, following up to `max_cname_chain` cached CNAMEs
+    /// and DNAMEs. the CNAME and DNAME records come first, followed by the final
+    /// record set or the SOA of a cached NXDOMAIN / NODATA
+    pub fn get_answer(
+        &mut self,
+        name: &str,
+        qtype: QueryType,
+        class: u16,
+        max_cname_chain: usize,
+    ) -> Option<CachedAnswer> {
+        let mut answer = Vec::new();
+        let mut name = name.to_string();
+
+        for _ in 0..=max_cname_chain {
+            let found = self
+                .lookup(&CacheKey::nxdomain(&name, class))
+                .or_else(|| self.lookup(&CacheKey::new(&name, qtype, class)));
+            if let Some((kind, records)) = found {
+                let (rescode, authorities) = match kind {
+                    EntryKind::Positive => {
+                        answer.extend(records);
+                        (ResultCode::NoError, Vec::new())
+                    }
+                    EntryKind::NoData => (ResultCode::NoError, records),
+                    EntryKind::NxDomain => (ResultCode::NXDomain, records),
+                };
+                return Some(CachedAnswer {
+                    rescode,
+                    answers: answer,
+                    authorities,
+                });
+            }
+            if qtype == QueryType::CNAME {
+                return None;
+            }
+
+            let cname = self
+                .get(&CacheKey::new(&name, QueryType::CNAME, class))
+                .or_else(|| self.get_dname(&name, class))?;
+            name = cname.iter().find_map(|rec| match rec {
+                DnsRecord::CNAME { host, .. } => Some(host.clone()),
+                _ => None,
+            })?;
+            answer.extend(cname);
+        }
+
+        None
+    }
+
+    /// the DNAME cached for an ancestor of `name` followed by the CNAME
+    /// it stands for (RFC 6672 3.4)
+    fn get_dname(&mut self, name: &str, class: u16) -> Option<Vec<DnsRecord>> {
+        let mut owner = name;
+        while !owner.is_empty() {
+            owner = parent(owner);
+            let Some(mut records) = self.get(&CacheKey::new(owner, QueryType::DNAME, class)) else {
+                continue;
+            };
+            let (host, ttl) = records.iter().find_map(|rec| match rec {
+                DnsRecord::DNAME { target, ttl, .. } => {
+                    Some((substitute_dname(name, owner, target)?, *ttl))
+                }
+                _ => None,
+            })?;
+            records.push(DnsRecord::CNAME {
+                domain: name.to_string(),
+                host,
+                ttl,
+            });
+            return Some(records);
+        }
+        None
+    }
+
+    /// stores a record set, all records are expected to share name, type and class.
+    /// sets with a zero TTL are not worth keeping, and neither are sets that would
+    /// replace one we trust more
+    pub fn insert(&mut self, key: CacheKey, records: Vec<DnsRecord>, trust: Trust) {
+        let nxdomain = CacheKey::nxdomain(&key.name, key.class);
+        if self.outranks(&key, trust) || self.outranks(&nxdomain, trust) {
+            return;
+        }
+        let lifetime = records.iter().map(|rec| rec.ttl()).min().unwrap_or(0);
+        // the name evidently exists now
+        self.remove(&nxdomain);
+        self.insert_entry(key, EntryKind::Positive, records, trust, lifetime);
+    }
+
+    /// whether a fresh entry for `key` ranks above `trust`
+    fn outranks(&self, key: &CacheKey, trust: Trust) -> bool {
+        self.entries
+            .get(key)
+            .is_some_and(|entry| entry.trust > trust && !entry.expired(Instant::now()))
+    }
+
+    /// caches a negative answer for `name` (RFC 2308). With `nxdomain` set the whole name
+    /// is gone, otherwise only `qtype` is missing. The SOA from the authority section decides
+    /// how long we keep it: the smaller of its own TTL and its minimum field.
+    /// `proof` are the NSEC records and signatures that came with it, kept for validation
+    pub fn insert_negative(
+        &mut self,
+        name: &str,
+        qtype: QueryType,
+        class: u16,
+        nxdomain: bool,
+        mut soa: DnsRecord,
+        proof: Vec<DnsRecord>,
+    ) {
+        let lifetime = match soa {
+            DnsRecord::SOA { minimum, ttl, .. } => minimum.min(ttl),
+            _ => return,
+        };
+        soa.set_ttl(lifetime);
+        let mut records = vec![soa];
+        records.extend(proof);
+
+        let (key, kind) = if nxdomain {
+            (CacheKey::nxdomain(name, class), EntryKind::NxDomain)
+        } else {
+            (CacheKey::new(name, qtype, class), EntryKind::NoData)
+        };
+        self.insert_entry(key, kind, records, Trust::Answer, lifetime);
+    }
+
+    fn insert_entry(
+        &mut self,
+        key: CacheKey,
+        kind: EntryKind,
+        records: Vec<DnsRecord>,
+        trust: Trust,
+        lifetime: u32,
+    ) {
+        if lifetime == 0 || self.max_entries == 0 {
+            return;
+        }
+
+        let now = Instant::now();
+        self.remove(&key);
+        if self.entries.len() >= self.max_entries {
+            self.evict_one();
+        }
+
+        self.tick += 1;
+        self.lru.insert(self.tick, key.clone());
+        self.entries.insert(
+            key,
+            CacheEntry {
+                kind,
+                records,
+                trust,
+                stored_at: now,
+                lifetime,
+                last_used: self.tick,
+            },
+        );
+    }
+
+    /// groups `records` into sets by name and type and stores each of them with `trust`.
+    /// signatures are kept with the set they cover
+    pub fn insert_records(&mut self, records: &[DnsRecord], class: u16, trust: Trust) {
+        let mut sets: HashMap<CacheKey, Vec<DnsRecord>> = HashMap::new();
+        for rec in records {
+            let qtype = match rec {
+                DnsRecord::RRSIG { type_covered, .. } => *type_covered,
+                _ => rec.qtype(),
+            };
+            sets.entry(CacheKey::new(rec.domain(), qtype, class))
+                .or_default()
+                .push(rec.clone());
+        }
+        for (key, set) in sets {
+            // signatures alone would push out the set they belong to
+            if set.iter().all(|rec| matches!(rec, DnsRecord::RRSIG { .. })) {
+                continue;
+            }
+            self.insert(key, set, trust);
+        }
+    }
+
+    pub fn remove(&mut self, key: &CacheKey) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.lru.remove(&entry.last_used);
+        }
+    }
+
+    /// makes room for one entry by dropping the least recently used one. stale entries
+    /// go when they are looked up or purged, scanning for them here would make every
+    /// insert into a full cache go over all of it
+    fn evict_one(&mut self) {
+        if let Some((_, key)) = self.lru.pop_first() {
+            self.entries.remove(&key);
+        }
+    }
+
+    /// drops everything that outlived its TTL
+    pub fn purge_expired(&mut self) {
+        let now = Instant::now();
+        let expired: Vec<CacheKey> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.expired(now))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in expired {
+            self.remove(&key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(name: &str) -> CacheKey {
+        CacheKey::new(name, QueryType::A, 1)
+    }
+
+    fn a(name: &str) -> Vec<DnsRecord> {
+        vec![DnsRecord::A {
+            domain: name.to_string(),
+            addr: [192, 0, 2, 1].into(),
+            ttl: 300,
+        }]
+    }
+
+    fn soa(ttl: u32, minimum: u32) -> DnsRecord {
+        DnsRecord::SOA {
+            domain: "example".to_string(),
+            mname: "ns.example".to_string(),
+            rname: "hostmaster.example".to_string(),
+            serial: 1,
+            refresh: 3600,
+            retry: 600,
+            expire: 86400,
+            minimum,
+            ttl,
+        }
+    }
+
+    #[test]
+    fn evicts_the_least_recently_used_set() {
+        let mut cache = Cache::new(2);
+        cache.insert(key("a.example"), a("a.example"), Trust::Answer);
+        cache.insert(key("b.example"), a("b.example"), Trust::Answer);
+        // a is used again, so b is the one to go
+        assert!(cache.get(&key("a.example")).is_some());
+        cache.insert(key("c.example"), a("c.example"), Trust::Answer);
+
+        assert_eq!(cache.entries.len(), 2);
+        assert!(cache.get(&key("a.example")).is_some());
+        assert!(cache.get(&key("b.example")).is_none());
+        assert!(cache.get(&key("c.example")).is_some());
+
+        cache.set_max_entries(1);
+        assert_eq!(cache.entries.len(), 1);
+        assert!(cache.get(&key("c.example")).is_some());
+    }
+
+    #[test]
+    fn follows_cname_chains_up_to_the_limit() {
+        let mut cache = Cache::new(16);
+        for (name, host) in [("a.example", "b.example"), ("b.example", "c.example")] {
+            let cname = DnsRecord::CNAME {
+                domain: name.to_string(),
+                host: host.to_string(),
+                ttl: 300,
+            };
+            cache.insert(
+                CacheKey::new(name, QueryType::CNAME, 1),
+                vec![cname],
+                Trust::Answer,
+            );
+        }
+        cache.insert(key("c.example"), a("c.example"), Trust::Answer);
+
+        let answer = cache.get_answer("a.example", QueryType::A, 1, 2).unwrap();
+        assert_eq!(answer.rescode, ResultCode::NoError);
+        assert_eq!(answer.answers.len(), 3);
+        assert_eq!(answer.answers[2], a("c.example")[0]);
+
+        assert!(cache.get_answer("a.example", QueryType::A, 1, 1).is_none());
+        assert!(cache.get_answer("b.example", QueryType::A, 1, 1).is_some());
+    }
+
+    #[test]
+    fn glue_never_replaces_an_answer() {
+        let mut cache = Cache::new(16);
+        let glue = vec![DnsRecord::A {
+            domain: "a.example".to_string(),
+            addr: [198, 51, 100, 1].into(),
+            ttl: 300,
+        }];
+        cache.insert(key("a.example"), a("a.example"), Trust::Answer);
+        cache.insert(key("a.example"), glue.clone(), Trust::Additional);
+        assert_eq!(cache.get(&key("a.example")), Some(a("a.example")));
+
+        // the other way around the answer wins as well
+        cache.insert(key("b.example"), glue, Trust::Additional);
+        cache.insert(key("b.example"), a("b.example"), Trust::Authority);
+        assert_eq!(cache.get(&key("b.example")), Some(a("b.example")));
+    }
+
+    #[test]
+    fn negative_answers_live_as_long_as_the_soa_allows() {
+        let mut cache = Cache::new(16);
+        cache.insert_negative(
+            "a.example",
+            QueryType::A,
+            1,
+            true,
+            soa(3600, 300),
+            Vec::new(),
+        );
+        cache.insert_negative(
+            "b.example",
+            QueryType::A,
+            1,
+            false,
+            soa(60, 300),
+            Vec::new(),
+        );
+
+        let answer = cache.get_answer("a.example", QueryType::A, 1, 0).unwrap();
+        assert_eq!(answer.authorities[0].ttl(), 300);
+        let answer = cache.get_answer("b.example", QueryType::A, 1, 0).unwrap();
+        assert_eq!(answer.authorities[0].ttl(), 60);
+    }
+
+    #[test]
+    fn nxdomain_answers_every_type_at_the_name() {
+        let mut cache = Cache::new(16);
+        cache.insert_negative(
+            "a.example",
+            QueryType::A,
+            1,
+            true,
+            soa(300, 300),
+            Vec::new(),
+        );
+
+        for qtype in [QueryType::A, QueryType::AAAA, QueryType::MX] {
+            let answer = cache.get_answer("a.example", qtype, 1, 0).unwrap();
+            assert_eq!(answer.rescode, ResultCode::NXDomain);
+            assert!(answer.answers.is_empty());
+            assert_eq!(answer.authorities, vec![soa(300, 300)]);
+        }
+    }
+
+    #[test]
+    fn nodata_answers_only_its_own_type() {
+        let mut cache = Cache::new(16);
+        cache.insert_negative(
+            "a.example",
+            QueryType::AAAA,
+            1,
+            false,
+            soa(300, 300),
+            Vec::new(),
+        );
+
+        let answer = cache
+            .get_answer("a.example", QueryType::AAAA, 1, 0)
+            .unwrap();
+        assert_eq!(answer.rescode, ResultCode::NoError);
+        assert!(answer.answers.is_empty());
+        assert_eq!(answer.authorities, vec![soa(300, 300)]);
+        assert!(cache.get_answer("a.example", QueryType::A, 1, 0).is_none());
+    }
+
+    #[test]
+    fn records_for_the_name_clear_its_nxdomain() {
+        let mut cache = Cache::new(16);
+        cache.insert_negative(
+            "a.example",
+            QueryType::A,
+            1,
+            true,
+            soa(300, 300),
+            Vec::new(),
+        );
+        cache.insert(key("a.example"), a("a.example"), Trust::Answer);
+
+        let answer = cache.get_answer("a.example", QueryType::A, 1, 0).unwrap();
+        assert_eq!(answer.rescode, ResultCode::NoError);
+        assert_eq!(answer.answers, a("a.example"));
+        assert!(cache.get_answer("a.example", QueryType::MX, 1, 0).is_none());
+    }
+}
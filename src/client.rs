@@ -0,0 +1,333 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    net::{IpAddr, SocketAddr, TcpStream, UdpSocket},
+    time::{Duration, Instant},
+};
+
+use ring::rand::{SecureRandom, SystemRandom};
+
+use crate::{
+    dnsmsg::DnsPackets,
+    edns::{Edns, DEFAULT_UDP_PAYLOAD},
+    header::ResultCode,
+    packet::{VectorPacketBuffer, MAX_MESSAGE_SIZE},
+    question::QueryType,
+    record::{reverse_name, DnsRecord},
+    tcp,
+};
+
+/// how long we wait for a server to answer
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// a stub resolver, it leaves the resolving to a recursive server
+#[derive(Debug, Clone)]
+pub struct Client {
+    server: SocketAddr,
+    timeout: Duration,
+}
+
+impl Client {
+    /// a client for the recursive server at `server`
+    pub fn new(server: SocketAddr) -> Client {
+        Client {
+            server,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// how long we wait for an answer before giving up
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// asks the server to resolve `name` for us
+    pub fn query(
+        &self,
+        name: &str,
+        qtype: QueryType,
+    ) -> Result<DnsPackets, Box<dyn std::error::Error>> {
+        let query =
+            DnsPackets::query(random_id(), name, qtype).with_edns(Edns::new(DEFAULT_UDP_PAYLOAD));
+        exchange(&query, self.server, self.timeout)
+    }
+
+    /// the names `addr` points back to, none if it has no PTR records.
+    /// CNAMEs are followed, classless in-addr.arpa delegation relies on them (RFC 2317)
+    pub fn reverse_lookup(&self, addr: IpAddr) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let name = reverse_name(addr);
+        let response = self.query(&name, QueryType::PTR)?;
+        match response.header.rescode {
+            ResultCode::NoError => {}
+            ResultCode::NXDomain => return Ok(Vec::new()),
+            rescode => return Err(format!("{} answered {:?}", self.server, rescode).into()),
+        }
+
+        let target = response.resolve_cname(&name);
+        Ok(response
+            .answers_for(target, QueryType::PTR)
+            .filter_map(|record| match record {
+                DnsRecord::PTR { host, .. } => Some(host.clone()),
+                _ => None,
+            })
+            .collect())
+    }
+
+    /// where the service `name` (like `_ldap._tcp.example.com`) can be reached, as
+    /// (host, port) pairs in the order they should be tried, see [`order_srv`]
+    pub fn lookup_srv(&self, name: &str) -> Result<Vec<(String, u16)>, Box<dyn std::error::Error>> {
+        let response = self.query(name, QueryType::SRV)?;
+        match response.header.rescode {
+            ResultCode::NoError => {}
+            ResultCode::NXDomain => return Ok(Vec::new()),
+            rescode => return Err(format!("{} answered {:?}", self.server, rescode).into()),
+        }
+
+        let target = response.resolve_cname(name);
+        let records: Vec<DnsRecord> = response
+            .answers_for(target, QueryType::SRV)
+            .cloned()
+            .collect();
+        Ok(order_srv(&records))
+    }
+}
+
+/// the targets of the SRV records among `records` in the order a client should try
+/// them (RFC 2782): lowest priority first, and within a priority each next one picked
+/// at random with a chance proportional to its weight.
+/// a lone target of "." means the service is decidedly not available
+pub fn order_srv(records: &[DnsRecord]) -> Vec<(String, u16)> {
+    order_srv_with(records, random)
+}
+
+/// [`order_srv`] drawing its random numbers from `random`
+fn order_srv_with(records: &[DnsRecord], mut random: impl FnMut() -> u64) -> Vec<(String, u16)> {
+    let mut targets: Vec<(u16, u16, u16, &str)> = records
+        .iter()
+        .filter_map(|record| match record {
+            DnsRecord::SRV {
+                priority,
+                weight,
+                port,
+                host,
+                ..
+            } => Some((*priority, *weight, *port, host.as_str())),
+            _ => None,
+        })
+        .collect();
+    if let [(_, _, _, "")] = targets[..] {
+        return Vec::new();
+    }
+    targets.sort_by_key(|(priority, _, _, _)| *priority);
+
+    let mut ordered = Vec::with_capacity(targets.len());
+    let mut rest = &mut targets[..];
+    while !rest.is_empty() {
+        let priority = rest[0].0;
+        let len = rest.iter().take_while(|t| t.0 == priority).count();
+        let (group, tail) = rest.split_at_mut(len);
+
+        for i in 0..group.len() {
+            // the zero weights go first so they get a (small) chance to be picked early
+            group[i..].sort_by_key(|t| t.1 > 0);
+            let total: u64 = group[i..].iter().map(|t| t.1 as u64).sum();
+            let pick = random() % (total + 1);
+            let mut sum = 0;
+            let chosen = (i..group.len())
+                .find(|&j| {
+                    sum += group[j].1 as u64;
+                    sum >= pick
+                })
+                .unwrap_or(i);
+            group.swap(i, chosen);
+            ordered.push((group[i].3.to_string(), group[i].2));
+        }
+        rest = tail;
+    }
+    ordered
+}
+
+/// sends `query` to `server` and waits for the response.
+/// goes over UDP first and retries over TCP if the answer came back truncated
+pub fn exchange(
+    query: &DnsPackets,
+    server: SocketAddr,
+    timeout: Duration,
+) -> Result<DnsPackets, Box<dyn std::error::Error>> {
+    let mut response = exchange_udp(query, server, timeout)?;
+
+    // servers that predate EDNS tend to reject the OPT record (RFC 6891 7)
+    let mut query = query.clone();
+    if query.edns.is_some()
+        && response.edns.is_none()
+        && matches!(
+            response.header.rescode,
+            ResultCode::FormerR | ResultCode::NOTimP
+        )
+    {
+        query.edns = None;
+        query.header.id = random_id();
+        response = exchange_udp(&query, server, timeout)?;
+    }
+
+    if !response.header.truncated_msg {
+        return Ok(response);
+    }
+    exchange_tcp(&query, server, timeout)
+}
+
+/// sends `query` over UDP, anything that isnt the response to it is ignored
+pub fn exchange_udp(
+    query: &DnsPackets,
+    server: SocketAddr,
+    timeout: Duration,
+) -> Result<DnsPackets, Box<dyn std::error::Error>> {
+    // Bind a UDP socket to an arbitrary port of the same family as the server
+    let socket = if server.is_ipv4() {
+        UdpSocket::bind(("0.0.0.0", 0))?
+    } else {
+        UdpSocket::bind(("::", 0))?
+    };
+
+    let mut req_buff = VectorPacketBuffer::new();
+    query.clone().write(&mut req_buff)?;
+
+    socket.send_to(req_buff.as_bytes(), server)?;
+
+    let deadline = Instant::now() + timeout;
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(format!("Timed out waiting for {}", server).into());
+        }
+        socket.set_read_timeout(Some(remaining))?;
+
+        let mut raw = [0u8; MAX_MESSAGE_SIZE];
+        let (len, source) = socket.recv_from(&mut raw)?;
+        if source != server {
+            continue;
+        }
+
+        let mut res_buff = VectorPacketBuffer::from_bytes(&raw[..len]);
+        let res_packet = DnsPackets::from_buffer(&mut res_buff)?;
+        if res_packet.header.id == query.header.id && res_packet.header.response {
+            return Ok(res_packet);
+        }
+    }
+}
+
+/// sends `query` over a fresh TCP connection
+pub fn exchange_tcp(
+    query: &DnsPackets,
+    server: SocketAddr,
+    timeout: Duration,
+) -> Result<DnsPackets, Box<dyn std::error::Error>> {
+    let mut stream = TcpStream::connect_timeout(&server, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+
+    let mut req_buff = VectorPacketBuffer::new();
+    query.clone().write(&mut req_buff)?;
+    tcp::write_message(&mut stream, req_buff.as_bytes())?;
+
+    let message = tcp::read_message(&mut stream)?;
+    let mut res_buff = VectorPacketBuffer::from_bytes(&message);
+    let res_packet = DnsPackets::from_buffer(&mut res_buff)?;
+    if res_packet.header.id != query.header.id || !res_packet.header.response {
+        return Err(format!("{} answered with a mismatched message", server).into());
+    }
+    Ok(res_packet)
+}
+
+/// an unpredictable query id, guessing it is all it takes to spoof a response (RFC 5452 4.3)
+pub fn random_id() -> u16 {
+    random() as u16
+}
+
+/// random bits from the operating system's generator
+fn random() -> u64 {
+    let mut bytes = [0u8; 8];
+    if SystemRandom::new().fill(&mut bytes).is_ok() {
+        return u64::from_be_bytes(bytes);
+    }
+    // std seeds the keys of RandomState once per thread and only counts them up from
+    // there. that is guessable for whoever sees enough ids, but still better than none
+    RandomState::new().build_hasher().finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn srv(priority: u16, weight: u16, host: &str) -> DnsRecord {
+        DnsRecord::SRV {
+            domain: "_sip._udp.example".to_string(),
+            priority,
+            weight,
+            port: 5060,
+            host: host.to_string(),
+            ttl: 300,
+        }
+    }
+
+    fn hosts(ordered: Vec<(String, u16)>) -> Vec<String> {
+        ordered.into_iter().map(|(host, _)| host).collect()
+    }
+
+    #[test]
+    fn lower_priorities_come_first() {
+        let records = [
+            srv(20, 0, "c"),
+            srv(10, 0, "a"),
+            srv(30, 0, "d"),
+            srv(10, 0, "b"),
+        ];
+        let ordered = hosts(order_srv_with(&records, || 0));
+        assert_eq!(ordered, ["a", "b", "c", "d"]);
+        assert_eq!(hosts(order_srv(&records))[2..], ["c", "d"]);
+    }
+
+    #[test]
+    fn zero_weights_come_first_within_a_priority() {
+        let records = [
+            srv(10, 50, "heavy"),
+            srv(10, 0, "none"),
+            srv(10, 10, "light"),
+        ];
+        // drawing 0 picks the first candidate whose running sum reaches it
+        assert_eq!(
+            hosts(order_srv_with(&records, || 0)),
+            ["none", "heavy", "light"]
+        );
+    }
+
+    #[test]
+    fn picks_by_weight_and_uses_every_target_once() {
+        let records = [srv(10, 10, "a"), srv(10, 20, "b"), srv(10, 30, "c")];
+        // the first draw of 25 lands on b (10 < 25 <= 30), the next of 35 on c (20+30 >= 35)
+        let mut draws = [25, 35, 0].into_iter();
+        let ordered = hosts(order_srv_with(&records, || draws.next().unwrap()));
+        assert_eq!(ordered, ["b", "c", "a"]);
+
+        for seed in 0..100u64 {
+            let mut state = seed;
+            let mut ordered = hosts(order_srv_with(&records, || {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                state >> 33
+            }));
+            ordered.sort();
+            assert_eq!(ordered, ["a", "b", "c"]);
+        }
+    }
+
+    #[test]
+    fn a_lone_root_target_means_no_service() {
+        assert!(order_srv(&[srv(0, 0, "")]).is_empty());
+        assert_eq!(
+            hosts(order_srv(&[srv(0, 0, "sip.example")])),
+            ["sip.example"]
+        );
+    }
+}
@@ -1,22 +1,40 @@
-use core::error;
+use std::net::IpAddr;
 
 use crate::{
-    header::DnsHeader,
-    packet::BytePacketBuffer,
+    edns::Edns,
+    error::DnsError,
+    header::{DnsHeader, ResultCode},
+    packet::{PacketBuffer, VectorPacketBuffer},
     question::{DnsQuestion, QueryType},
     record::DnsRecord,
 };
 
+/// a whole DNS message. build one with [`DnsPackets::query`] or [`DnsPackets::response_to`]
+/// and the `with_*` methods, or read one with [`DnsPackets::from_buffer`]
 #[derive(Debug, Clone)]
 pub struct DnsPackets {
+    /// id, flags and result code. the section counts are filled in by `write`
     pub header: DnsHeader,
+    /// what is being asked, in practice always a single question
     pub questions: Vec<DnsQuestion>,
+    /// the answer section
     pub answers: Vec<DnsRecord>,
+    /// the authority section, NS records of a referral or the SOA of a negative answer
     pub authoritiees: Vec<DnsRecord>,
+    /// the additional section, without the OPT record
     pub resources: Vec<DnsRecord>,
+    /// the OPT pseudo-record of the additional section, kept apart from `resources`
+    pub edns: Option<Edns>,
+}
+
+impl Default for DnsPackets {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl DnsPackets {
+    /// an empty message with a zeroed header
     pub fn new() -> DnsPackets {
         DnsPackets {
             header: DnsHeader::new(),
@@ -24,16 +42,97 @@ impl DnsPackets {
             answers: Vec::new(),
             authoritiees: Vec::new(),
             resources: Vec::new(),
+            edns: None,
         }
     }
-    pub fn write(
-        &mut self,
-        packet: &mut BytePacketBuffer,
-    ) -> Result<(), Box<dyn std::error::Error>> {
+
+    /// a query for `name` and `qtype` asking for recursion
+    pub fn query(id: u16, name: &str, qtype: QueryType) -> DnsPackets {
+        let mut packet = DnsPackets::new();
+        packet.header.id = id;
+        packet.header.recursion_desired = true;
+        packet.with_question(DnsQuestion::new(name.to_string(), qtype))
+    }
+
+    /// an empty response to `request`, carrying over its id, opcode,
+    /// recursion desired flag and questions
+    pub fn response_to(request: &DnsPackets) -> DnsPackets {
+        let mut packet = DnsPackets::new();
+        packet.header.id = request.header.id;
+        packet.header.opcode = request.header.opcode;
+        packet.header.recursion_desired = request.header.recursion_desired;
+        packet.header.response = true;
+        packet.questions = request.questions.clone();
+        packet
+    }
+
+    /// adds `question` to the question section
+    pub fn with_question(mut self, question: DnsQuestion) -> DnsPackets {
+        self.questions.push(question);
+        self
+    }
+
+    /// adds `record` to the answer section
+    pub fn with_answer(mut self, record: DnsRecord) -> DnsPackets {
+        self.answers.push(record);
+        self
+    }
+
+    /// adds `record` to the authority section
+    pub fn with_authority(mut self, record: DnsRecord) -> DnsPackets {
+        self.authoritiees.push(record);
+        self
+    }
+
+    /// adds `record` to the additional section
+    pub fn with_resource(mut self, record: DnsRecord) -> DnsPackets {
+        self.resources.push(record);
+        self
+    }
+
+    /// sends `edns` along in an OPT record
+    pub fn with_edns(mut self, edns: Edns) -> DnsPackets {
+        self.edns = Some(edns);
+        self
+    }
+
+    /// sets the result code, extended ones go out in the OPT record
+    pub fn with_rescode(mut self, rescode: ResultCode) -> DnsPackets {
+        self.header.rescode = rescode;
+        self
+    }
+
+    /// the records of the answer, authority and additional sections, in that order.
+    /// the OPT record is not among them, see `edns`
+    pub fn records(&self) -> impl Iterator<Item = &DnsRecord> {
+        self.answers
+            .iter()
+            .chain(self.authoritiees.iter())
+            .chain(self.resources.iter())
+    }
+
+    /// records of the answer section owned by `name` with type `qtype`
+    pub fn answers_for<'a>(
+        &'a self,
+        name: &'a str,
+        qtype: QueryType,
+    ) -> impl Iterator<Item = &'a DnsRecord> {
+        self.answers.iter().filter(move |record| {
+            record.qtype() == qtype && record.domain().eq_ignore_ascii_case(name)
+        })
+    }
+
+    /// encodes the message into `packet`, updating the header's section counts on the way
+    pub fn write<T: PacketBuffer>(&mut self, packet: &mut T) -> Result<(), DnsError> {
         self.header.questions = self.questions.len() as u16;
         self.header.answers = self.answers.len() as u16;
         self.header.authorative_entries = self.authoritiees.len() as u16;
-        self.header.resource_entries = self.resources.len() as u16;
+        self.header.resource_entries = (self.resources.len() + self.edns.is_some() as usize) as u16;
+
+        // the upper bits of extended RCODEs travel in the OPT record
+        if let Some(edns) = self.edns.as_mut() {
+            edns.extended_rcode = u8::from(self.header.rescode) >> 4;
+        }
 
         self.header.write(packet)?;
         for question in &self.questions {
@@ -49,11 +148,50 @@ impl DnsPackets {
         for resource in &self.resources {
             resource.write(packet)?;
         }
+        if let Some(edns) = &self.edns {
+            edns.write(packet)?;
+        }
         Ok(())
     }
-    pub fn from_buffer(
-        buffer: &mut BytePacketBuffer,
-    ) -> Result<DnsPackets, Box<dyn std::error::Error>> {
+
+    /// encodes the message for a client that takes at most `max_size` bytes.
+    /// the additional section is given up first, if that isnt enough only the header and
+    /// question go out with the TC bit set, telling the client to retry over TCP
+    pub fn write_with_limit(&mut self, max_size: usize) -> Result<VectorPacketBuffer, DnsError> {
+        if let Some(buffer) = self.write_within(max_size)? {
+            return Ok(buffer);
+        }
+
+        let mut trimmed = self.clone();
+        trimmed.resources.clear();
+        if let Some(buffer) = trimmed.write_within(max_size)? {
+            return Ok(buffer);
+        }
+
+        let mut truncated = self.clone();
+        truncated.header.truncated_msg = true;
+        truncated.answers.clear();
+        truncated.authoritiees.clear();
+        truncated.resources.clear();
+        let mut buffer = VectorPacketBuffer::with_limit(max_size);
+        truncated.write(&mut buffer)?;
+        Ok(buffer)
+    }
+
+    /// the encoded message, or None if it takes more than `max_size` bytes.
+    /// anything wrong with the message itself is still an error
+    fn write_within(&mut self, max_size: usize) -> Result<Option<VectorPacketBuffer>, DnsError> {
+        let mut buffer = VectorPacketBuffer::with_limit(max_size);
+        match self.write(&mut buffer) {
+            Ok(()) => Ok(Some(buffer)),
+            Err(DnsError::BufferOverflow { .. }) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// decodes a whole message from `buffer`, merging the extended result code
+    /// of an OPT record into the header
+    pub fn from_buffer<T: PacketBuffer>(buffer: &mut T) -> Result<DnsPackets, DnsError> {
         let mut result = DnsPackets::new();
         result.header.read(buffer)?;
 
@@ -72,10 +210,308 @@ impl DnsPackets {
             result.authoritiees.push(rec);
         }
         for _ in 0..result.header.resource_entries {
-            let rec = DnsRecord::read(buffer)?;
-            result.resources.push(rec);
+            // peek at the type to tell the OPT pseudo-record apart
+            let start = buffer.pos();
+            buffer.read_qname(&mut String::new())?;
+            let qtype = QueryType::from(buffer.read_u16()?);
+            buffer.seek(start)?;
+
+            if qtype == QueryType::OPT {
+                if result.edns.is_some() {
+                    return Err(DnsError::MultipleOpt { offset: start });
+                }
+                result.edns = Some(Edns::read(buffer)?);
+            } else {
+                let rec = DnsRecord::read(buffer)?;
+                result.resources.push(rec);
+            }
+        }
+
+        if let Some(edns) = &result.edns {
+            let rescode =
+                ((edns.extended_rcode as u16) << 4) | u8::from(result.header.rescode) as u16;
+            if let Ok(rescode) = u8::try_from(rescode) {
+                result.header.rescode = ResultCode::from(rescode);
+            }
         }
 
         Ok(result)
     }
+
+    /// NS records in the authority section for zones that `qname` falls under,
+    /// as (zone, name server host) pairs
+    pub fn get_ns<'a>(&'a self, qname: &'a str) -> impl Iterator<Item = (&'a str, &'a str)> {
+        self.authoritiees
+            .iter()
+            .filter_map(|record| match record {
+                DnsRecord::NS { domain, host, .. } => Some((domain.as_str(), host.as_str())),
+                _ => None,
+            })
+            .filter(move |(domain, _)| is_subdomain(qname, domain))
+    }
+
+    /// the closest zone to `qname` this response delegates to
+    pub fn get_referral<'a>(&'a self, qname: &'a str) -> Option<&'a str> {
+        self.get_ns(qname)
+            .map(|(domain, _)| domain)
+            .max_by_key(|domain| domain.split('.').filter(|l| !l.is_empty()).count())
+    }
+
+    /// addresses of the name servers of `zone` taken from the glue
+    /// records in the additional section, IPv4 first
+    pub fn get_resolved_ns(&self, zone: &str) -> Vec<IpAddr> {
+        let mut addrs: Vec<IpAddr> = self
+            .get_ns(zone)
+            .filter(|(domain, _)| domain.eq_ignore_ascii_case(zone))
+            .flat_map(|(_, host)| self.glue_for(host))
+            .collect();
+        addrs.sort_by_key(|addr| addr.is_ipv6());
+        addrs
+    }
+
+    /// name servers of `zone` which came without any glue
+    pub fn get_unresolved_ns<'a>(&'a self, zone: &'a str) -> impl Iterator<Item = &'a str> {
+        self.get_ns(zone)
+            .filter(move |(domain, _)| domain.eq_ignore_ascii_case(zone))
+            .map(|(_, host)| host)
+            .filter(|host| self.glue_for(host).next().is_none())
+    }
+
+    /// A and AAAA addresses for `name` in the answer section, IPv4 first
+    pub fn get_addrs(&self, name: &str) -> Vec<IpAddr> {
+        let mut addrs: Vec<IpAddr> = self
+            .answers
+            .iter()
+            .filter_map(|record| address_of(record, name))
+            .collect();
+        addrs.sort_by_key(|addr| addr.is_ipv6());
+        addrs
+    }
+
+    /// follows the CNAME chain for `qname` through the answer section
+    /// and returns the name at its end
+    pub fn resolve_cname<'a>(&'a self, qname: &'a str) -> &'a str {
+        let mut name = qname;
+        // every record can only take us one step, which also breaks loops
+        for _ in 0..self.answers.len() {
+            let next = self.answers.iter().find_map(|record| match record {
+                DnsRecord::CNAME { domain, host, .. } if domain.eq_ignore_ascii_case(name) => {
+                    Some(host.as_str())
+                }
+                _ => None,
+            });
+            match next {
+                Some(host) => name = host,
+                None => break,
+            }
+        }
+        name
+    }
+
+    fn glue_for<'a>(&'a self, host: &'a str) -> impl Iterator<Item = IpAddr> + 'a {
+        self.resources
+            .iter()
+            .filter_map(move |record| address_of(record, host))
+    }
+}
+
+fn address_of(record: &DnsRecord, name: &str) -> Option<IpAddr> {
+    match record {
+        DnsRecord::A { domain, addr, .. } if domain.eq_ignore_ascii_case(name) => {
+            Some(IpAddr::V4(*addr))
+        }
+        DnsRecord::AAAA { domain, addr, .. } if domain.eq_ignore_ascii_case(name) => {
+            Some(IpAddr::V6(*addr))
+        }
+        _ => None,
+    }
+}
+
+/// what a DNAME at `owner` with `target` turns `name`, which has to be below `owner`,
+/// into: the labels above `owner` followed by `target` (RFC 6672 2.2).
+/// `None` if the result is too long for a name
+pub fn substitute_dname(name: &str, owner: &str, target: &str) -> Option<String> {
+    let prefix = if owner.is_empty() {
+        name
+    } else {
+        name.get(..name.len().checked_sub(owner.len() + 1)?)?
+    };
+    let substituted = if target.is_empty() {
+        prefix.to_string()
+    } else {
+        format!("{}.{}", prefix, target)
+    };
+    // 255 bytes on the wire, counting the length bytes and the root
+    (substituted.len() <= 253).then_some(substituted)
+}
+
+/// the name one label up from `name`, the root is its own parent
+pub fn parent(name: &str) -> &str {
+    name.split_once('.').map_or("", |(_, parent)| parent)
+}
+
+/// whether `name` is equal to or below `zone`, comparing whole labels.
+/// the root zone is the empty string
+pub fn is_subdomain(name: &str, zone: &str) -> bool {
+    let name = name.trim_end_matches('.');
+    let zone = zone.trim_end_matches('.');
+    if zone.is_empty() {
+        return true;
+    }
+    if name.len() < zone.len() || !name.is_char_boundary(name.len() - zone.len()) {
+        return false;
+    }
+    let (head, tail) = name.split_at(name.len() - zone.len());
+    tail.eq_ignore_ascii_case(zone) && (head.is_empty() || head.ends_with('.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::edns::DEFAULT_UDP_PAYLOAD;
+
+    #[test]
+    fn carries_extended_result_codes_in_the_opt_record() {
+        for rescode in [ResultCode::BadVers, ResultCode::Unknown(17)] {
+            let mut packet = DnsPackets::new()
+                .with_edns(Edns::new(DEFAULT_UDP_PAYLOAD))
+                .with_rescode(rescode);
+            let buffer = packet.write_with_limit(512).unwrap();
+            // only the lower bits fit in the header
+            assert_eq!(buffer.as_bytes()[3] & 0x0F, u8::from(rescode) & 0x0F);
+
+            let read =
+                DnsPackets::from_buffer(&mut VectorPacketBuffer::from_bytes(buffer.as_bytes()))
+                    .unwrap();
+            assert_eq!(read.header.rescode, rescode);
+        }
+    }
+
+    fn big_response() -> DnsPackets {
+        let query = DnsPackets::query(7, "www.example", QueryType::A);
+        let mut response = DnsPackets::response_to(&query);
+        for i in 0..20 {
+            response = response
+                .with_answer(DnsRecord::A {
+                    domain: "www.example".to_string(),
+                    addr: [192, 0, 2, i].into(),
+                    ttl: 300,
+                })
+                .with_resource(DnsRecord::A {
+                    domain: format!("ns{}.example", i),
+                    addr: [198, 51, 100, i].into(),
+                    ttl: 300,
+                });
+        }
+        response
+    }
+
+    fn size(packet: &mut DnsPackets) -> usize {
+        let mut buffer = VectorPacketBuffer::new();
+        packet.write(&mut buffer).unwrap();
+        buffer.as_bytes().len()
+    }
+
+    #[test]
+    fn gives_up_the_additional_section_before_truncating() {
+        let mut response = big_response();
+        let full = size(&mut response);
+        let mut trimmed = big_response();
+        trimmed.resources.clear();
+        let without_additional = size(&mut trimmed);
+
+        let buffer = response.write_with_limit(full).unwrap();
+        assert_eq!(buffer.as_bytes().len(), full);
+
+        let buffer = response.write_with_limit(full - 1).unwrap();
+        let read = DnsPackets::from_buffer(&mut VectorPacketBuffer::from_bytes(buffer.as_bytes()))
+            .unwrap();
+        assert!(!read.header.truncated_msg);
+        assert_eq!(read.answers.len(), 20);
+        assert!(read.resources.is_empty());
+
+        let buffer = response.write_with_limit(without_additional - 1).unwrap();
+        let read = DnsPackets::from_buffer(&mut VectorPacketBuffer::from_bytes(buffer.as_bytes()))
+            .unwrap();
+        assert!(read.header.truncated_msg);
+        assert_eq!(read.header.id, 7);
+        assert_eq!(read.questions, response.questions);
+        assert!(read.answers.is_empty());
+        assert!(read.authoritiees.is_empty());
+        assert!(read.resources.is_empty());
+    }
+
+    #[test]
+    fn only_falls_back_when_the_message_is_too_big() {
+        let mut response = big_response().with_answer(DnsRecord::A {
+            domain: format!("{}.example", "a".repeat(64)),
+            addr: [192, 0, 2, 1].into(),
+            ttl: 300,
+        });
+        assert!(matches!(
+            response.write_with_limit(512),
+            Err(DnsError::LabelTooLong { len: 64, .. })
+        ));
+    }
+
+    #[test]
+    fn refuses_to_write_past_the_limit() {
+        let mut buffer = VectorPacketBuffer::with_limit(4);
+        buffer.write_u32(0xdeadbeef).unwrap();
+        assert_eq!(
+            buffer.write(0),
+            Err(DnsError::BufferOverflow {
+                offset: 4,
+                limit: 4
+            })
+        );
+        assert_eq!(buffer.as_bytes(), &[0xde, 0xad, 0xbe, 0xef]);
+    }
+
+    #[test]
+    fn walks_up_the_name_tree() {
+        assert_eq!(parent("www.example.com"), "example.com");
+        assert_eq!(parent("com"), "");
+        assert_eq!(parent(""), "");
+
+        assert!(is_subdomain("www.example.com", "example.com"));
+        assert!(is_subdomain("WWW.Example.com.", "example.COM"));
+        assert!(is_subdomain("example.com", "example.com"));
+        assert!(is_subdomain("example.com", ""));
+        assert!(!is_subdomain("wwwexample.com", "example.com"));
+        assert!(!is_subdomain("com", "example.com"));
+    }
+
+    #[test]
+    fn substitutes_below_dnames() {
+        assert_eq!(
+            substitute_dname("www.old.example", "old.example", "new.test").unwrap(),
+            "www.new.test"
+        );
+        assert_eq!(
+            substitute_dname("a.b.c.old.example", "old.example", "new").unwrap(),
+            "a.b.c.new"
+        );
+        assert_eq!(
+            substitute_dname("www.example", "", "mirror").unwrap(),
+            "www.example.mirror"
+        );
+        assert_eq!(
+            substitute_dname("www.old.example", "old.example", "").unwrap(),
+            "www"
+        );
+        assert_eq!(
+            substitute_dname("old.example", "www.old.example", "new"),
+            None
+        );
+
+        // the result has to fit in 255 octets on the wire
+        let label = "x".repeat(63);
+        let target = format!("{0}.{0}.{0}", label);
+        assert_eq!(target.len(), 191);
+        let fits = format!("{}.old", "y".repeat(61));
+        assert_eq!(substitute_dname(&fits, "old", &target).unwrap().len(), 253);
+        let too_long = format!("{}.old", "y".repeat(62));
+        assert_eq!(substitute_dname(&too_long, "old", &target), None);
+    }
 }
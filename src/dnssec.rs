@@ -0,0 +1,1155 @@
+use std::cmp::Ordering;
+
+use ring::{digest, signature};
+
+use crate::{
+    dnsmsg::{is_subdomain, parent},
+    encoding::base32hex_decode,
+    error::DnsError,
+    packet::{PacketBuffer, VectorPacketBuffer},
+    question::QueryType,
+    record::{serial_newer, DnsRecord},
+};
+
+// DNSKEY, RRSIG, DS and NSEC (RFC 4034), NSEC3 and NSEC3PARAM (RFC 5155)
+
+// the signing algorithms we can verify
+/// RSA/SHA-256 (RFC 5702)
+pub const RSASHA256: u8 = 8;
+/// ECDSA on P-256 with SHA-256 (RFC 6605)
+pub const ECDSAP256SHA256: u8 = 13;
+/// ECDSA on P-384 with SHA-384 (RFC 6605)
+pub const ECDSAP384SHA384: u8 = 14;
+/// Ed25519 (RFC 8080)
+pub const ED25519: u8 = 15;
+
+// DS digest types
+/// SHA-1, still around but not to be trusted on its own
+pub const DIGEST_SHA1: u8 = 1;
+/// SHA-256 (RFC 4509)
+pub const DIGEST_SHA256: u8 = 2;
+/// SHA-384 (RFC 6605)
+pub const DIGEST_SHA384: u8 = 4;
+
+/// DNSKEY flag of the keys that sign zone data
+pub const ZONE_KEY: u16 = 0x0100;
+/// NSEC3 flag of spans that may hide unsigned delegations
+pub const OPT_OUT: u8 = 0x01;
+/// NSEC3 chains with more iterations than this are treated as unsigned (RFC 9276 3.2)
+pub const MAX_NSEC3_ITERATIONS: u16 = 150;
+
+/// what validating (a part of) a response tells us about it (RFC 4033 5)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Security {
+    /// signed all the way up to a trust anchor
+    Secure,
+    /// provably unsigned
+    Insecure,
+    /// should have been signed, but the signatures or proofs dont hold up
+    Bogus(&'static str),
+}
+
+impl Security {
+    /// the weaker of the two, a response is only as secure as its least secure part
+    pub fn and(self, other: Security) -> Security {
+        match (self, other) {
+            (Security::Bogus(reason), _) | (_, Security::Bogus(reason)) => Security::Bogus(reason),
+            (Security::Insecure, _) | (_, Security::Insecure) => Security::Insecure,
+            _ => Security::Secure,
+        }
+    }
+}
+
+/// reads the type bitmap of an NSEC or NSEC3 record ending at `end` (RFC 4034 4.1.2)
+//
+//  {window (8), bitmap length (8), bitmap (1 to 32 bytes)} ... in increasing window order
+//  bit n of the bitmap of window w stands for type w * 256 + n, counted from the top bit
+pub(crate) fn read_type_bitmap<T: PacketBuffer>(
+    packet: &mut T,
+    end: usize,
+    qtype: u16,
+) -> Result<Vec<QueryType>, DnsError> {
+    let mut types = Vec::new();
+    let mut last_window = None;
+    while packet.pos() < end {
+        let offset = packet.pos();
+        let window = packet.read()?;
+        let len = packet.read()? as usize;
+        if last_window.is_some_and(|last| window <= last)
+            || !(1..=32).contains(&len)
+            || packet.pos() + len > end
+        {
+            return Err(DnsError::BadRdata {
+                offset,
+                qtype,
+                reason: "malformed type bitmap",
+            });
+        }
+        last_window = Some(window);
+
+        let bitmap = packet.get_range(packet.pos(), len)?;
+        for (i, byte) in bitmap.iter().enumerate() {
+            for bit in 0..8 {
+                if byte & (0x80 >> bit) != 0 {
+                    let low = (i * 8 + bit) as u16;
+                    types.push(QueryType::from(((window as u16) << 8) | low));
+                }
+            }
+        }
+        packet.step(len)?;
+    }
+    Ok(types)
+}
+
+/// writes `types` as a type bitmap, sorted and without duplicates as it has to be
+pub(crate) fn write_type_bitmap<T: PacketBuffer>(
+    packet: &mut T,
+    types: &[QueryType],
+) -> Result<(), DnsError> {
+    let mut types: Vec<u16> = types.iter().map(|t| u16::from(*t)).collect();
+    types.sort_unstable();
+    types.dedup();
+
+    for window in types.chunk_by(|a, b| a >> 8 == b >> 8) {
+        let mut bitmap = [0u8; 32];
+        let mut len = 0;
+        for t in window {
+            let low = (t & 0xFF) as usize;
+            bitmap[low / 8] |= 0x80 >> (low % 8);
+            len = low / 8 + 1;
+        }
+        packet.write((window[0] >> 8) as u8)?;
+        packet.write(len as u8)?;
+        for b in &bitmap[..len] {
+            packet.write(*b)?;
+        }
+    }
+    Ok(())
+}
+
+/// parses an RRSIG time written either as YYYYMMDDHHmmSS in UTC
+/// or as plain seconds since 1970 (RFC 4034 3.2)
+pub fn parse_timestamp(text: &str) -> Option<u32> {
+    if !text.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    if text.len() != 14 {
+        return text.parse().ok();
+    }
+
+    let field = |range: std::ops::Range<usize>| text[range].parse::<i64>().ok();
+    let (year, month, day) = (field(0..4)?, field(4..6)?, field(6..8)?);
+    let (hour, minute, second) = (field(8..10)?, field(10..12)?, field(12..14)?);
+    if !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 59
+    {
+        return None;
+    }
+
+    // days since 1970 from the civil calendar (the days_from_civil algorithm)
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+
+    // times past 2106 wrap around, the serial number arithmetic takes care of them
+    Some((days * 86400 + hour * 3600 + minute * 60 + second) as u32)
+}
+
+/// an RRSIG inception or expiration time as YYYYMMDDHHmmSS in UTC (RFC 4034 3.2)
+pub fn format_timestamp(time: u32) -> String {
+    let (secs, days) = (time % 86400, (time / 86400) as i64);
+
+    // the civil calendar from days since 1970 (the days_from_civil algorithm, inverted)
+    let z = days + 719468;
+    let era = z / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+
+    format!(
+        "{:04}{:02}{:02}{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+/// whether we can verify signatures made with `algorithm`
+pub fn algorithm_supported(algorithm: u8) -> bool {
+    matches!(
+        algorithm,
+        RSASHA256 | ECDSAP256SHA256 | ECDSAP384SHA384 | ED25519
+    )
+}
+
+/// whether a DS record is of any use to us, the ones we cant check are
+/// as good as absent (RFC 4035 5.2)
+pub fn ds_supported(ds: &DnsRecord) -> bool {
+    match ds {
+        DnsRecord::DS {
+            algorithm,
+            digest_type,
+            ..
+        } => {
+            algorithm_supported(*algorithm)
+                && matches!(*digest_type, DIGEST_SHA1 | DIGEST_SHA256 | DIGEST_SHA384)
+        }
+        _ => false,
+    }
+}
+
+/// the key tag of a DNSKEY, the checksum over its RDATA that RRSIG and DS records
+/// use to point at it (RFC 4034 appendix B)
+pub fn key_tag(dnskey: &DnsRecord) -> Option<u16> {
+    if !matches!(dnskey, DnsRecord::DNSKEY { .. }) {
+        return None;
+    }
+    let mut sum = 0u64;
+    for pair in rdata(dnskey).ok()?.chunks(2) {
+        sum += (pair[0] as u64) << 8 | pair.get(1).copied().unwrap_or(0) as u64;
+    }
+    sum += (sum >> 16) & 0xFFFF;
+    Some(sum as u16)
+}
+
+/// the digest a DS record of `digest_type` holds for `dnskey`,
+/// taken over the owner name and RDATA of the key (RFC 4034 5.1.4)
+pub fn ds_digest(dnskey: &DnsRecord, digest_type: u8) -> Option<Vec<u8>> {
+    let algorithm = match digest_type {
+        DIGEST_SHA1 => &digest::SHA1_FOR_LEGACY_USE_ONLY,
+        DIGEST_SHA256 => &digest::SHA256,
+        DIGEST_SHA384 => &digest::SHA384,
+        _ => return None,
+    };
+    let mut data = name_wire(dnskey.domain()).ok()?;
+    data.extend(rdata(dnskey).ok()?);
+    Some(digest::digest(algorithm, &data).as_ref().to_vec())
+}
+
+/// whether `ds` vouches for `dnskey`
+pub fn ds_matches(ds: &DnsRecord, dnskey: &DnsRecord) -> bool {
+    match (ds, dnskey) {
+        (
+            DnsRecord::DS {
+                domain,
+                key_tag: tag,
+                algorithm,
+                digest_type,
+                digest,
+                ..
+            },
+            DnsRecord::DNSKEY {
+                domain: key_domain,
+                algorithm: key_algorithm,
+                ..
+            },
+        ) => {
+            domain.eq_ignore_ascii_case(key_domain)
+                && algorithm == key_algorithm
+                && key_tag(dnskey) == Some(*tag)
+                && ds_digest(dnskey, *digest_type).as_ref() == Some(digest)
+        }
+        _ => false,
+    }
+}
+
+/// the data an RRSIG signature is made over: the RRSIG RDATA without the signature,
+/// followed by the RRset in canonical form and order (RFC 4034 3.1.8.1, 6.3).
+/// records expanded from a wildcard are put back under the wildcard's name
+pub fn signed_data(rrsig: &DnsRecord, rrset: &[DnsRecord]) -> Option<Vec<u8>> {
+    let mut unsigned = rrsig.clone();
+    let (labels, original_ttl) = match &mut unsigned {
+        DnsRecord::RRSIG {
+            labels,
+            original_ttl,
+            signature,
+            ..
+        } => {
+            signature.clear();
+            (*labels as usize, *original_ttl)
+        }
+        _ => return None,
+    };
+    let mut data = rdata(&unsigned).ok()?;
+
+    let mut records = Vec::with_capacity(rrset.len());
+    for record in rrset {
+        let owner = record.domain();
+        let signed_owner = if labels < label_count(owner) {
+            wildcard_of(&suffix(owner, labels))
+        } else {
+            owner.to_string()
+        };
+        let wire = record.canonical_form(original_ttl).ok()?;
+        let mut signed = name_wire(&signed_owner).ok()?;
+        signed.extend_from_slice(&wire[name_wire(owner).ok()?.len()..]);
+        records.push(signed);
+    }
+    // with owner, type, class and TTL all the same this orders them by RDATA
+    records.sort();
+    records.dedup();
+    data.extend(records.concat());
+    Some(data)
+}
+
+/// checks `sig` over `data` with a DNSKEY public key, which comes in the
+/// wire form its algorithm defines
+pub fn verify_signature(algorithm: u8, public_key: &[u8], data: &[u8], sig: &[u8]) -> bool {
+    match algorithm {
+        // exponent length, exponent and modulus (RFC 3110 2)
+        RSASHA256 => {
+            let (len, rest) = match public_key {
+                [0, high, low, rest @ ..] => (u16::from_be_bytes([*high, *low]) as usize, rest),
+                [len, rest @ ..] => (*len as usize, rest),
+                [] => return false,
+            };
+            if len == 0 || rest.len() <= len {
+                return false;
+            }
+            let (e, n) = rest.split_at(len);
+            signature::RsaPublicKeyComponents { n, e }
+                .verify(
+                    &signature::RSA_PKCS1_1024_8192_SHA256_FOR_LEGACY_USE_ONLY,
+                    data,
+                    sig,
+                )
+                .is_ok()
+        }
+        // the bare point, ring wants the uncompressed marker in front of it (RFC 6605 4)
+        ECDSAP256SHA256 | ECDSAP384SHA384 => {
+            let curve = if algorithm == ECDSAP256SHA256 {
+                &signature::ECDSA_P256_SHA256_FIXED
+            } else {
+                &signature::ECDSA_P384_SHA384_FIXED
+            };
+            let mut point = vec![4];
+            point.extend_from_slice(public_key);
+            signature::UnparsedPublicKey::new(curve, point)
+                .verify(data, sig)
+                .is_ok()
+        }
+        ED25519 => signature::UnparsedPublicKey::new(&signature::ED25519, public_key)
+            .verify(data, sig)
+            .is_ok(),
+        _ => false,
+    }
+}
+
+/// checks that one of `rrsigs` is a signature of `zone` over `rrset`, made with one of
+/// `keys` and valid at `now` (RFC 4035 5.3). returns the labels field of the signature
+/// that held up, fewer labels than the owner has means the RRset came from a wildcard
+pub fn verify_rrset(
+    rrset: &[DnsRecord],
+    rrsigs: &[DnsRecord],
+    keys: &[DnsRecord],
+    zone: &str,
+    now: u32,
+) -> Result<u8, &'static str> {
+    let first = rrset.first().ok_or("empty RRset")?;
+    let (owner, qtype) = (first.domain(), first.qtype());
+
+    let mut reason = "RRset is not signed";
+    for rrsig in rrsigs {
+        let DnsRecord::RRSIG {
+            domain,
+            type_covered,
+            algorithm,
+            labels,
+            expiration,
+            inception,
+            key_tag: tag,
+            signer,
+            signature: sig,
+            ..
+        } = rrsig
+        else {
+            continue;
+        };
+        if *type_covered != qtype
+            || !domain.eq_ignore_ascii_case(owner)
+            || !signer.eq_ignore_ascii_case(zone)
+            || !is_subdomain(owner, signer)
+            || *labels as usize > label_count(owner)
+        {
+            continue;
+        }
+        // the validity period is in serial number arithmetic (RFC 4034 3.1.5)
+        if serial_newer(*inception, now) || serial_newer(now, *expiration) {
+            reason = "signature expired or not yet valid";
+            continue;
+        }
+
+        reason = "no key for the signature";
+        for key in keys {
+            let DnsRecord::DNSKEY {
+                flags,
+                protocol,
+                algorithm: key_algorithm,
+                public_key,
+                ..
+            } = key
+            else {
+                continue;
+            };
+            if flags & ZONE_KEY == 0
+                || *protocol != 3
+                || key_algorithm != algorithm
+                || key_tag(key) != Some(*tag)
+                || !key.domain().eq_ignore_ascii_case(signer)
+            {
+                continue;
+            }
+
+            reason = "signature does not verify";
+            let Some(data) = signed_data(rrsig, rrset) else {
+                continue;
+            };
+            if verify_signature(*algorithm, public_key, &data, sig) {
+                return Ok(*labels);
+            }
+        }
+    }
+    Err(reason)
+}
+
+/// splits `records` into RRsets, each with the RRSIGs that cover it
+pub fn rrsets(records: &[DnsRecord]) -> Vec<(Vec<DnsRecord>, Vec<DnsRecord>)> {
+    let mut sets: Vec<(Vec<DnsRecord>, Vec<DnsRecord>)> = Vec::new();
+    for record in records {
+        if matches!(record, DnsRecord::RRSIG { .. }) {
+            continue;
+        }
+        let set = sets.iter_mut().find(|(set, _)| {
+            set[0].qtype() == record.qtype()
+                && set[0].domain().eq_ignore_ascii_case(record.domain())
+        });
+        match set {
+            Some((set, _)) => set.push(record.clone()),
+            None => sets.push((vec![record.clone()], Vec::new())),
+        }
+    }
+    for record in records {
+        if let DnsRecord::RRSIG { type_covered, .. } = record {
+            let set = sets.iter_mut().find(|(set, _)| {
+                set[0].qtype() == *type_covered
+                    && set[0].domain().eq_ignore_ascii_case(record.domain())
+            });
+            if let Some((_, rrsigs)) = set {
+                rrsigs.push(record.clone());
+            }
+        }
+    }
+    sets
+}
+
+/// orders names the way DNSSEC does: label by label from the root down, each
+/// label compared as lowercase bytes, so a name sorts right after its parent (RFC 4034 6.1)
+pub fn canonical_cmp(a: &str, b: &str) -> Ordering {
+    let a = labels(a).into_iter().rev().map(str::to_ascii_lowercase);
+    let b = labels(b).into_iter().rev().map(str::to_ascii_lowercase);
+    a.cmp(b)
+}
+
+/// the NSEC3 hash of `name`: SHA-1 over the name and the salt, then over
+/// the hash and the salt again `iterations` more times (RFC 5155 5)
+pub fn nsec3_hash(name: &str, salt: &[u8], iterations: u16) -> Vec<u8> {
+    let mut hash = name_wire(name).unwrap_or_default();
+    for _ in 0..=iterations {
+        let mut context = digest::Context::new(&digest::SHA1_FOR_LEGACY_USE_ONLY);
+        context.update(&hash);
+        context.update(salt);
+        hash = context.finish().as_ref().to_vec();
+    }
+    hash
+}
+
+/// checks that the NSEC or NSEC3 records among `authority` prove that `name` doesnt
+/// exist (`nxdomain`) or has no `qtype` records (RFC 4035 5.4, RFC 5155 8).
+/// the records are expected to have been verified already
+pub fn verify_denial(
+    authority: &[DnsRecord],
+    name: &str,
+    qtype: QueryType,
+    nxdomain: bool,
+) -> Security {
+    let nsec3s = nsec3s(authority);
+    if !nsec3s.is_empty() {
+        return nsec3_denial(&nsec3s, name, qtype, nxdomain);
+    }
+
+    let nsecs = nsecs(authority);
+    if nsecs.is_empty() {
+        return Security::Bogus("no NSEC or NSEC3 records in the denial");
+    }
+    let matching = nsecs
+        .iter()
+        .find(|(owner, _, _)| owner.eq_ignore_ascii_case(name));
+    match matching {
+        Some(_) if nxdomain => return Security::Bogus("NSEC shows the name exists"),
+        Some((_, _, types)) => return types_denied(types, name, qtype),
+        None => {}
+    }
+
+    let Some((owner, next, _)) = nsecs
+        .iter()
+        .find(|(owner, next, _)| covers(owner, next, name))
+    else {
+        return Security::Bogus("no NSEC covers the name");
+    };
+    // the name before an empty non-terminal points at a name below it, it exists
+    // without any records
+    if is_subdomain(next, name) {
+        if nxdomain {
+            return Security::Bogus("NSEC shows the name exists");
+        }
+        return Security::Secure;
+    }
+
+    // the closest encloser is the longest ancestor the covering NSEC shows to exist
+    let mut encloser = parent(name);
+    while !is_subdomain(owner, encloser) && !is_subdomain(next, encloser) {
+        encloser = parent(encloser);
+    }
+    let wildcard = wildcard_of(encloser);
+    if nxdomain {
+        if !nsecs
+            .iter()
+            .any(|(owner, next, _)| covers(owner, next, &wildcard))
+        {
+            return Security::Bogus("no NSEC proves there is no wildcard");
+        }
+        return Security::Secure;
+    }
+    match nsecs
+        .iter()
+        .find(|(owner, _, _)| owner.eq_ignore_ascii_case(&wildcard))
+    {
+        Some((_, _, types)) => types_denied(types, name, qtype),
+        None => Security::Bogus("no NSEC proves the type doesnt exist"),
+    }
+}
+
+/// checks the proof that comes with an RRset expanded from a wildcard whose signature
+/// has only `labels` labels: `name` itself has to be shown not to exist, by covering
+/// the next closer name (RFC 4035 5.3.4, RFC 5155 8.8)
+pub fn verify_wildcard(authority: &[DnsRecord], name: &str, labels: u8) -> Security {
+    let next_closer = suffix(name, labels as usize + 1);
+
+    let nsec3s = nsec3s(authority);
+    if !nsec3s.is_empty() {
+        let first = &nsec3s[0];
+        if !nsec3_supported(&nsec3s) {
+            return Security::Insecure;
+        }
+        let hash = nsec3_hash(&next_closer, first.salt, first.iterations);
+        return match nsec3s.iter().find(|n| n.covers(&hash)) {
+            Some(cover) if cover.flags & OPT_OUT != 0 => Security::Insecure,
+            Some(_) => Security::Secure,
+            None => Security::Bogus("no NSEC3 proves the answer came from a wildcard"),
+        };
+    }
+
+    if !nsecs(authority)
+        .iter()
+        .any(|(owner, next, _)| covers(owner, next, name))
+    {
+        return Security::Bogus("no NSEC proves the answer came from a wildcard");
+    }
+    Security::Secure
+}
+
+/// whether the NSEC or NSEC3 records among `authority` show `name` to be a delegation,
+/// it has name servers but no SOA
+pub fn is_delegation(authority: &[DnsRecord], name: &str) -> bool {
+    let delegation =
+        |types: &[QueryType]| types.contains(&QueryType::NS) && !types.contains(&QueryType::SOA);
+
+    let nsec3s = nsec3s(authority);
+    if let Some(first) = nsec3s.first() {
+        let hash = nsec3_hash(name, first.salt, first.iterations);
+        return nsec3s
+            .iter()
+            .any(|n| n.owner_hash == hash && delegation(n.types));
+    }
+    nsecs(authority)
+        .iter()
+        .any(|(owner, _, types)| owner.eq_ignore_ascii_case(name) && delegation(types))
+}
+
+/// an NSEC3 record taken apart for checking denials
+struct Nsec3<'a> {
+    owner_hash: Vec<u8>,
+    zone: &'a str,
+    hash_algorithm: u8,
+    flags: u8,
+    iterations: u16,
+    salt: &'a [u8],
+    next_hashed: &'a [u8],
+    types: &'a [QueryType],
+}
+
+impl Nsec3<'_> {
+    /// whether `hash` falls strictly between the owner hash and the next one,
+    /// wrapping around at the last record of the chain
+    fn covers(&self, hash: &[u8]) -> bool {
+        let (owner, next) = (self.owner_hash.as_slice(), self.next_hashed);
+        if owner < next {
+            owner < hash && hash < next
+        } else {
+            owner < hash || hash < next
+        }
+    }
+}
+
+fn nsec3s(records: &[DnsRecord]) -> Vec<Nsec3<'_>> {
+    records
+        .iter()
+        .filter_map(|record| match record {
+            DnsRecord::NSEC3 {
+                domain,
+                hash_algorithm,
+                flags,
+                iterations,
+                salt,
+                next_hashed,
+                types,
+                ..
+            } => {
+                let (label, zone) = domain.split_once('.').unwrap_or((domain, ""));
+                Some(Nsec3 {
+                    owner_hash: base32hex_decode(label)?,
+                    zone,
+                    hash_algorithm: *hash_algorithm,
+                    flags: *flags,
+                    iterations: *iterations,
+                    salt,
+                    next_hashed,
+                    types,
+                })
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// SHA-1 is the only hash there is, and chains that take too much hashing are
+/// treated as unsigned. they all have to agree on how the hashing is done
+fn nsec3_supported(nsec3s: &[Nsec3]) -> bool {
+    let first = &nsec3s[0];
+    nsec3s.iter().all(|n| {
+        n.hash_algorithm == 1
+            && n.iterations <= MAX_NSEC3_ITERATIONS
+            && n.iterations == first.iterations
+            && n.salt == first.salt
+            && n.zone.eq_ignore_ascii_case(first.zone)
+    })
+}
+
+fn nsec3_denial(nsec3s: &[Nsec3], name: &str, qtype: QueryType, nxdomain: bool) -> Security {
+    if !nsec3_supported(nsec3s) {
+        return Security::Insecure;
+    }
+    let (salt, iterations, zone) = (nsec3s[0].salt, nsec3s[0].iterations, nsec3s[0].zone);
+    let matching = |name: &str| {
+        let hash = nsec3_hash(name, salt, iterations);
+        nsec3s.iter().find(|n| n.owner_hash == hash)
+    };
+    let covering = |name: &str| {
+        let hash = nsec3_hash(name, salt, iterations);
+        nsec3s.iter().find(|n| n.covers(&hash))
+    };
+
+    match matching(name) {
+        Some(_) if nxdomain => return Security::Bogus("NSEC3 shows the name exists"),
+        Some(n) => return types_denied(n.types, name, qtype),
+        None => {}
+    }
+
+    // the closest encloser proof (RFC 5155 8.3): the longest existing ancestor, and
+    // the name one label below it on the way to `name`, which has to be covered
+    if name.is_empty() {
+        return Security::Bogus("no NSEC3 matches the name");
+    }
+    let (mut next_closer, mut encloser) = (name, parent(name));
+    while matching(encloser).is_none() {
+        if encloser.is_empty() || !is_subdomain(encloser, zone) {
+            return Security::Bogus("no closest encloser proof");
+        }
+        (next_closer, encloser) = (encloser, parent(encloser));
+    }
+    let Some(cover) = covering(next_closer) else {
+        return Security::Bogus("no NSEC3 covers the next closer name");
+    };
+    let opt_out = cover.flags & OPT_OUT != 0;
+
+    let wildcard = wildcard_of(encloser);
+    if nxdomain {
+        return match covering(&wildcard) {
+            None => Security::Bogus("no NSEC3 proves there is no wildcard"),
+            // an unsigned delegation could hide in an opt-out span
+            Some(_) if opt_out => Security::Insecure,
+            Some(_) => Security::Secure,
+        };
+    }
+    // which is also where the DS of an unsigned delegation goes missing (RFC 5155 8.6)
+    if qtype == QueryType::DS && opt_out {
+        return Security::Insecure;
+    }
+    match matching(&wildcard) {
+        Some(n) => types_denied(n.types, name, qtype),
+        None => Security::Bogus("no NSEC3 proves the type doesnt exist"),
+    }
+}
+
+/// whether a type bitmap of `name` proves it has no `qtype` records, a CNAME would
+/// have been followed instead
+fn types_denied(types: &[QueryType], name: &str, qtype: QueryType) -> Security {
+    let has = |qtype| types.contains(&qtype);
+    if has(qtype) || has(QueryType::CNAME) {
+        return Security::Bogus("type bitmap shows the type exists");
+    }
+    // at a zone cut the parent only speaks for the DS and the child for everything
+    // else (RFC 6840 4.4)
+    if qtype == QueryType::DS {
+        if has(QueryType::SOA) && !name.is_empty() {
+            return Security::Bogus("DS denial from the child zone");
+        }
+    } else if has(QueryType::NS) && !has(QueryType::SOA) {
+        return Security::Bogus("denial from the parent side of a delegation");
+    }
+    Security::Secure
+}
+
+/// (owner, next, types) of every NSEC record
+fn nsecs(records: &[DnsRecord]) -> Vec<(&str, &str, &[QueryType])> {
+    records
+        .iter()
+        .filter_map(|record| match record {
+            DnsRecord::NSEC {
+                domain,
+                next,
+                types,
+                ..
+            } => Some((domain.as_str(), next.as_str(), types.as_slice())),
+            _ => None,
+        })
+        .collect()
+}
+
+/// whether `name` falls strictly between an NSEC owner and its next name. the last
+/// NSEC of a zone points back to the apex, so its span wraps around
+fn covers(owner: &str, next: &str, name: &str) -> bool {
+    let after_owner = canonical_cmp(owner, name) == Ordering::Less;
+    if canonical_cmp(owner, next) == Ordering::Less {
+        after_owner && canonical_cmp(name, next) == Ordering::Less
+    } else {
+        after_owner && is_subdomain(name, next)
+    }
+}
+
+fn labels(name: &str) -> Vec<&str> {
+    name.split('.').filter(|label| !label.is_empty()).collect()
+}
+
+/// labels of a name as RRSIG counts them, without the root and a leading wildcard
+pub fn label_count(name: &str) -> usize {
+    let labels = labels(name);
+    match labels.first() {
+        Some(&"*") => labels.len() - 1,
+        _ => labels.len(),
+    }
+}
+
+/// the last `count` labels of `name`
+fn suffix(name: &str, count: usize) -> String {
+    let labels = labels(name);
+    labels[labels.len().saturating_sub(count)..].join(".")
+}
+
+fn wildcard_of(name: &str) -> String {
+    if name.is_empty() {
+        "*".to_string()
+    } else {
+        format!("*.{}", name)
+    }
+}
+
+/// the uncompressed, lowercase wire form of a name
+fn name_wire(name: &str) -> Result<Vec<u8>, DnsError> {
+    let mut buffer = VectorPacketBuffer::uncompressed();
+    buffer.write_qname(name)?;
+    Ok(buffer.as_bytes().to_vec())
+}
+
+/// the RDATA of a record in canonical form
+fn rdata(record: &DnsRecord) -> Result<Vec<u8>, DnsError> {
+    let wire = record.canonical_form(record.ttl())?;
+    let start = name_wire(record.domain())?.len() + 10;
+    Ok(wire[start..].to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use ring::{rand::SystemRandom, signature::KeyPair};
+
+    use super::*;
+    use crate::encoding::base32hex_encode;
+
+    const NOW: u32 = 1_700_000_000;
+
+    /// an Ed25519 zone key along with its DNSKEY record
+    struct ZoneKey {
+        pair: signature::Ed25519KeyPair,
+        dnskey: DnsRecord,
+    }
+
+    impl ZoneKey {
+        fn new(zone: &str) -> ZoneKey {
+            let pkcs8 = signature::Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+            let pair = signature::Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+            let dnskey = DnsRecord::DNSKEY {
+                domain: zone.to_string(),
+                flags: ZONE_KEY | 1,
+                protocol: 3,
+                algorithm: ED25519,
+                public_key: pair.public_key().as_ref().to_vec(),
+                ttl: 3600,
+            };
+            ZoneKey { pair, dnskey }
+        }
+
+        /// the RRSIG over `rrset` valid from `inception` to `expiration`
+        fn sign(&self, rrset: &[DnsRecord], inception: u32, expiration: u32) -> DnsRecord {
+            let mut rrsig = DnsRecord::RRSIG {
+                domain: rrset[0].domain().to_string(),
+                type_covered: rrset[0].qtype(),
+                algorithm: ED25519,
+                labels: label_count(rrset[0].domain()) as u8,
+                original_ttl: rrset[0].ttl(),
+                expiration,
+                inception,
+                key_tag: key_tag(&self.dnskey).unwrap(),
+                signer: self.dnskey.domain().to_string(),
+                signature: Vec::new(),
+                ttl: rrset[0].ttl(),
+            };
+            let data = signed_data(&rrsig, rrset).unwrap();
+            if let DnsRecord::RRSIG { signature, .. } = &mut rrsig {
+                *signature = self.pair.sign(&data).as_ref().to_vec();
+            }
+            rrsig
+        }
+    }
+
+    /// the modulus of a 1024 bit RSA key with exponent 65537
+    const RSA_MODULUS: [u8; 128] = [
+        0xcc, 0x3f, 0x6c, 0x66, 0x71, 0x3c, 0xce, 0xa1, 0x43, 0x80, 0x8b, 0x73, 0x98, 0xe9, 0x70,
+        0xfc, 0xa3, 0x64, 0x18, 0x70, 0xd3, 0x14, 0x6d, 0xf0, 0x8e, 0x62, 0x2c, 0x5e, 0xf3, 0xff,
+        0xd1, 0x98, 0x32, 0xc2, 0x6f, 0xcd, 0x8f, 0x15, 0x7a, 0x7c, 0x0b, 0xdf, 0x32, 0x76, 0x57,
+        0xed, 0x3e, 0x6e, 0xfa, 0x5c, 0x18, 0xa3, 0x20, 0x30, 0x94, 0x5a, 0xbb, 0x79, 0xbc, 0xee,
+        0x41, 0xad, 0xe7, 0xc1, 0x66, 0x63, 0x15, 0xf5, 0xe6, 0x8e, 0xbe, 0xdf, 0x6d, 0x02, 0xfb,
+        0xeb, 0xc9, 0xd0, 0x35, 0xba, 0xcc, 0x5b, 0xae, 0x65, 0x36, 0x1a, 0x65, 0xa5, 0x6a, 0x95,
+        0xd5, 0xcf, 0x95, 0x16, 0x88, 0x33, 0x16, 0x3d, 0x34, 0x92, 0xb6, 0xcc, 0xa8, 0xcc, 0x5f,
+        0xfa, 0xb0, 0xff, 0x19, 0x30, 0xf3, 0xe0, 0x7e, 0xa0, 0x95, 0x63, 0x16, 0x14, 0x1d, 0x69,
+        0x85, 0x8e, 0xa1, 0x25, 0x70, 0x41, 0x1b, 0xfb,
+    ];
+    /// what that key made of [`SIGNED_DATA`] with RSA/SHA-256
+    const RSA_SIGNATURE: [u8; 128] = [
+        0x91, 0x61, 0x73, 0xac, 0x12, 0xff, 0x1f, 0xae, 0x13, 0x5e, 0x11, 0xea, 0x33, 0xe0, 0x21,
+        0xf3, 0xb2, 0x53, 0x93, 0x0c, 0xe1, 0x54, 0xaf, 0x26, 0x5c, 0x6c, 0xda, 0x04, 0x6d, 0x36,
+        0x71, 0x16, 0x41, 0xec, 0x96, 0x56, 0x1a, 0x18, 0x25, 0xdf, 0xcc, 0xf6, 0xb5, 0xf7, 0x7c,
+        0x2d, 0x00, 0xdd, 0x35, 0x8c, 0xe5, 0xc9, 0x8c, 0x36, 0x01, 0x77, 0xf6, 0xfe, 0x11, 0xc1,
+        0x3d, 0x12, 0x2d, 0x9a, 0x2e, 0x7a, 0x9c, 0x3f, 0xfb, 0xdc, 0x62, 0xc9, 0x4f, 0x57, 0xb9,
+        0xf3, 0x8e, 0x51, 0x99, 0x8e, 0x93, 0x44, 0x86, 0xab, 0xe1, 0xbe, 0xbc, 0x9c, 0x02, 0x36,
+        0xe7, 0x4e, 0xfc, 0xf0, 0xc1, 0xe5, 0xbb, 0xd3, 0x5b, 0x55, 0xdc, 0xb3, 0xf1, 0x98, 0x49,
+        0xb3, 0x3b, 0xab, 0x75, 0x1a, 0x93, 0x90, 0xfe, 0x63, 0xc3, 0x7e, 0xc6, 0x12, 0x4c, 0xa2,
+        0x91, 0x06, 0x8b, 0x4f, 0x76, 0xbc, 0x9a, 0x49,
+    ];
+    const SIGNED_DATA: &[u8] = b"RRSIG RDATA followed by the RRset in canonical form";
+
+    fn a(domain: &str, addr: [u8; 4]) -> DnsRecord {
+        DnsRecord::A {
+            domain: domain.to_string(),
+            addr: addr.into(),
+            ttl: 300,
+        }
+    }
+
+    fn nsec(domain: &str, next: &str, types: &[QueryType]) -> DnsRecord {
+        DnsRecord::NSEC {
+            domain: domain.to_string(),
+            next: next.to_string(),
+            types: types.to_vec(),
+            ttl: 300,
+        }
+    }
+
+    /// the NSEC3 chain of example with one record per name in `names`, no salt
+    /// and a single extra iteration
+    fn nsec3_chain(names: &[(&str, &[QueryType])], flags: u8) -> Vec<DnsRecord> {
+        let mut hashed: Vec<(Vec<u8>, &[QueryType])> = names
+            .iter()
+            .map(|(name, types)| (nsec3_hash(name, &[], 1), *types))
+            .collect();
+        hashed.sort_by(|a, b| a.0.cmp(&b.0));
+        (0..hashed.len())
+            .map(|i| {
+                let (hash, types) = &hashed[i];
+                DnsRecord::NSEC3 {
+                    domain: format!("{}.example", base32hex_encode(hash).to_lowercase()),
+                    hash_algorithm: 1,
+                    flags,
+                    iterations: 1,
+                    salt: Vec::new(),
+                    next_hashed: hashed[(i + 1) % hashed.len()].0.clone(),
+                    types: types.to_vec(),
+                    ttl: 300,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn verifies_signed_rrsets() {
+        let key = ZoneKey::new("example");
+        let rrset = vec![
+            a("www.example", [192, 0, 2, 1]),
+            a("www.example", [192, 0, 2, 2]),
+        ];
+        let rrsigs = [key.sign(&rrset, NOW - 3600, NOW + 3600)];
+        let keys = [key.dnskey.clone()];
+
+        let verify = |rrset: &[DnsRecord]| verify_rrset(rrset, &rrsigs, &keys, "example", NOW);
+        assert_eq!(verify(&rrset), Ok(2));
+        // the order of the records doesnt matter, they are signed in canonical order
+        assert_eq!(verify(&[rrset[1].clone(), rrset[0].clone()]), Ok(2));
+
+        let tampered = [
+            a("www.example", [192, 0, 2, 1]),
+            a("www.example", [192, 0, 2, 3]),
+        ];
+        assert_eq!(verify(&tampered), Err("signature does not verify"));
+        assert_eq!(verify(&rrset[..1]), Err("signature does not verify"));
+
+        let other = ZoneKey::new("example");
+        assert_eq!(
+            verify_rrset(&rrset, &rrsigs, &[other.dnskey], "example", NOW),
+            Err("no key for the signature")
+        );
+        assert_eq!(
+            verify_rrset(&rrset, &rrsigs, &keys, "com", NOW),
+            Err("RRset is not signed")
+        );
+    }
+
+    #[test]
+    fn rejects_signatures_outside_their_validity_period() {
+        let key = ZoneKey::new("example");
+        let rrset = vec![a("www.example", [192, 0, 2, 1])];
+        let keys = [key.dnskey.clone()];
+
+        let expired = key.sign(&rrset, NOW - 7200, NOW - 3600);
+        let not_yet_valid = key.sign(&rrset, NOW + 3600, NOW + 7200);
+        for rrsig in [expired, not_yet_valid] {
+            assert_eq!(
+                verify_rrset(&rrset, &[rrsig], &keys, "example", NOW),
+                Err("signature expired or not yet valid")
+            );
+        }
+
+        // the period is in serial number arithmetic, so it can span the year 2106
+        let spanning = key.sign(&rrset, u32::MAX - 3600, 3600);
+        assert_eq!(
+            verify_rrset(&rrset, &[spanning], &keys, "example", u32::MAX),
+            Ok(2)
+        );
+    }
+
+    #[test]
+    fn nsec_proves_nxdomain_and_nodata() {
+        use QueryType::*;
+        // example, a.example, c.example and nothing else, in canonical order
+        let apex = nsec("example", "a.example", &[NS, SOA, RRSIG, NSEC, DNSKEY]);
+        let a_nsec = nsec("a.example", "c.example", &[A, RRSIG, NSEC]);
+        let c_nsec = nsec("c.example", "example", &[MX, RRSIG, NSEC]);
+        let chain = [apex.clone(), a_nsec, c_nsec.clone()];
+
+        assert_eq!(
+            verify_denial(&chain, "b.example", A, true),
+            Security::Secure
+        );
+        // *.example sorts between the apex and a.example
+        assert_eq!(
+            verify_denial(&chain[1..2], "b.example", A, true),
+            Security::Bogus("no NSEC proves there is no wildcard")
+        );
+        assert_eq!(
+            verify_denial(&[apex.clone(), c_nsec.clone()], "b.example", A, true),
+            Security::Bogus("no NSEC covers the name")
+        );
+        assert_eq!(
+            verify_denial(&chain, "a.example", A, true),
+            Security::Bogus("NSEC shows the name exists")
+        );
+
+        assert_eq!(
+            verify_denial(&chain, "a.example", AAAA, false),
+            Security::Secure
+        );
+        assert_eq!(
+            verify_denial(&chain, "a.example", A, false),
+            Security::Bogus("type bitmap shows the type exists")
+        );
+        assert_eq!(
+            verify_denial(&[], "a.example", AAAA, false),
+            Security::Bogus("no NSEC or NSEC3 records in the denial")
+        );
+    }
+
+    #[test]
+    fn nsec3_proves_nxdomain_and_nodata() {
+        use QueryType::*;
+        let names: [(&str, &[QueryType]); 2] = [
+            ("example", &[NS, SOA, RRSIG, DNSKEY, NSEC3PARAM]),
+            ("a.example", &[A, RRSIG]),
+        ];
+        let chain = nsec3_chain(&names, 0);
+
+        // with only two names every other hash falls in one of the spans
+        assert_eq!(
+            verify_denial(&chain, "b.example", A, true),
+            Security::Secure
+        );
+        assert_eq!(
+            verify_denial(&chain, "x.b.example", A, true),
+            Security::Secure
+        );
+        assert_eq!(
+            verify_denial(&chain, "a.example", A, true),
+            Security::Bogus("NSEC3 shows the name exists")
+        );
+
+        assert_eq!(
+            verify_denial(&chain, "a.example", AAAA, false),
+            Security::Secure
+        );
+        assert_eq!(
+            verify_denial(&chain, "a.example", A, false),
+            Security::Bogus("type bitmap shows the type exists")
+        );
+        assert_eq!(
+            verify_denial(&chain, "b.example", A, false),
+            Security::Bogus("no NSEC3 proves the type doesnt exist")
+        );
+
+        // an opt-out span may hide an unsigned delegation
+        let opt_out = nsec3_chain(&names, OPT_OUT);
+        assert_eq!(
+            verify_denial(&opt_out, "b.example", A, true),
+            Security::Insecure
+        );
+        assert_eq!(
+            verify_denial(&opt_out, "b.example", DS, false),
+            Security::Insecure
+        );
+    }
+
+    #[test]
+    fn verifies_rsa_signatures() {
+        // the exponent length fits in a byte
+        let mut short = vec![3, 1, 0, 1];
+        short.extend_from_slice(&RSA_MODULUS);
+        // or comes as a zero followed by two bytes (RFC 3110 2)
+        let mut long = vec![0, 0, 3, 1, 0, 1];
+        long.extend_from_slice(&RSA_MODULUS);
+
+        for key in [&short, &long] {
+            assert!(verify_signature(
+                RSASHA256,
+                key,
+                SIGNED_DATA,
+                &RSA_SIGNATURE
+            ));
+            assert!(!verify_signature(
+                RSASHA256,
+                key,
+                b"something else",
+                &RSA_SIGNATURE
+            ));
+            // ECDSA wouldnt know what to make of the key
+            assert!(!verify_signature(
+                ECDSAP256SHA256,
+                key,
+                SIGNED_DATA,
+                &RSA_SIGNATURE
+            ));
+        }
+        let mut tampered = RSA_SIGNATURE;
+        tampered[0] ^= 1;
+        assert!(!verify_signature(RSASHA256, &short, SIGNED_DATA, &tampered));
+        // an exponent that swallows the whole key leaves no modulus
+        assert!(!verify_signature(
+            RSASHA256,
+            &[4, 1, 0, 1],
+            SIGNED_DATA,
+            &RSA_SIGNATURE
+        ));
+        assert!(!verify_signature(
+            RSASHA256,
+            &[],
+            SIGNED_DATA,
+            &RSA_SIGNATURE
+        ));
+    }
+
+    #[test]
+    fn verifies_ecdsa_signatures() {
+        let rng = SystemRandom::new();
+        for (algorithm, signing, point_len) in [
+            (
+                ECDSAP256SHA256,
+                &signature::ECDSA_P256_SHA256_FIXED_SIGNING,
+                64,
+            ),
+            (
+                ECDSAP384SHA384,
+                &signature::ECDSA_P384_SHA384_FIXED_SIGNING,
+                96,
+            ),
+        ] {
+            let pkcs8 = signature::EcdsaKeyPair::generate_pkcs8(signing, &rng).unwrap();
+            let pair = signature::EcdsaKeyPair::from_pkcs8(signing, pkcs8.as_ref(), &rng).unwrap();
+            let data = SIGNED_DATA;
+            let sig = pair.sign(&rng, data).unwrap();
+
+            // the DNSKEY holds x and y without the 0x04 ring puts in front
+            let point = pair.public_key().as_ref();
+            assert_eq!(point[0], 4);
+            let public_key = &point[1..];
+            assert_eq!(public_key.len(), point_len);
+
+            assert!(verify_signature(algorithm, public_key, data, sig.as_ref()));
+            assert!(!verify_signature(
+                algorithm,
+                public_key,
+                b"something else",
+                sig.as_ref()
+            ));
+            assert!(!verify_signature(algorithm, point, data, sig.as_ref()));
+        }
+    }
+}
@@ -0,0 +1,319 @@
+use crate::{error::DnsError, packet::PacketBuffer, question::QueryType};
+
+/// the UDP payload size we advertise and accept, small enough to avoid
+/// IP fragmentation on pretty much any path (DNS flag day 2020)
+pub const DEFAULT_UDP_PAYLOAD: u16 = 1232;
+
+/// the only EDNS version there is
+pub const EDNS_VERSION: u8 = 0;
+
+/// OPT pseudo-record (RFC 6891)
+//
+//  NAME         root, a single zero byte
+//  TYPE         41
+//  CLASS        requestor's UDP payload size
+//  TTL          extended RCODE (8) | VERSION (8) | DO (1) | Z (15)
+//  RDLEN / RDATA  {OPTION-CODE (16), OPTION-LENGTH (16), OPTION-DATA} ...
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edns {
+    /// the largest UDP response the sender can take
+    pub udp_payload_size: u16,
+    /// upper 8 bits of the 12 bit RCODE
+    pub extended_rcode: u8,
+    /// the EDNS version the sender speaks, [`EDNS_VERSION`] for us
+    pub version: u8,
+    /// DNSSEC OK, the sender wants DNSSEC records
+    pub dnssec_ok: bool,
+    /// the options of the RDATA, in the order they came in
+    pub options: Vec<EdnsOption>,
+}
+
+/// a single option of an OPT record
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EdnsOption {
+    /// Name server identifier (RFC 5001), code 3
+    Nsid(Vec<u8>),
+    /// Client subnet (RFC 7871), code 8
+    ClientSubnet {
+        /// address family, 1 for IPv4 and 2 for IPv6
+        family: u16,
+        /// how many leading bits of `address` the client reveals
+        source_prefix: u8,
+        /// how many leading bits the answer is good for, 0 in queries
+        scope_prefix: u8,
+        /// the address cut down to the bytes `source_prefix` covers
+        address: Vec<u8>,
+    },
+    /// DNS cookies (RFC 7873), code 10
+    Cookie {
+        /// the cookie the client made up
+        client: [u8; 8],
+        /// the server's cookie, empty until the client has one
+        server: Vec<u8>,
+    },
+    /// edns-tcp-keepalive (RFC 7828) in units of 100ms, code 11
+    TcpKeepalive(Option<u16>),
+    /// Padding (RFC 7830), code 12. Only the length matters
+    Padding(u16),
+    /// any other option, kept as it came
+    Unknown {
+        /// the option code
+        code: u16,
+        /// the option data
+        data: Vec<u8>,
+    },
+}
+
+impl EdnsOption {
+    /// the option code on the wire
+    pub fn code(&self) -> u16 {
+        match self {
+            EdnsOption::Nsid(_) => 3,
+            EdnsOption::ClientSubnet { .. } => 8,
+            EdnsOption::Cookie { .. } => 10,
+            EdnsOption::TcpKeepalive(_) => 11,
+            EdnsOption::Padding(_) => 12,
+            EdnsOption::Unknown { code, .. } => *code,
+        }
+    }
+
+    /// `offset` is where the option starts, for error reporting
+    fn parse(code: u16, data: &[u8], offset: usize) -> Result<EdnsOption, DnsError> {
+        let option = match code {
+            3 => EdnsOption::Nsid(data.to_vec()),
+            8 => {
+                if data.len() < 4 {
+                    return Err(DnsError::BadOpt {
+                        offset,
+                        reason: "client subnet option too short",
+                    });
+                }
+                EdnsOption::ClientSubnet {
+                    family: u16::from_be_bytes([data[0], data[1]]),
+                    source_prefix: data[2],
+                    scope_prefix: data[3],
+                    address: data[4..].to_vec(),
+                }
+            }
+            10 => {
+                // 8 byte client cookie, optionally followed by an 8 to 32 byte server cookie
+                if data.len() != 8 && !(16..=40).contains(&data.len()) {
+                    return Err(DnsError::BadOpt {
+                        offset,
+                        reason: "malformed cookie option",
+                    });
+                }
+                let mut client = [0u8; 8];
+                client.copy_from_slice(&data[..8]);
+                EdnsOption::Cookie {
+                    client,
+                    server: data[8..].to_vec(),
+                }
+            }
+            11 => match data.len() {
+                0 => EdnsOption::TcpKeepalive(None),
+                2 => EdnsOption::TcpKeepalive(Some(u16::from_be_bytes([data[0], data[1]]))),
+                _ => {
+                    return Err(DnsError::BadOpt {
+                        offset,
+                        reason: "malformed tcp keepalive option",
+                    })
+                }
+            },
+            12 => EdnsOption::Padding(data.len() as u16),
+            _ => EdnsOption::Unknown {
+                code,
+                data: data.to_vec(),
+            },
+        };
+        Ok(option)
+    }
+
+    fn data(&self) -> Vec<u8> {
+        match self {
+            EdnsOption::Nsid(data) | EdnsOption::Unknown { data, .. } => data.clone(),
+            EdnsOption::ClientSubnet {
+                family,
+                source_prefix,
+                scope_prefix,
+                address,
+            } => {
+                let mut data = family.to_be_bytes().to_vec();
+                data.push(*source_prefix);
+                data.push(*scope_prefix);
+                data.extend_from_slice(address);
+                data
+            }
+            EdnsOption::Cookie { client, server } => {
+                let mut data = client.to_vec();
+                data.extend_from_slice(server);
+                data
+            }
+            EdnsOption::TcpKeepalive(timeout) => timeout
+                .map(|t| t.to_be_bytes().to_vec())
+                .unwrap_or_default(),
+            EdnsOption::Padding(len) => vec![0u8; *len as usize],
+        }
+    }
+}
+
+impl Edns {
+    /// version 0 with no flags or options, advertising `udp_payload_size`
+    pub fn new(udp_payload_size: u16) -> Self {
+        Self {
+            udp_payload_size,
+            extended_rcode: 0,
+            version: EDNS_VERSION,
+            dnssec_ok: false,
+            options: Vec::new(),
+        }
+    }
+
+    /// reads a whole OPT record, starting at its owner name
+    pub fn read<T: PacketBuffer>(packet: &mut T) -> Result<Edns, DnsError> {
+        let start = packet.pos();
+        let mut domain = String::new();
+        packet.read_qname(&mut domain)?;
+        if !domain.is_empty() {
+            return Err(DnsError::BadOpt {
+                offset: start,
+                reason: "not owned by the root",
+            });
+        }
+        if QueryType::from(packet.read_u16()?) != QueryType::OPT {
+            return Err(DnsError::BadOpt {
+                offset: start,
+                reason: "not an OPT record",
+            });
+        }
+
+        let udp_payload_size = packet.read_u16()?;
+        let ttl = packet.read_u32()?;
+        let data_len = packet.read_u16()? as usize;
+
+        let end = packet.pos() + data_len;
+        let mut options = Vec::new();
+        while packet.pos() < end {
+            let option_start = packet.pos();
+            let code = packet.read_u16()?;
+            let len = packet.read_u16()? as usize;
+            if packet.pos() + len > end {
+                return Err(DnsError::BadOpt {
+                    offset: option_start,
+                    reason: "option overruns the record",
+                });
+            }
+            let data = packet.get_range(packet.pos(), len)?.to_vec();
+            packet.step(len)?;
+            options.push(EdnsOption::parse(code, &data, option_start)?);
+        }
+        if packet.pos() != end {
+            return Err(DnsError::BadOpt {
+                offset: end,
+                reason: "option overruns the record",
+            });
+        }
+
+        Ok(Edns {
+            udp_payload_size,
+            extended_rcode: (ttl >> 24) as u8,
+            version: ((ttl >> 16) & 0xFF) as u8,
+            dnssec_ok: (ttl & 0x8000) > 0,
+            options,
+        })
+    }
+
+    /// writes the whole OPT record, returning how many bytes it took
+    pub fn write<T: PacketBuffer>(&self, packet: &mut T) -> Result<usize, DnsError> {
+        let start_pos = packet.pos();
+
+        packet.write_qname("")?;
+        packet.write_u16(u16::from(QueryType::OPT))?;
+        packet.write_u16(self.udp_payload_size)?;
+        packet.write_u32(
+            ((self.extended_rcode as u32) << 24)
+                | ((self.version as u32) << 16)
+                | ((self.dnssec_ok as u32) << 15),
+        )?;
+
+        let pos = packet.pos();
+        packet.write_u16(0)?;
+
+        for option in &self.options {
+            let data = option.data();
+            packet.write_u16(option.code())?;
+            packet.write_u16(data.len() as u16)?;
+            for b in data {
+                packet.write(b)?;
+            }
+        }
+
+        let size = packet.pos() - (pos + 2);
+        packet.set_u16(pos, size as u16)?;
+
+        Ok(packet.pos() - start_pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        dnsmsg::DnsPackets,
+        header::{DnsHeader, ResultCode},
+        packet::VectorPacketBuffer,
+    };
+
+    #[test]
+    fn round_trips_options() {
+        let mut edns = Edns::new(DEFAULT_UDP_PAYLOAD);
+        edns.dnssec_ok = true;
+        edns.options = vec![
+            EdnsOption::Nsid(b"ns1".to_vec()),
+            EdnsOption::ClientSubnet {
+                family: 1,
+                source_prefix: 24,
+                scope_prefix: 0,
+                address: vec![192, 0, 2],
+            },
+            EdnsOption::Cookie {
+                client: [1, 2, 3, 4, 5, 6, 7, 8],
+                server: vec![9; 16],
+            },
+        ];
+
+        let mut buffer = VectorPacketBuffer::new();
+        let len = edns.write(&mut buffer).unwrap();
+        assert_eq!(len, buffer.as_bytes().len());
+        // owner, type, class and TTL are fixed, the options follow the RDLENGTH
+        assert_eq!(&buffer.as_bytes()[..3], &[0, 0, 41]);
+        assert_eq!(&buffer.as_bytes()[11..15], &[0, 3, 0, 3]);
+
+        let read = Edns::read(&mut VectorPacketBuffer::from_bytes(buffer.as_bytes())).unwrap();
+        assert_eq!(read, edns);
+    }
+
+    #[test]
+    fn merges_the_extended_rcode_into_the_header() {
+        for (low, extended, rescode) in [
+            (ResultCode::NoError, 1, ResultCode::BadVers),
+            (ResultCode::FormerR, 1, ResultCode::Unknown(17)),
+            (ResultCode::ServFail, 0, ResultCode::ServFail),
+        ] {
+            let mut buffer = VectorPacketBuffer::new();
+            let mut header = DnsHeader::new();
+            header.response = true;
+            header.rescode = low;
+            header.resource_entries = 1;
+            header.write(&mut buffer).unwrap();
+            let mut edns = Edns::new(DEFAULT_UDP_PAYLOAD);
+            edns.extended_rcode = extended;
+            edns.write(&mut buffer).unwrap();
+
+            let packet =
+                DnsPackets::from_buffer(&mut VectorPacketBuffer::from_bytes(buffer.as_bytes()))
+                    .unwrap();
+            assert_eq!(packet.header.rescode, rescode);
+        }
+    }
+}
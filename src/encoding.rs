@@ -0,0 +1,117 @@
+// binary data in presentation format, keys and signatures are base64 (RFC 4648 4)
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// `data` as base64 with padding
+pub fn base64_encode(data: &[u8]) -> String {
+    let mut text = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = ((b[0] as u32) << 16) | ((b[1] as u32) << 8) | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                text.push(BASE64[(n >> (18 - 6 * i)) as usize & 0x3F] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+/// decodes base64, whitespace in between is skipped since zone files
+/// tend to split long keys over several lines
+pub fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let text: Vec<u8> = text.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+    if !text.len().is_multiple_of(4) {
+        return None;
+    }
+
+    let mut data = Vec::with_capacity(text.len() / 4 * 3);
+    for (i, chunk) in text.chunks(4).enumerate() {
+        let last = i == text.len() / 4 - 1;
+        let padding = chunk.iter().rev().take_while(|&&b| b == b'=').count();
+        if padding > 2 || (padding > 0 && !last) {
+            return None;
+        }
+
+        let mut n = 0u32;
+        for &b in &chunk[..4 - padding] {
+            let value = BASE64.iter().position(|&c| c == b)? as u32;
+            n = (n << 6) | value;
+        }
+        n <<= 6 * padding as u32;
+        let bytes = [(n >> 16) as u8, (n >> 8) as u8, n as u8];
+        data.extend_from_slice(&bytes[..3 - padding]);
+    }
+    Some(data)
+}
+
+/// hex as DS digests and NSEC3 salts are written, in uppercase
+pub fn hex_encode(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+/// decodes hex in either case, whitespace in between is skipped
+pub fn hex_decode(text: &str) -> Option<Vec<u8>> {
+    let text: Vec<u8> = text.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    text.chunks(2)
+        .map(|pair| {
+            let high = (pair[0] as char).to_digit(16)?;
+            let low = (pair[1] as char).to_digit(16)?;
+            Some((high << 4 | low) as u8)
+        })
+        .collect()
+}
+
+const BASE32HEX: &[u8; 32] = b"0123456789ABCDEFGHIJKLMNOPQRSTUV";
+
+/// base32 with the extended hex alphabet and without padding, the way
+/// NSEC3 writes hashed owner names (RFC 5155 3.3)
+pub fn base32hex_encode(data: &[u8]) -> String {
+    let mut text = String::with_capacity(data.len().div_ceil(5) * 8);
+    let mut bits = 0u32;
+    let mut n = 0u32;
+    for &b in data {
+        n = (n << 8) | b as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            text.push(BASE32HEX[(n >> bits) as usize & 0x1F] as char);
+        }
+    }
+    if bits > 0 {
+        text.push(BASE32HEX[(n << (5 - bits)) as usize & 0x1F] as char);
+    }
+    text
+}
+
+/// decodes unpadded base32hex in either case, NSEC3 owner names are usually lowercase
+pub fn base32hex_decode(text: &str) -> Option<Vec<u8>> {
+    let mut data = Vec::with_capacity(text.len() * 5 / 8);
+    let mut bits = 0u32;
+    let mut n = 0u32;
+    for b in text.bytes() {
+        let value = BASE32HEX
+            .iter()
+            .position(|&c| c == b.to_ascii_uppercase())? as u32;
+        n = (n << 5) | value;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            data.push((n >> bits) as u8);
+        }
+    }
+    // whatever is left over has to be zero padding, less than a byte of it
+    if bits >= 5 || n & ((1 << bits) - 1) != 0 {
+        return None;
+    }
+    Some(data)
+}
@@ -0,0 +1,376 @@
+use std::fmt;
+
+use crate::header::ResultCode;
+
+/// Everything that can go wrong reading or writing a DNS message.
+/// `offset` is the position in the message where the problem was found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DnsError {
+    /// the message ended in the middle of something
+    UnexpectedEnd {
+        /// where in the message it happened
+        offset: usize,
+    },
+    /// writing past the size the message is allowed to have
+    BufferOverflow {
+        /// where in the message it happened
+        offset: usize,
+        /// the size the message may not exceed
+        limit: usize,
+    },
+    /// a label longer than 63 bytes
+    LabelTooLong {
+        /// where in the message it happened
+        offset: usize,
+        /// how long the label is
+        len: usize,
+    },
+    /// two dots in a row, only the root may be empty
+    EmptyLabel {
+        /// where in the message it happened
+        offset: usize,
+    },
+    /// a name longer than 255 bytes on the wire
+    NameTooLong {
+        /// where in the message it happened
+        offset: usize,
+    },
+    /// a compression pointer that doesnt point backwards, which is how loops are made
+    PointerLoop {
+        /// where in the message it happened
+        offset: usize,
+        /// where the pointer points to
+        target: usize,
+    },
+    /// the reserved 0x40 and 0x80 label types
+    InvalidLabelType {
+        /// where in the message it happened
+        offset: usize,
+        /// the length byte with the reserved bits set
+        byte: u8,
+    },
+    /// a label that isnt valid UTF-8
+    InvalidUtf8 {
+        /// where in the message it happened
+        offset: usize,
+    },
+    /// the RDATA length doesnt match what the record type takes up
+    BadRdataLength {
+        /// where in the message it happened
+        offset: usize,
+        /// the type of the record
+        qtype: u16,
+        /// the RDLENGTH of the record
+        expected: usize,
+        /// how much RDATA its type took up
+        actual: usize,
+    },
+    /// an RDATA field with a value its type doesnt allow
+    BadRdata {
+        /// where in the message it happened
+        offset: usize,
+        /// the type of the record
+        qtype: u16,
+        /// what is wrong with the field
+        reason: &'static str,
+    },
+    /// a malformed OPT record or EDNS option
+    BadOpt {
+        /// where in the message it happened
+        offset: usize,
+        /// what is wrong with it
+        reason: &'static str,
+    },
+    /// more than one OPT record in a message (RFC 6891 6.1.1)
+    MultipleOpt {
+        /// where in the message it happened
+        offset: usize,
+    },
+    /// presentation format that cant be parsed, `offset` is into the text
+    BadText {
+        /// where in the text it happened
+        offset: usize,
+        /// what is wrong with it
+        reason: &'static str,
+    },
+}
+
+impl DnsError {
+    /// the response code to answer a message with that caused this error.
+    /// a message we cant read is the client's fault, one we cant write is ours
+    pub fn rescode(&self) -> ResultCode {
+        match self {
+            DnsError::BufferOverflow { .. } => ResultCode::ServFail,
+            _ => ResultCode::FormerR,
+        }
+    }
+}
+
+impl fmt::Display for DnsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DnsError::UnexpectedEnd { offset } => {
+                write!(f, "message ends unexpectedly at byte {}", offset)
+            }
+            DnsError::BufferOverflow { offset, limit } => write!(
+                f,
+                "writing byte {} exceeds the message limit of {} bytes",
+                offset, limit
+            ),
+            DnsError::LabelTooLong { offset, len } => write!(
+                f,
+                "label at byte {} is {} bytes long, at most 63 are allowed",
+                offset, len
+            ),
+            DnsError::EmptyLabel { offset } => write!(f, "empty label at byte {}", offset),
+            DnsError::NameTooLong { offset } => {
+                write!(f, "name at byte {} exceeds 255 bytes", offset)
+            }
+            DnsError::PointerLoop { offset, target } => write!(
+                f,
+                "compression pointer at byte {} points forward to {}",
+                offset, target
+            ),
+            DnsError::InvalidLabelType { offset, byte } => {
+                write!(f, "invalid label type {:#04x} at byte {}", byte, offset)
+            }
+            DnsError::InvalidUtf8 { offset } => {
+                write!(f, "label at byte {} is not valid UTF-8", offset)
+            }
+            DnsError::BadRdataLength {
+                offset,
+                qtype,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "RDATA of type {} at byte {} is {} bytes, expected {}",
+                qtype, offset, actual, expected
+            ),
+            DnsError::BadRdata {
+                offset,
+                qtype,
+                reason,
+            } => write!(
+                f,
+                "invalid RDATA of type {} at byte {}: {}",
+                qtype, offset, reason
+            ),
+            DnsError::BadOpt { offset, reason } => {
+                write!(f, "invalid OPT record at byte {}: {}", offset, reason)
+            }
+            DnsError::MultipleOpt { offset } => {
+                write!(f, "second OPT record at byte {}", offset)
+            }
+            DnsError::BadText { offset, reason } => {
+                write!(f, "invalid text at byte {}: {}", offset, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DnsError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        dnsmsg::DnsPackets,
+        packet::{PacketBuffer, VectorPacketBuffer},
+    };
+
+    /// a header for a response with `answers` records and nothing else
+    fn header(questions: u16, answers: u16) -> Vec<u8> {
+        let mut bytes = vec![0x12, 0x34, 0x81, 0x80];
+        for count in [questions, answers, 0, 0] {
+            bytes.extend_from_slice(&count.to_be_bytes());
+        }
+        bytes
+    }
+
+    fn parse(bytes: &[u8]) -> Result<DnsPackets, DnsError> {
+        DnsPackets::from_buffer(&mut VectorPacketBuffer::from_bytes(bytes))
+    }
+
+    fn read_name(bytes: &[u8], pos: usize) -> Result<String, DnsError> {
+        let mut buffer = VectorPacketBuffer::from_bytes(bytes);
+        buffer.seek(pos)?;
+        let mut name = String::new();
+        buffer.read_qname(&mut name)?;
+        Ok(name)
+    }
+
+    fn write_name(name: &str) -> Result<(), DnsError> {
+        VectorPacketBuffer::new().write_qname(name)
+    }
+
+    #[test]
+    fn truncated_message() {
+        let err = parse(&header(1, 0)[..7]).unwrap_err();
+        assert_eq!(err, DnsError::UnexpectedEnd { offset: 7 });
+        assert_eq!(err.rescode(), ResultCode::FormerR);
+
+        // the question announced in the header never comes
+        let err = parse(&header(1, 0)).unwrap_err();
+        assert_eq!(err, DnsError::UnexpectedEnd { offset: 12 });
+    }
+
+    #[test]
+    fn compression_pointer_forward() {
+        // the name at 12 points at itself
+        let mut bytes = header(1, 0);
+        bytes.extend_from_slice(&[0xC0, 0x0C, 0x00, 0x01, 0x00, 0x01]);
+        assert_eq!(
+            parse(&bytes).unwrap_err(),
+            DnsError::PointerLoop {
+                offset: 12,
+                target: 12
+            }
+        );
+
+        let err = read_name(&[0x01, b'a', 0x00, 0xC0, 0x10], 3).unwrap_err();
+        assert_eq!(
+            err,
+            DnsError::PointerLoop {
+                offset: 3,
+                target: 16
+            }
+        );
+    }
+
+    #[test]
+    fn compression_pointer_loop() {
+        // two pointers pointing at each other, the second one goes forward again
+        let err = read_name(&[0xC0, 0x02, 0xC0, 0x00], 2).unwrap_err();
+        assert_eq!(
+            err,
+            DnsError::PointerLoop {
+                offset: 0,
+                target: 2
+            }
+        );
+    }
+
+    #[test]
+    fn label_too_long() {
+        // 64 doesnt fit in the six bits of a length, it is the reserved 0x40 label type
+        let mut bytes = vec![64];
+        bytes.extend_from_slice(&[b'a'; 64]);
+        bytes.push(0);
+        assert_eq!(
+            read_name(&bytes, 0).unwrap_err(),
+            DnsError::InvalidLabelType {
+                offset: 0,
+                byte: 0x40
+            }
+        );
+
+        let name = format!("{}.com", "a".repeat(64));
+        assert_eq!(
+            write_name(&name).unwrap_err(),
+            DnsError::LabelTooLong { offset: 0, len: 64 }
+        );
+        assert!(write_name(&format!("{}.com", "a".repeat(63))).is_ok());
+    }
+
+    #[test]
+    fn name_too_long() {
+        // four labels of 63 bytes make 257 bytes with their lengths and the root
+        let mut bytes = Vec::new();
+        for _ in 0..4 {
+            bytes.push(63);
+            bytes.extend_from_slice(&[b'a'; 63]);
+        }
+        bytes.push(0);
+        assert_eq!(
+            read_name(&bytes, 0).unwrap_err(),
+            DnsError::NameTooLong { offset: 0 }
+        );
+
+        let name = vec!["a".repeat(63); 4].join(".");
+        assert_eq!(
+            write_name(&name).unwrap_err(),
+            DnsError::NameTooLong { offset: 0 }
+        );
+        // 253 characters is as long as a name gets
+        let name = format!("{}.{}", vec!["a".repeat(63); 3].join("."), "a".repeat(61));
+        assert!(write_name(&name).is_ok());
+    }
+
+    #[test]
+    fn empty_label() {
+        assert_eq!(
+            write_name("a..b").unwrap_err(),
+            DnsError::EmptyLabel { offset: 2 }
+        );
+    }
+
+    #[test]
+    fn invalid_utf8() {
+        assert_eq!(
+            read_name(&[0x02, 0xC3, 0x28, 0x00], 0).unwrap_err(),
+            DnsError::InvalidUtf8 { offset: 0 }
+        );
+    }
+
+    /// a message with one A record for the root whose RDATA is `rdata`,
+    /// announced as `rdlength` bytes long
+    fn a_record(rdlength: u16, rdata: &[u8]) -> Vec<u8> {
+        let mut bytes = header(0, 1);
+        bytes.extend_from_slice(&[0x00, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x0E, 0x10]);
+        bytes.extend_from_slice(&rdlength.to_be_bytes());
+        bytes.extend_from_slice(rdata);
+        bytes
+    }
+
+    #[test]
+    fn rdlength_overrun() {
+        // RDLENGTH claims more than an A record takes up
+        let bytes = a_record(6, &[192, 0, 2, 1, 0, 0]);
+        assert_eq!(
+            parse(&bytes).unwrap_err(),
+            DnsError::BadRdataLength {
+                offset: 23,
+                qtype: 1,
+                expected: 6,
+                actual: 4
+            }
+        );
+
+        // and less
+        let bytes = a_record(2, &[192, 0, 2, 1]);
+        assert_eq!(
+            parse(&bytes).unwrap_err(),
+            DnsError::BadRdataLength {
+                offset: 23,
+                qtype: 1,
+                expected: 2,
+                actual: 4
+            }
+        );
+
+        // RDLENGTH running past the end of the message
+        let mut bytes = header(0, 1);
+        bytes.extend_from_slice(&[0x00, 0x00, 0x63, 0x00, 0x01, 0x00, 0x00, 0x0E, 0x10]);
+        bytes.extend_from_slice(&[0x00, 0x10, 0xAA]);
+        assert_eq!(
+            parse(&bytes).unwrap_err(),
+            DnsError::UnexpectedEnd { offset: 24 }
+        );
+    }
+
+    #[test]
+    fn buffer_overflow() {
+        let mut buffer = VectorPacketBuffer::with_limit(4);
+        buffer.write_u32(1).unwrap();
+        let err = buffer.write(0).unwrap_err();
+        assert_eq!(
+            err,
+            DnsError::BufferOverflow {
+                offset: 4,
+                limit: 4
+            }
+        );
+        assert_eq!(err.rescode(), ResultCode::ServFail);
+    }
+}
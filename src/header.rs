@@ -1,4 +1,4 @@
-use crate::packet::BytePacketBuffer;
+use crate::{error::DnsError, packet::PacketBuffer};
 
 /// for reference purposes:
 ///
@@ -13,51 +13,110 @@ use crate::packet::BytePacketBuffer;
 //////////////////////
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum ResultCode {
-    NoError = 0,
-    FormerR = 1,
-    ServFail = 2,
-    NXDomain = 3,
-    NOTimP = 4,
-    Refused = 5,
+    /// no error condition
+    NoError,
+    /// the server couldnt make sense of the query
+    FormerR,
+    /// the server couldnt answer because of a problem on its side
+    ServFail,
+    /// the name asked for doesnt exist
+    NXDomain,
+    /// the server doesnt support this kind of query
+    NOTimP,
+    /// the server wont answer, for policy reasons
+    Refused,
+    /// a name exists that should not, or a DNAME would make one too long (RFC 6672 2.2)
+    YXDomain,
+    /// extended RCODE, needs an OPT record to carry the upper bits
+    BadVers,
+    /// any other code, such as NOTAUTH from a server that isnt authoritative for a zone
+    Unknown(u8),
 }
 
 impl From<u8> for ResultCode {
     fn from(value: u8) -> Self {
         match value {
+            0 => ResultCode::NoError,
             1 => ResultCode::FormerR,
             2 => ResultCode::ServFail,
             3 => ResultCode::NXDomain,
             4 => ResultCode::NOTimP,
             5 => ResultCode::Refused,
-            0 | _ => ResultCode::NoError,
+            6 => ResultCode::YXDomain,
+            16 => ResultCode::BadVers,
+            _ => ResultCode::Unknown(value),
         }
     }
 }
 
+impl From<ResultCode> for u8 {
+    fn from(rescode: ResultCode) -> Self {
+        match rescode {
+            ResultCode::NoError => 0,
+            ResultCode::FormerR => 1,
+            ResultCode::ServFail => 2,
+            ResultCode::NXDomain => 3,
+            ResultCode::NOTimP => 4,
+            ResultCode::Refused => 5,
+            ResultCode::YXDomain => 6,
+            ResultCode::BadVers => 16,
+            ResultCode::Unknown(value) => value,
+        }
+    }
+}
+
+/// a standard query (RFC 1035 4.1.1)
+pub const OPCODE_QUERY: u8 = 0;
+/// a zone changed and its secondaries should check for a new version (RFC 1996 3)
+pub const OPCODE_NOTIFY: u8 = 4;
+
+/// the fixed 12 bytes every message starts with
 // mind the types, eg: u16 => 16 bits
 #[derive(Debug, Clone)]
 pub struct DnsHeader {
+    /// picked by the sender of a query and copied into the response to match them up
     pub id: u16,
 
+    /// RD, the client wants the server to resolve the query for it
     pub recursion_desired: bool, // 1bit
+    /// TC, the message didnt fit and was cut short
     pub truncated_msg: bool,
+    /// AA, the answer comes from a server authoritative for the zone
     pub authorative_answer: bool,
+    /// kind of request, such as [`OPCODE_QUERY`] or [`OPCODE_NOTIFY`]
     pub opcode: u8, //4 bits
+    /// QR, set on responses
     pub response: bool,
 
+    /// RCODE, only the lower 4 bits are in the header, see [`crate::edns::Edns`]
     pub rescode: ResultCode, //4bits
+    /// CD, the client does its own DNSSEC validation
     pub checking_disabled: bool,
+    /// AD, every record of the answer was validated
     pub authed_data: bool,
+    /// reserved, always zero
     pub z: bool,
+    /// RA, the server resolves queries for its clients
     pub recursion_available: bool,
 
+    /// number of entries in the question section
     pub questions: u16,
+    /// number of records in the answer section
     pub answers: u16,
+    /// number of records in the authority section
     pub authorative_entries: u16,
+    /// number of records in the additional section, the OPT record included
     pub resource_entries: u16,
 }
 
+impl Default for DnsHeader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl DnsHeader {
+    /// a header with every field zeroed
     pub fn new() -> Self {
         Self {
             id: 0,
@@ -80,20 +139,18 @@ impl DnsHeader {
             resource_entries: 0,
         }
     }
-    pub fn write(
-        &mut self,
-        packet: &mut BytePacketBuffer,
-    ) -> Result<(), Box<dyn std::error::Error>> {
+    /// writes the 12 header bytes
+    pub fn write<T: PacketBuffer>(&mut self, packet: &mut T) -> Result<(), DnsError> {
         packet.write_u16(self.id)?;
         packet.write(
             (self.recursion_desired as u8)
                 | ((self.truncated_msg as u8) << 1)
                 | ((self.authorative_answer as u8) << 2)
-                | ((self.opcode as u8) << 3)
+                | ((self.opcode & 0x0F) << 3)
                 | ((self.response as u8) << 7),
         )?;
         packet.write(
-            (self.rescode as u8)
+            (u8::from(self.rescode) & 0x0F)
                 | ((self.checking_disabled as u8) << 4)
                 | ((self.authed_data as u8) << 5)
                 | ((self.z as u8) << 6)
@@ -106,13 +163,11 @@ impl DnsHeader {
         Ok(())
     }
 
-    pub fn read(
-        &mut self,
-        packet: &mut BytePacketBuffer,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        self.id = packet.read_u16().expect("failed to read id");
+    /// reads the 12 header bytes into `self`
+    pub fn read<T: PacketBuffer>(&mut self, packet: &mut T) -> Result<(), DnsError> {
+        self.id = packet.read_u16()?;
 
-        let flags = packet.read_u16().expect("failed to read flags");
+        let flags = packet.read_u16()?;
 
         let a = (flags >> 8) as u8; //getting the first 8bits
         let b = (flags & 0xFF) as u8; //getting the second 8bits with a mask- masks 8 bits?!
@@ -140,3 +195,52 @@ impl DnsHeader {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::VectorPacketBuffer;
+
+    fn round_trip(rescode: ResultCode) -> ResultCode {
+        let mut header = DnsHeader::new();
+        header.rescode = rescode;
+        let mut buffer = VectorPacketBuffer::new();
+        header.write(&mut buffer).unwrap();
+
+        let mut read = DnsHeader::new();
+        read.read(&mut VectorPacketBuffer::from_bytes(buffer.as_bytes()))
+            .unwrap();
+        read.rescode
+    }
+
+    #[test]
+    fn keeps_result_codes_we_dont_know() {
+        for rescode in [
+            ResultCode::NoError,
+            ResultCode::Refused,
+            ResultCode::YXDomain,
+        ] {
+            assert_eq!(round_trip(rescode), rescode);
+        }
+        // NOTAUTH isnt a success just because we have no name for it
+        assert_eq!(ResultCode::from(9), ResultCode::Unknown(9));
+        assert_eq!(round_trip(ResultCode::Unknown(9)), ResultCode::Unknown(9));
+        assert_eq!(u8::from(ResultCode::BadVers), 16);
+        assert_eq!(ResultCode::from(16), ResultCode::BadVers);
+    }
+
+    #[test]
+    fn keeps_the_opcode_to_its_four_bits() {
+        let mut header = DnsHeader::new();
+        header.opcode = 0x1F;
+        let mut buffer = VectorPacketBuffer::new();
+        header.write(&mut buffer).unwrap();
+        assert_eq!(buffer.as_bytes()[2], 0x0F << 3);
+
+        let mut read = DnsHeader::new();
+        read.read(&mut VectorPacketBuffer::from_bytes(buffer.as_bytes()))
+            .unwrap();
+        assert_eq!(read.opcode, 0x0F);
+        assert!(!read.response);
+    }
+}
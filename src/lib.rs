@@ -0,0 +1,47 @@
+//! Reading and writing DNS messages.
+//!
+//! [`DnsPackets`] is a whole message, made of a [`DnsHeader`], [`DnsQuestion`]s and
+//! [`DnsRecord`]s, and is encoded into or decoded from a [`PacketBuffer`].
+//! [`tcp`] has the length prefixed framing for sending messages over TCP and
+//! [`Client`] sends queries to a recursive server.
+//! [`dnssec`] has what it takes to check signatures and denials of existence.
+//! [`zonefile`] reads records from RFC 1035 master files.
+
+#![warn(missing_docs)]
+
+/// a stub resolver sending queries to a recursive server
+pub mod client;
+/// whole DNS messages and what can be read off them
+pub mod dnsmsg;
+/// signature checks, DS digests and denial of existence (RFC 4033-4035, 5155)
+pub mod dnssec;
+/// the OPT pseudo-record and its options (RFC 6891)
+pub mod edns;
+/// base64 and hex for binary data in presentation format
+pub mod encoding;
+/// what can go wrong reading or writing a message
+pub mod error;
+/// the fixed 12 byte message header
+pub mod header;
+/// buffers messages are read from and written to, with name compression
+pub mod packet;
+/// the question section and the record types that can be asked for
+pub mod question;
+/// resource records, on the wire and in presentation format
+pub mod record;
+/// the SvcParams of SVCB and HTTPS records (RFC 9460)
+pub mod svcb;
+/// length prefixed framing for DNS over TCP (RFC 1035 4.2.2)
+pub mod tcp;
+/// reading and writing RFC 1035 master files
+pub mod zonefile;
+
+pub use client::Client;
+pub use dnsmsg::DnsPackets;
+pub use edns::{Edns, EdnsOption};
+pub use error::DnsError;
+pub use header::{DnsHeader, ResultCode};
+pub use packet::{BytePacketBuffer, PacketBuffer, VectorPacketBuffer};
+pub use question::{DnsQuestion, QueryType};
+pub use record::DnsRecord;
+pub use svcb::SvcParam;
@@ -1,112 +1,138 @@
-use std::{io::Read, net::UdpSocket};
+use std::{
+    env, fs,
+    net::{SocketAddr, TcpListener, UdpSocket},
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use notify::Notify;
+use resolver::Resolver;
+use secondary::Secondary;
+use server::Server;
+use transfer::Acl;
+use zone::{Catalog, Zone};
+
+mod cache;
+mod notify;
+mod resolver;
+mod secondary;
+mod server;
+mod transfer;
+mod zone;
+
+/// how often records that outlived their TTL are dropped from the cache
+const CACHE_PURGE_INTERVAL: Duration = Duration::from_secs(60);
+/// how often the files of our primary zones are checked for changes
+const ZONE_RELOAD_INTERVAL: Duration = Duration::from_secs(10);
+
+const USAGE: &str = "usage: dns [--port <port>] [--cache-size <record sets>] \
+    [--timeout <ms>] [--max-cname-chain <CNAMEs>] \
+    [--zone <origin>=<file>]... \
+    [--secondary <origin>=<primary>[,<file>]]... [--allow-transfer <network>]... \
+    [--notify <origin>=<secondary>]...";
 
-use dnsmsg::DnsPackets;
-use header::ResultCode;
-use packet::BytePacketBuffer;
-use question::{DnsQuestion, QueryType};
-
-pub mod dnsmsg;
-pub mod header;
-pub mod packet;
-pub mod question;
-pub mod record;
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let socket = UdpSocket::bind(("0.0.0.0", 2053))?;
-    println!("Entering the main loop...");
-    //sequentially receiving queries!
-    loop {
-        if let Err(e) = handle_query(&socket) {
-            eprint!("an error occured: {:?}", e);
+    let mut port = 2053;
+    let mut resolver = Resolver::new();
+    let mut zones = Catalog::new();
+    // the origin and file of every primary zone, with when the file was last changed
+    let mut primaries = Vec::new();
+    let mut secondaries = Vec::new();
+    let mut transfer_acl = Acl::default();
+    let mut notify = Notify::new();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--port" => port = args.next().ok_or(USAGE)?.parse()?,
+            "--cache-size" => resolver.set_cache_size(args.next().ok_or(USAGE)?.parse()?),
+            "--timeout" => {
+                resolver.set_timeout(Duration::from_millis(args.next().ok_or(USAGE)?.parse()?))
+            }
+            "--max-cname-chain" => resolver.set_max_cname_chain(args.next().ok_or(USAGE)?.parse()?),
+            "--zone" => {
+                let spec = args.next().ok_or(USAGE)?;
+                let (origin, file) = spec.split_once('=').ok_or(USAGE)?;
+                let zone = Zone::load(Path::new(file), origin)?;
+                println!("Loaded zone {}.", zone.origin());
+                primaries.push((
+                    zone.origin().to_string(),
+                    PathBuf::from(file),
+                    modified(Path::new(file)),
+                ));
+                zones.insert(zone);
+            }
+            "--secondary" => {
+                let spec = args.next().ok_or(USAGE)?;
+                let (origin, primary) = spec.split_once('=').ok_or(USAGE)?;
+                let (primary, file) = match primary.split_once(',') {
+                    Some((primary, file)) => (primary, Some(PathBuf::from(file))),
+                    None => (primary, None),
+                };
+                let primary: SocketAddr = primary.parse()?;
+                secondaries.push(Secondary::new(origin, primary, file));
+            }
+            "--allow-transfer" => transfer_acl.allow(&args.next().ok_or(USAGE)?)?,
+            "--notify" => {
+                let spec = args.next().ok_or(USAGE)?;
+                let (origin, secondary) = spec.split_once('=').ok_or(USAGE)?;
+                notify.add_target(origin, secondary.parse()?);
+            }
+            _ => return Err(USAGE.into()),
         }
     }
-}
-
-fn handle_query(socket: &UdpSocket) -> Result<(), Box<dyn std::error::Error>> {
-    // a buffer to read from socket onto
-    let mut req_buff = BytePacketBuffer::new();
-
-    // block until we receive a bytepacket
-    let (_, source) = socket.recv_from(&mut req_buff.buff)?;
-
-    //parsing the msg into a dns packet
-    let mut request_packet = DnsPackets::from_buffer(&mut req_buff)?;
-
-    // creating a dnspacket as a response
-    let mut res_packet = DnsPackets::new();
-    res_packet.header.id = request_packet.header.id;
-    res_packet.header.recursion_desired = true;
-    res_packet.header.recursion_available = true;
-    res_packet.header.response = true;
-
-    // cosnidering one question..
-    if let Some(question) = request_packet.questions.pop() {
-        println!("Received a query: {:?}", question);
 
-        //if query fails, SERVFAIL will be returned
-        //otherwise question and response records are copied into our response
-        if let Ok(result) = lookup(&question.name, question.qtype) {
-            res_packet.questions.push(question);
-            res_packet.header.rescode = result.header.rescode;
+    let socket = UdpSocket::bind(("0.0.0.0", port))?;
+    let listener = TcpListener::bind(("0.0.0.0", port))?;
+    for secondary in &secondaries {
+        notify.listen(secondary);
+    }
+    let server = Arc::new(Server {
+        zones: RwLock::new(zones),
+        resolver,
+        transfer_acl,
+        notify,
+    });
+    // the secondaries may have missed whatever changed while we were down
+    server.notify.send_all(&server.zones());
+
+    for secondary in secondaries {
+        let server = Arc::clone(&server);
+        thread::spawn(move || secondary.run(server));
+    }
 
-            for rec in result.answers {
-                println!("Answer: {:?}", rec);
-                res_packet.answers.push(rec);
-            }
-            for rec in result.authoritiees {
-                println!("Authority: {:?}", rec);
-                res_packet.authoritiees.push(rec);
+    let purge_server = Arc::clone(&server);
+    thread::spawn(move || loop {
+        thread::sleep(CACHE_PURGE_INTERVAL);
+        purge_server.resolver.purge_cache();
+    });
+
+    let reload_server = Arc::clone(&server);
+    thread::spawn(move || loop {
+        thread::sleep(ZONE_RELOAD_INTERVAL);
+        for (origin, file, last_modified) in &mut primaries {
+            let modified = modified(file);
+            if modified == *last_modified {
+                continue;
             }
-            for rec in result.resources {
-                println!("Resource: {:?}", rec);
-                res_packet.resources.push(rec);
+            *last_modified = modified;
+            if let Err(e) = reload_server.reload_zone(file, origin) {
+                eprintln!("couldnt reload zone {}: {}", origin, e);
             }
-        } else {
-            res_packet.header.rescode = ResultCode::ServFail;
         }
-    } else {
-        // if a question is not present we return FORMERR
-        // indicates that sender made a mistake
-        res_packet.header.rescode = ResultCode::FormerR;
-    }
-    // encode and send the response
-    let mut res_buffer = BytePacketBuffer::new();
-    res_packet.write(&mut res_buffer)?;
+    });
 
-    let len = res_buffer.pos();
-    let data = res_buffer.get_range(0, len)?;
-    socket.send_to(data, source)?;
+    let tcp_server = Arc::clone(&server);
+    thread::spawn(move || server::run_tcp(listener, tcp_server));
 
+    println!("Entering the main loop...");
+    server::run_udp(socket, server);
     Ok(())
 }
 
-fn lookup(qname: &str, qtype: QueryType) -> Result<DnsPackets, Box<dyn std::error::Error>> {
-    // Using googles public DNS server
-    let server = ("8.8.8.8", 53);
-
-    // Bind a UDP socket to an arbitrary port
-    let socket = UdpSocket::bind(("0.0.0.0", 43210))?;
-
-    // Build our query packet. It's important that we remember to set the
-    // `recursion_desired` flag. As noted earlier, the packet id is arbitrary.
-    let mut packet = DnsPackets::new();
-    packet.header.id = 6666;
-    packet.header.questions = 1;
-    packet.header.recursion_desired = false;
-    packet
-        .questions
-        .push(DnsQuestion::new(qname.to_string(), qtype));
-
-    // now we create a buffer to write to!
-    let mut req_buff = BytePacketBuffer::new();
-    packet.write(&mut req_buff)?;
-
-    socket.send_to(&req_buff.buff[0..req_buff.pos], server)?;
-
-    // creating a receiving buff
-    let mut res_buff = BytePacketBuffer::new();
-    socket.recv_from(&mut res_buff.buff)?;
-
-    //now the parsing part:
-    let res_packet = DnsPackets::from_buffer(&mut res_buff)?;
-    Ok(res_packet)
+/// when the file at `path` was last changed, `None` if we cant tell
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
 }
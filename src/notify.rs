@@ -0,0 +1,199 @@
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::mpsc::Sender,
+    thread,
+};
+
+use dns::{
+    client::{self, random_id, DEFAULT_TIMEOUT},
+    dnsmsg::DnsPackets,
+    header::{ResultCode, OPCODE_NOTIFY},
+    question::QueryType,
+    record::DnsRecord,
+};
+
+use crate::{secondary::Secondary, server::Server, transfer, zone::Catalog};
+
+/// how often a NOTIFY is sent before we give up on the secondary acknowledging it
+const NOTIFY_ATTEMPTS: usize = 5;
+
+/// who to tell about new versions of our zones and who tells us about theirs (RFC 1996)
+#[derive(Debug, Default)]
+pub struct Notify {
+    /// secondaries by the origin of the zone they copy from us
+    targets: HashMap<String, Vec<SocketAddr>>,
+    /// our secondary zones by origin, with their primary and how to wake them up
+    listeners: HashMap<String, (IpAddr, Sender<()>)>,
+}
+
+impl Notify {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// tells `target` whenever the zone at `origin` gets a new serial
+    pub fn add_target(&mut self, origin: &str, target: SocketAddr) {
+        let origin = origin.trim_end_matches('.').to_lowercase();
+        self.targets.entry(origin).or_default().push(target);
+    }
+
+    /// has NOTIFY messages from the primary of `secondary` refresh it right away
+    pub fn listen(&mut self, secondary: &Secondary) {
+        self.listeners.insert(
+            secondary.origin().to_string(),
+            (secondary.primary().ip(), secondary.notifier()),
+        );
+    }
+
+    /// tells the secondaries of the zone `soa` is the SOA of about its new version.
+    /// every one of them is notified on its own thread, so nobody waits for the others
+    pub fn send(&self, soa: &DnsRecord) {
+        let Some(targets) = self.targets.get(soa.domain()) else {
+            return;
+        };
+        for &target in targets {
+            let soa = soa.clone();
+            thread::spawn(move || notify(target, soa));
+        }
+    }
+
+    /// tells the secondaries of every zone in `zones` about the version we have
+    pub fn send_all(&self, zones: &Catalog) {
+        for origin in self.targets.keys() {
+            if let Some(zone) = zones.get(origin) {
+                self.send(zone.soa());
+            }
+        }
+    }
+}
+
+/// the response to a NOTIFY from `peer`. only the primary of one of our secondary zones
+/// may send one, the secondary then checks for a new version as if its refresh timer
+/// had run out (RFC 1996 3.11)
+pub fn answer(request: &DnsPackets, server: &Server, peer: IpAddr) -> DnsPackets {
+    let mut response = DnsPackets::response_to(request);
+    response.header.recursion_desired = false;
+    let Some(question) = request.questions.first() else {
+        return response.with_rescode(ResultCode::FormerR);
+    };
+    if question.qtype != QueryType::SOA {
+        return response.with_rescode(ResultCode::NOTimP);
+    }
+
+    let origin = question.name.trim_end_matches('.').to_lowercase();
+    let Some((primary, wake)) = server.notify.listeners.get(&origin) else {
+        return response.with_rescode(ResultCode::Refused);
+    };
+    let peer = transfer::unmapped(peer);
+    if peer != transfer::unmapped(*primary) {
+        eprintln!("ignored a NOTIFY for {} from {}", origin, peer);
+        return response.with_rescode(ResultCode::Refused);
+    }
+
+    println!("Received a NOTIFY for {}.", origin);
+    // a secondary already busy refreshing picks it up afterwards
+    let _ = wake.send(());
+    response.header.authorative_answer = true;
+    response
+}
+
+/// sends a NOTIFY for the zone of `soa` to `target` up to [`NOTIFY_ATTEMPTS`] times, until
+/// an answer to it comes back (RFC 1996 3.6). returns whether one did
+fn notify(target: SocketAddr, soa: DnsRecord) -> bool {
+    let mut query = DnsPackets::query(random_id(), soa.domain(), QueryType::SOA);
+    query.header.opcode = OPCODE_NOTIFY;
+    query.header.recursion_desired = false;
+    query.header.authorative_answer = true;
+    // the SOA is a hint, the secondary asks us for it anyway (RFC 1996 3.7)
+    let query = query.with_answer(soa);
+
+    let origin = &query.questions[0].name;
+    for _ in 0..NOTIFY_ATTEMPTS {
+        let response = match client::exchange_udp(&query, target, DEFAULT_TIMEOUT) {
+            Ok(response) => response,
+            Err(e) => {
+                eprintln!("NOTIFY to {} failed: {}", target, e);
+                continue;
+            }
+        };
+        // only a response to this very NOTIFY acknowledges it (RFC 1996 4.7)
+        if response.header.id != query.header.id
+            || !response.header.response
+            || response.header.opcode != OPCODE_NOTIFY
+        {
+            eprintln!(
+                "{} answered a NOTIFY for {} with something else",
+                target, origin
+            );
+            continue;
+        }
+        if response.header.rescode != ResultCode::NoError {
+            eprintln!(
+                "{} answered a NOTIFY for {} with {:?}",
+                target, origin, response.header.rescode
+            );
+        }
+        return true;
+    }
+    eprintln!("{} never acknowledged the NOTIFY for {}", target, origin);
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::UdpSocket,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    use dns::{header::OPCODE_QUERY, packet::VectorPacketBuffer};
+
+    use super::*;
+
+    fn soa() -> DnsRecord {
+        DnsRecord::SOA {
+            domain: "example".to_string(),
+            mname: "ns.example".to_string(),
+            rname: "hostmaster.example".to_string(),
+            serial: 2,
+            refresh: 3600,
+            retry: 600,
+            expire: 86400,
+            minimum: 300,
+            ttl: 300,
+        }
+    }
+
+    #[test]
+    fn retries_until_the_notify_itself_is_answered() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let target = socket.local_addr().unwrap();
+        let received = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&received);
+        thread::spawn(move || loop {
+            let mut raw = [0u8; 512];
+            let Ok((len, source)) = socket.recv_from(&mut raw) else {
+                return;
+            };
+            let request =
+                DnsPackets::from_buffer(&mut VectorPacketBuffer::from_bytes(&raw[..len])).unwrap();
+            assert_eq!(request.header.opcode, OPCODE_NOTIFY);
+            assert_eq!(request.answers, vec![soa()]);
+
+            // the first answer is for a query, not a NOTIFY
+            let mut response = DnsPackets::response_to(&request);
+            if counter.fetch_add(1, Ordering::SeqCst) == 0 {
+                response.header.opcode = OPCODE_QUERY;
+            }
+            let buffer = response.write_with_limit(512).unwrap();
+            socket.send_to(buffer.as_bytes(), source).unwrap();
+        });
+
+        assert!(notify(target, soa()));
+        assert_eq!(received.load(Ordering::SeqCst), 2);
+    }
+}
@@ -1,107 +1,124 @@
-pub struct BytePacketBuffer {
-    pub buff: [u8; 512],
-    pub pos: usize,
-}
+use std::collections::HashMap;
 
-impl BytePacketBuffer {
-    pub fn new() -> Self {
-        Self {
-            buff: [0u8; 512],
-            pos: 0,
-        }
-    }
-    pub fn set(&mut self, pos: usize, val: u8) {
-        self.buff[pos] = val;
-    }
-    pub fn set_u16(&mut self, pos: usize, val: u16) {
-        self.set(pos, (val >> 8) as u8);
-        self.set(pos + 1, (val & 0xFF) as u8);
-    }
-    ///get the position
-    pub fn pos(&self) -> usize {
-        self.pos
-    }
-    /// Step forward
-    pub fn step(&mut self, step: usize) {
-        self.pos += step;
-    }
-    /// change the buffer positon
-    pub fn seek(&mut self, pos: usize) {
-        self.pos = pos;
-    }
+use crate::error::DnsError;
+
+/// the classic UDP limit without EDNS
+pub const UDP_MESSAGE_SIZE: usize = 512;
+/// the largest message the 16 bit TCP length prefix can describe
+pub const MAX_MESSAGE_SIZE: usize = 65535;
+/// longest name on the wire, length bytes and terminating zero included
+pub const MAX_NAME_LENGTH: usize = 255;
+
+/// A buffer a DNS message is read from or written to.
+/// Implementors provide the byte level access, everything built on top
+/// of it (integers, names) comes for free.
+pub trait PacketBuffer {
     /// read a byte and move forward the position for one step
-    pub fn read(&mut self) -> Result<u8, Box<dyn std::error::Error>> {
-        if self.pos >= 512 {
-            return Err("End of buffer bounds".into());
-        }
-        let byte_read = self.buff[self.pos];
-        self.pos += 1;
-        Ok(byte_read)
-    }
+    fn read(&mut self) -> Result<u8, DnsError>;
+    /// get a single byte without changing the buffer position
+    fn get(&mut self, pos: usize) -> Result<u8, DnsError>;
+    /// get a range of bytes
+    fn get_range(&mut self, start: usize, length: usize) -> Result<&[u8], DnsError>;
     /// writes a single byte and moves one step forward
-    pub fn write(&mut self, val: u8) -> Result<(), Box<dyn std::error::Error>> {
-        if self.pos >= 512 {
-            return Err("End of buffer bounds".into());
-        }
+    fn write(&mut self, val: u8) -> Result<(), DnsError>;
+    /// overwrite an already written byte
+    fn set(&mut self, pos: usize, val: u8) -> Result<(), DnsError>;
+    ///get the position
+    fn pos(&self) -> usize;
+    /// change the buffer positon
+    fn seek(&mut self, pos: usize) -> Result<(), DnsError>;
+    /// Step forward
+    fn step(&mut self, steps: usize) -> Result<(), DnsError>;
+    /// where a name (or the tail of one) was written before, for compression
+    fn find_label(&self, name: &str) -> Option<usize>;
+    /// remember that `name` was written at `pos`
+    fn save_label(&mut self, name: &str, pos: usize);
 
-        self.buff[self.pos] = val;
-        self.pos += 1;
+    /// overwrite two already written bytes, such as a length filled in afterwards
+    fn set_u16(&mut self, pos: usize, val: u16) -> Result<(), DnsError> {
+        self.set(pos, (val >> 8) as u8)?;
+        self.set(pos + 1, (val & 0xFF) as u8)?;
         Ok(())
     }
-    /// get a single byte without changing the buffer position
-    pub fn get(&mut self, pos: usize) -> Result<u8, Box<dyn std::error::Error>> {
-        if pos >= 512 {
-            return Err("Position out of bounds".into());
-        }
-        Ok(self.buff[pos])
-    }
-    /// get a range of bytes
-    pub fn get_range(
-        &mut self,
-        start: usize,
-        length: usize,
-    ) -> Result<&[u8], Box<dyn std::error::Error>> {
-        if start + length >= 512 {
-            return Err("Out of bounds!".into());
-        }
-        let bytes = &self.buff[start..start + length];
-        Ok(bytes)
-    }
-    pub fn write_u16(&mut self, val: u16) -> Result<(), Box<dyn std::error::Error>> {
+
+    /// write 2 bytes in network order
+    fn write_u16(&mut self, val: u16) -> Result<(), DnsError> {
         self.write((val >> 8) as u8)?;
         self.write((val & 0xff) as u8)?;
 
         Ok(())
     }
     ///read 2 bytes, stepping 2 steps forward
-    pub fn read_u16(&mut self) -> Result<u16, Box<dyn std::error::Error>> {
+    fn read_u16(&mut self) -> Result<u16, DnsError> {
         let ret = (self.read()? as u16) << 8 | self.read()? as u16;
 
         Ok(ret)
     }
-    pub fn write_u32(&mut self, val: u32) -> Result<(), Box<dyn std::error::Error>> {
+    /// write 4 bytes in network order
+    fn write_u32(&mut self, val: u32) -> Result<(), DnsError> {
         self.write(((val >> 24) & 0xFF) as u8)?;
         self.write(((val >> 16) & 0xFF) as u8)?;
         self.write(((val >> 8) & 0xFF) as u8)?;
-        self.write(((val >> 0) & 0xFF) as u8)?;
+        self.write((val & 0xFF) as u8)?;
 
         Ok(())
     }
 
     /// read four bytes, step four bytes forward
-    pub fn read_u32(&mut self) -> Result<u32, Box<dyn std::error::Error>> {
+    fn read_u32(&mut self) -> Result<u32, DnsError> {
         let ret = (self.read()? as u32) << 24
             | (self.read()? as u32) << 16
             | (self.read()? as u32) << 8
-            | (self.read()? as u32) << 0;
+            | (self.read()? as u32);
         Ok(ret)
     }
 
-    pub fn write_qname(&mut self, qname: &str) -> Result<(), Box<dyn std::error::Error>> {
-        for label in qname.split('.') {
+    /// writes a name, pointing back at an earlier occurrence of its tail where possible
+    fn write_qname(&mut self, qname: &str) -> Result<(), DnsError> {
+        self.write_name(qname, true)
+    }
+
+    /// writes a name in full, for RDATA where compression is forbidden (RFC 3597 4)
+    fn write_qname_uncompressed(&mut self, qname: &str) -> Result<(), DnsError> {
+        self.write_name(qname, false)
+    }
+
+    /// writes a name, pointing back at an earlier occurrence of its tail if `compress` is set
+    fn write_name(&mut self, qname: &str, compress: bool) -> Result<(), DnsError> {
+        // the root is just the terminating zero
+        let qname = qname.trim_end_matches('.').to_lowercase();
+        let labels: Vec<&str> = qname.split('.').filter(|_| !qname.is_empty()).collect();
+
+        let start = self.pos();
+        if qname.len() + 2 > MAX_NAME_LENGTH {
+            return Err(DnsError::NameTooLong { offset: start });
+        }
+
+        for (i, label) in labels.iter().enumerate() {
             let len = label.len();
             if len > 0x3f {
-                return Err("Label length exceeds 63 characters".into());
+                return Err(DnsError::LabelTooLong {
+                    offset: self.pos(),
+                    len,
+                });
+            }
+            if len == 0 {
+                return Err(DnsError::EmptyLabel { offset: self.pos() });
+            }
+
+            let tail = labels[i..].join(".");
+            if compress {
+                if let Some(pos) = self.find_label(&tail) {
+                    // a pointer ends the name, no terminating zero
+                    self.write_u16(0xC000 | pos as u16)?;
+                    return Ok(());
+                }
+            }
+
+            // pointers only have 14 bits for the offset
+            let pos = self.pos();
+            if pos < 0x3FFF {
+                self.save_label(&tail, pos);
             }
 
             self.write(len as u8)?;
@@ -109,39 +126,50 @@ impl BytePacketBuffer {
                 self.write(*b)?;
             }
         }
-        self.write(0 as u8)?;
+        self.write(0_u8)?;
         Ok(())
     }
     /// read a qname
-    pub fn read_qname(&mut self, outstr: &mut String) -> Result<(), Box<dyn std::error::Error>> {
+    fn read_qname(&mut self, outstr: &mut String) -> Result<(), DnsError> {
         let mut pos = self.pos();
+        let start = pos;
 
         let mut jumped = false;
-        let max_jumps = 5;
-        let mut jumps_performed = 0;
+        // every pointer has to point further back than the one before it,
+        // so we cant go round in circles
+        let mut lowest_pointer = pos;
+        // length of the name on the wire so far
+        let mut wire_len = 1;
 
         let mut delimiter = "";
 
         loop {
-            if jumps_performed > max_jumps {
-                return Err("Limit of jumps reached!".into());
-            }
-
             let len = self.get(pos)?;
 
             if (len & 0xC0) == 0xC0 {
                 if !jumped {
-                    self.seek(pos + 2);
+                    self.seek(pos + 2)?;
                 }
                 let b2 = self.get(pos + 1)? as u16;
                 let offset = (((len as u16) ^ 0xC0) << 8) | b2;
+                if offset as usize >= lowest_pointer {
+                    return Err(DnsError::PointerLoop {
+                        offset: pos,
+                        target: offset as usize,
+                    });
+                }
+                lowest_pointer = offset as usize;
                 pos = offset as usize;
 
                 // Indicate that a jump was performed.
                 jumped = true;
-                jumps_performed += 1;
 
                 continue;
+            } else if (len & 0xC0) != 0 {
+                return Err(DnsError::InvalidLabelType {
+                    offset: pos,
+                    byte: len,
+                });
             } else {
                 pos += 1;
 
@@ -149,11 +177,18 @@ impl BytePacketBuffer {
                     break;
                 }
 
+                wire_len += len as usize + 1;
+                if wire_len > MAX_NAME_LENGTH {
+                    return Err(DnsError::NameTooLong { offset: start });
+                }
+
                 outstr.push_str(delimiter);
 
                 let str_out = self.get_range(pos, len as usize)?;
+                let label = std::str::from_utf8(str_out)
+                    .map_err(|_| DnsError::InvalidUtf8 { offset: pos - 1 })?;
 
-                outstr.push_str(&String::from_utf8_lossy(str_out).to_lowercase());
+                outstr.push_str(&label.to_lowercase());
 
                 delimiter = ".";
                 pos += len as usize;
@@ -161,8 +196,310 @@ impl BytePacketBuffer {
         }
 
         if !jumped {
-            self.seek(pos);
+            self.seek(pos)?;
+        }
+        Ok(())
+    }
+}
+
+/// A fixed 512 byte buffer, all a plain UDP message can hold
+pub struct BytePacketBuffer {
+    /// the message bytes
+    pub buff: [u8; UDP_MESSAGE_SIZE],
+    /// where the next read or write happens
+    pub pos: usize,
+    /// names written so far and where
+    labels: HashMap<String, usize>,
+}
+
+impl Default for BytePacketBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BytePacketBuffer {
+    /// a zeroed buffer positioned at the start
+    pub fn new() -> Self {
+        Self {
+            buff: [0u8; UDP_MESSAGE_SIZE],
+            pos: 0,
+            labels: HashMap::new(),
+        }
+    }
+}
+
+impl PacketBuffer for BytePacketBuffer {
+    fn read(&mut self) -> Result<u8, DnsError> {
+        if self.pos >= UDP_MESSAGE_SIZE {
+            return Err(DnsError::UnexpectedEnd { offset: self.pos });
+        }
+        let byte_read = self.buff[self.pos];
+        self.pos += 1;
+        Ok(byte_read)
+    }
+    fn get(&mut self, pos: usize) -> Result<u8, DnsError> {
+        if pos >= UDP_MESSAGE_SIZE {
+            return Err(DnsError::UnexpectedEnd { offset: pos });
+        }
+        Ok(self.buff[pos])
+    }
+    fn get_range(&mut self, start: usize, length: usize) -> Result<&[u8], DnsError> {
+        if start + length > UDP_MESSAGE_SIZE {
+            return Err(DnsError::UnexpectedEnd {
+                offset: UDP_MESSAGE_SIZE,
+            });
+        }
+        let bytes = &self.buff[start..start + length];
+        Ok(bytes)
+    }
+    fn write(&mut self, val: u8) -> Result<(), DnsError> {
+        if self.pos >= UDP_MESSAGE_SIZE {
+            return Err(DnsError::BufferOverflow {
+                offset: self.pos,
+                limit: UDP_MESSAGE_SIZE,
+            });
         }
+
+        self.buff[self.pos] = val;
+        self.pos += 1;
         Ok(())
     }
+    fn set(&mut self, pos: usize, val: u8) -> Result<(), DnsError> {
+        if pos >= UDP_MESSAGE_SIZE {
+            return Err(DnsError::BufferOverflow {
+                offset: pos,
+                limit: UDP_MESSAGE_SIZE,
+            });
+        }
+        self.buff[pos] = val;
+        Ok(())
+    }
+    fn pos(&self) -> usize {
+        self.pos
+    }
+    fn seek(&mut self, pos: usize) -> Result<(), DnsError> {
+        self.pos = pos;
+        Ok(())
+    }
+    fn step(&mut self, steps: usize) -> Result<(), DnsError> {
+        self.pos += steps;
+        Ok(())
+    }
+    fn find_label(&self, name: &str) -> Option<usize> {
+        self.labels.get(name).copied()
+    }
+    fn save_label(&mut self, name: &str, pos: usize) {
+        self.labels.insert(name.to_string(), pos);
+    }
+}
+
+/// A growable buffer for messages up to 65535 bytes (EDNS, TCP).
+/// Writes past `limit` fail, which is how a UDP response finds out
+/// it doesnt fit the payload size the client can take.
+pub struct VectorPacketBuffer {
+    /// the message bytes
+    pub buffer: Vec<u8>,
+    /// where the next read or write happens
+    pub pos: usize,
+    limit: usize,
+    /// names written so far and where
+    labels: HashMap<String, usize>,
+    /// whether names may point back at earlier ones
+    compress: bool,
+}
+
+impl Default for VectorPacketBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VectorPacketBuffer {
+    /// an empty buffer that grows up to the maximum message size
+    pub fn new() -> Self {
+        Self::with_limit(MAX_MESSAGE_SIZE)
+    }
+
+    /// an empty buffer that refuses to grow past `limit` bytes
+    pub fn with_limit(limit: usize) -> Self {
+        Self {
+            buffer: Vec::new(),
+            pos: 0,
+            limit: limit.min(MAX_MESSAGE_SIZE),
+            labels: HashMap::new(),
+            compress: true,
+        }
+    }
+
+    /// an empty buffer that writes every name in full, the way DNSSEC's
+    /// canonical form wants it (RFC 4034 6.2)
+    pub fn uncompressed() -> Self {
+        Self {
+            compress: false,
+            ..Self::new()
+        }
+    }
+
+    /// a buffer to read a received message from
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        Self {
+            buffer: bytes.to_vec(),
+            pos: 0,
+            limit: MAX_MESSAGE_SIZE,
+            labels: HashMap::new(),
+            compress: true,
+        }
+    }
+
+    /// everything written so far
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buffer
+    }
+}
+
+impl PacketBuffer for VectorPacketBuffer {
+    fn read(&mut self) -> Result<u8, DnsError> {
+        let byte_read = self.get(self.pos)?;
+        self.pos += 1;
+        Ok(byte_read)
+    }
+    fn get(&mut self, pos: usize) -> Result<u8, DnsError> {
+        match self.buffer.get(pos) {
+            Some(byte) => Ok(*byte),
+            None => Err(DnsError::UnexpectedEnd { offset: pos }),
+        }
+    }
+    fn get_range(&mut self, start: usize, length: usize) -> Result<&[u8], DnsError> {
+        if start + length > self.buffer.len() {
+            return Err(DnsError::UnexpectedEnd {
+                offset: self.buffer.len(),
+            });
+        }
+        Ok(&self.buffer[start..start + length])
+    }
+    fn write(&mut self, val: u8) -> Result<(), DnsError> {
+        if self.pos >= self.limit {
+            return Err(DnsError::BufferOverflow {
+                offset: self.pos,
+                limit: self.limit,
+            });
+        }
+        if self.pos < self.buffer.len() {
+            self.buffer[self.pos] = val;
+        } else {
+            // seeking past the end leaves a gap, fill it with zeroes
+            self.buffer.resize(self.pos, 0);
+            self.buffer.push(val);
+        }
+        self.pos += 1;
+        Ok(())
+    }
+    fn set(&mut self, pos: usize, val: u8) -> Result<(), DnsError> {
+        match self.buffer.get_mut(pos) {
+            Some(byte) => {
+                *byte = val;
+                Ok(())
+            }
+            None => Err(DnsError::BufferOverflow {
+                offset: pos,
+                limit: self.buffer.len(),
+            }),
+        }
+    }
+    fn pos(&self) -> usize {
+        self.pos
+    }
+    fn seek(&mut self, pos: usize) -> Result<(), DnsError> {
+        self.pos = pos;
+        Ok(())
+    }
+    fn step(&mut self, steps: usize) -> Result<(), DnsError> {
+        self.pos += steps;
+        Ok(())
+    }
+    fn find_label(&self, name: &str) -> Option<usize> {
+        self.labels.get(name).copied()
+    }
+    fn save_label(&mut self, name: &str, pos: usize) {
+        if self.compress {
+            self.labels.insert(name.to_string(), pos);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_name(bytes: &[u8], pos: usize) -> Result<String, DnsError> {
+        let mut buffer = VectorPacketBuffer::from_bytes(bytes);
+        buffer.seek(pos)?;
+        let mut name = String::new();
+        buffer.read_qname(&mut name)?;
+        Ok(name)
+    }
+
+    #[test]
+    fn points_back_at_repeated_suffixes() {
+        let mut buffer = VectorPacketBuffer::new();
+        buffer.write_qname("www.example.com").unwrap();
+        let second = buffer.pos();
+        buffer.write_qname("mail.Example.COM").unwrap();
+
+        // "mail" and a pointer to "example.com" at offset 4
+        assert_eq!(&buffer.as_bytes()[second..], b"\x04mail\xc0\x04");
+        assert_eq!(
+            read_name(buffer.as_bytes(), second).unwrap(),
+            "mail.example.com"
+        );
+
+        let third = buffer.pos();
+        buffer.write_qname("WWW.example.com").unwrap();
+        assert_eq!(&buffer.as_bytes()[third..], b"\xc0\x00");
+    }
+
+    #[test]
+    fn only_points_at_offsets_a_pointer_can_hold() {
+        let mut buffer = VectorPacketBuffer::new();
+        buffer.seek(0x4000).unwrap();
+        buffer.write_qname("example.com").unwrap();
+        let second = buffer.pos();
+        buffer.write_qname("example.com").unwrap();
+        assert_eq!(&buffer.as_bytes()[second..], b"\x07example\x03com\x00");
+    }
+
+    #[test]
+    fn writes_names_in_full_when_uncompressed() {
+        let mut buffer = VectorPacketBuffer::uncompressed();
+        buffer.write_qname("www.example.com").unwrap();
+        buffer.write_qname("example.com").unwrap();
+        assert_eq!(
+            buffer.as_bytes(),
+            b"\x03www\x07example\x03com\x00\x07example\x03com\x00"
+        );
+        assert!(!buffer.as_bytes().iter().any(|b| b & 0xC0 == 0xC0));
+    }
+
+    #[test]
+    fn rejects_pointers_that_dont_point_back() {
+        // a pointer to itself
+        assert_eq!(
+            read_name(b"\xc0\x00", 0),
+            Err(DnsError::PointerLoop {
+                offset: 0,
+                target: 0
+            })
+        );
+        // a pointer ahead of itself, to a name that would point back at it
+        assert_eq!(
+            read_name(b"\x01a\xc0\x04\x01b\xc0\x00", 0),
+            Err(DnsError::PointerLoop {
+                offset: 2,
+                target: 4
+            })
+        );
+        // pointing back is fine
+        assert_eq!(read_name(b"\x01a\x00\x01b\xc0\x00", 3).unwrap(), "b.a");
+    }
 }
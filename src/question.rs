@@ -1,20 +1,80 @@
-use crate::packet::BytePacketBuffer;
+use std::fmt;
+
+use crate::{error::DnsError, packet::PacketBuffer};
 
 //ID	Name	Description	                                                Encoding
 //1	A	Alias - Mapping names to IP addresses	                        Preamble + Four bytes for IPv4 adress
 //2	NS	Name Server - The DNS server address for a domain	        Preamble + Label Sequence
 //5	CNAME	Canonical Name - Maps names to names	                        Preamble + Label Sequence
+//6	SOA	Start of Authority - Zone parameters                    	Preamble + 2 Label Sequences + Five 4-byte integers
+//12	PTR	Pointer - Maps addresses back to names                  	Preamble + Label Sequence
 //15	MX	Mail eXchange - The host of the mail server for a domain	Preamble + 2-bytes for priority + Label Sequence
+//16	TXT	Text - Arbitrary strings, SPF, DKIM and the like        	Preamble + length prefixed byte strings
 //28	AAAA	IPv6 alias	                                                Premable + Sixteen bytes for IPv6 adress
+//33	SRV	Service locator - Where a service runs                  	Preamble + 2-bytes each for priority, weight, port + Label Sequence
+//39	DNAME	Delegation Name - Maps a whole subtree to another name  	Preamble + Label Sequence
+//41	OPT	EDNS pseudo-record, only ever in the additional section 	Preamble + list of options
+//43	DS	Delegation Signer - Hash of a child zone's key          	Preamble + 2-bytes key tag + algorithm + digest type + digest
+//46	RRSIG	Signature over an RRset                                 	Preamble + covered type, algorithm, labels, TTL, times, key tag + Label Sequence + signature
+//47	NSEC	Next Secure - The next name in the zone and types here  	Preamble + Label Sequence + type bitmap
+//48	DNSKEY	A public key of a zone                                  	Preamble + 2-bytes flags + protocol + algorithm + key
+//50	NSEC3	Hashed Next Secure - NSEC over hashed names             	Preamble + hash algorithm, flags, iterations, salt + next hash + type bitmap
+//51	NSEC3PARAM	The NSEC3 parameters a zone uses                        	Preamble + hash algorithm, flags, iterations, salt
+//64	SVCB	Service binding - Endpoints and parameters of a service 	Preamble + 2-bytes for priority + Label Sequence + SvcParams
+//65	HTTPS	HTTPS service binding - SVCB for https origins          	Preamble + 2-bytes for priority + Label Sequence + SvcParams
+//251	IXFR	Incremental zone transfer - Changes since a serial      	Only ever in the question section
+//252	AXFR	Zone transfer - Every record of a zone                  	Only ever in the question section
+//257	CAA	CA Authorization - Which CAs may issue certificates     	Preamble + 1-byte flags + length prefixed tag + value
 
+/// the type of a record, or what a question asks for
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
 pub enum QueryType {
+    /// any type we dont model, by number
     Unknown(u16),
-    A,     //1
-    NS,    //2
-    CNAME, //5
-    MX,    //15
-    AAAA,  //28
+    /// Alias - Mapping names to IP addresses (1)
+    A,
+    /// Name Server - The DNS server address for a domain (2)
+    NS,
+    /// Canonical Name - Maps names to names (5)
+    CNAME,
+    /// Start of Authority - Zone parameters (6)
+    SOA,
+    /// Pointer - Maps addresses back to names (12)
+    PTR,
+    /// Mail eXchange - The host of the mail server for a domain (15)
+    MX,
+    /// Text - Arbitrary strings, SPF, DKIM and the like (16)
+    TXT,
+    /// IPv6 alias (28)
+    AAAA,
+    /// Service locator - Where a service runs (33)
+    SRV,
+    /// Delegation Name - Maps a whole subtree to another name (39)
+    DNAME,
+    /// EDNS pseudo-record, only ever in the additional section (41)
+    OPT,
+    /// Delegation Signer - Hash of a child zone's key (43)
+    DS,
+    /// Signature over an RRset (46)
+    RRSIG,
+    /// Next Secure - The next name in the zone and types here (47)
+    NSEC,
+    /// A public key of a zone (48)
+    DNSKEY,
+    /// Hashed Next Secure - NSEC over hashed names (50)
+    NSEC3,
+    /// The NSEC3 parameters a zone uses (51)
+    NSEC3PARAM,
+    /// Service binding - Endpoints and parameters of a service (64)
+    SVCB,
+    /// HTTPS service binding - SVCB for https origins (65)
+    HTTPS,
+    /// Incremental zone transfer - Changes since a serial (251)
+    IXFR,
+    /// Zone transfer - Every record of a zone (252)
+    AXFR,
+    /// CA Authorization - Which CAs may issue certificates (257)
+    CAA,
 }
 
 impl From<u16> for QueryType {
@@ -23,8 +83,25 @@ impl From<u16> for QueryType {
             1 => QueryType::A,
             2 => QueryType::NS,
             5 => QueryType::CNAME,
+            6 => QueryType::SOA,
+            12 => QueryType::PTR,
             15 => QueryType::MX,
+            16 => QueryType::TXT,
             28 => QueryType::AAAA,
+            33 => QueryType::SRV,
+            39 => QueryType::DNAME,
+            41 => QueryType::OPT,
+            43 => QueryType::DS,
+            46 => QueryType::RRSIG,
+            47 => QueryType::NSEC,
+            48 => QueryType::DNSKEY,
+            50 => QueryType::NSEC3,
+            51 => QueryType::NSEC3PARAM,
+            64 => QueryType::SVCB,
+            65 => QueryType::HTTPS,
+            251 => QueryType::IXFR,
+            252 => QueryType::AXFR,
+            257 => QueryType::CAA,
             _ => QueryType::Unknown(value),
         }
     }
@@ -36,38 +113,134 @@ impl From<QueryType> for u16 {
             QueryType::A => 1,
             QueryType::NS => 2,
             QueryType::CNAME => 5,
+            QueryType::SOA => 6,
+            QueryType::PTR => 12,
             QueryType::MX => 15,
+            QueryType::TXT => 16,
             QueryType::AAAA => 28,
+            QueryType::SRV => 33,
+            QueryType::DNAME => 39,
+            QueryType::OPT => 41,
+            QueryType::DS => 43,
+            QueryType::RRSIG => 46,
+            QueryType::NSEC => 47,
+            QueryType::DNSKEY => 48,
+            QueryType::NSEC3 => 50,
+            QueryType::NSEC3PARAM => 51,
+            QueryType::SVCB => 64,
+            QueryType::HTTPS => 65,
+            QueryType::IXFR => 251,
+            QueryType::AXFR => 252,
+            QueryType::CAA => 257,
             QueryType::Unknown(x) => x,
         }
     }
 }
 
+/// the mnemonic used in zone files, `TYPE<n>` for types we dont know (RFC 3597 5)
+impl fmt::Display for QueryType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueryType::A => write!(f, "A"),
+            QueryType::NS => write!(f, "NS"),
+            QueryType::CNAME => write!(f, "CNAME"),
+            QueryType::SOA => write!(f, "SOA"),
+            QueryType::PTR => write!(f, "PTR"),
+            QueryType::MX => write!(f, "MX"),
+            QueryType::TXT => write!(f, "TXT"),
+            QueryType::AAAA => write!(f, "AAAA"),
+            QueryType::SRV => write!(f, "SRV"),
+            QueryType::DNAME => write!(f, "DNAME"),
+            QueryType::OPT => write!(f, "OPT"),
+            QueryType::DS => write!(f, "DS"),
+            QueryType::RRSIG => write!(f, "RRSIG"),
+            QueryType::NSEC => write!(f, "NSEC"),
+            QueryType::DNSKEY => write!(f, "DNSKEY"),
+            QueryType::NSEC3 => write!(f, "NSEC3"),
+            QueryType::NSEC3PARAM => write!(f, "NSEC3PARAM"),
+            QueryType::SVCB => write!(f, "SVCB"),
+            QueryType::HTTPS => write!(f, "HTTPS"),
+            QueryType::IXFR => write!(f, "IXFR"),
+            QueryType::AXFR => write!(f, "AXFR"),
+            QueryType::CAA => write!(f, "CAA"),
+            QueryType::Unknown(x) => write!(f, "TYPE{}", x),
+        }
+    }
+}
+
+impl QueryType {
+    /// the type a zone file mnemonic stands for, in any case. `TYPE<n>` works for every type
+    pub fn from_name(name: &str) -> Option<QueryType> {
+        let qtype = match name.to_ascii_uppercase().as_str() {
+            "A" => QueryType::A,
+            "NS" => QueryType::NS,
+            "CNAME" => QueryType::CNAME,
+            "SOA" => QueryType::SOA,
+            "PTR" => QueryType::PTR,
+            "MX" => QueryType::MX,
+            "TXT" => QueryType::TXT,
+            "AAAA" => QueryType::AAAA,
+            "SRV" => QueryType::SRV,
+            "DNAME" => QueryType::DNAME,
+            "OPT" => QueryType::OPT,
+            "DS" => QueryType::DS,
+            "RRSIG" => QueryType::RRSIG,
+            "NSEC" => QueryType::NSEC,
+            "DNSKEY" => QueryType::DNSKEY,
+            "NSEC3" => QueryType::NSEC3,
+            "NSEC3PARAM" => QueryType::NSEC3PARAM,
+            "SVCB" => QueryType::SVCB,
+            "HTTPS" => QueryType::HTTPS,
+            "IXFR" => QueryType::IXFR,
+            "AXFR" => QueryType::AXFR,
+            "CAA" => QueryType::CAA,
+            upper => {
+                let digits = upper.strip_prefix("TYPE")?;
+                if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+                    return None;
+                }
+                QueryType::from(digits.parse::<u16>().ok()?)
+            }
+        };
+        Some(qtype)
+    }
+}
+
+/// an entry of the question section
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct DnsQuestion {
+    /// the name asked about
     pub name: String,
+    /// the type of record wanted
     pub qtype: QueryType,
+    /// the class, 1 (IN) for anything but odd corners
+    pub class: u16,
 }
 
 impl DnsQuestion {
+    /// a question for `name` and `qtype` in class IN
     pub fn new(name: String, qtype: QueryType) -> Self {
-        Self { name, qtype }
+        // 1 is IN, the only class anyone uses
+        Self {
+            name,
+            qtype,
+            class: 1,
+        }
     }
 
-    pub fn write(&self, packet: &mut BytePacketBuffer) -> Result<(), Box<dyn std::error::Error>> {
+    /// writes name, type and class
+    pub fn write<T: PacketBuffer>(&self, packet: &mut T) -> Result<(), DnsError> {
         packet.write_qname(&self.name)?;
         let numbtype = u16::from(self.qtype);
         packet.write_u16(numbtype)?;
-        packet.write_u16(1)?;
+        packet.write_u16(self.class)?;
         Ok(())
     }
-    pub fn read(
-        &mut self,
-        packet: &mut BytePacketBuffer,
-    ) -> Result<(), Box<dyn std::error::Error>> {
+    /// reads name, type and class into `self`
+    pub fn read<T: PacketBuffer>(&mut self, packet: &mut T) -> Result<(), DnsError> {
         packet.read_qname(&mut self.name)?;
         self.qtype = QueryType::from(packet.read_u16()?);
-        let _ = packet.read_u16()?; //class
+        self.class = packet.read_u16()?;
         Ok(())
     }
 }
@@ -1,65 +1,454 @@
 use std::{
-    net::{Ipv4Addr, Ipv6Addr},
-    os::windows::raw,
+    fmt,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
 };
 
-use crate::{packet::BytePacketBuffer, question::QueryType};
+use crate::{
+    dnssec::{self, format_timestamp},
+    encoding::{base32hex_encode, base64_encode, hex_encode},
+    error::DnsError,
+    packet::{PacketBuffer, VectorPacketBuffer},
+    question::QueryType,
+    svcb::{self, SvcParam},
+};
+
+/// the longest character-string, its length has to fit in a byte
+pub const MAX_CHARACTER_STRING: usize = 255;
 
+/// a resource record, one variant per type we understand
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum DnsRecord {
+    /// any type we dont model, kept as raw RDATA so it survives the trip (RFC 3597)
     Unknown {
+        /// the owner name
         domain: String,
+        /// the record type
         qtype: u16,
-        data_len: u16,
+        /// the record class
+        class: u16,
+        /// the RDATA as it came
+        data: Vec<u8>,
+        /// seconds the record may be cached
         ttl: u32,
     },
+    /// an IPv4 address (RFC 1035)
     A {
+        /// the owner name
         domain: String,
+        /// the address
         addr: Ipv4Addr,
+        /// seconds the record may be cached
         ttl: u32,
     }, //1
+    /// an authoritative server of the zone at `domain`
     NS {
+        /// the owner name
         domain: String,
+        /// the name server
         host: String,
+        /// seconds the record may be cached
         ttl: u32,
     }, //2
+    /// `domain` is an alias for `host`
     CNAME {
+        /// the owner name
         domain: String,
+        /// the canonical name
         host: String,
+        /// seconds the record may be cached
         ttl: u32,
     }, //5
+    /// start of a zone, its parameters for secondaries and negative caching
+    SOA {
+        /// the owner name
+        domain: String,
+        /// the primary server of the zone
+        mname: String,
+        /// the mailbox of whoever runs the zone, with the @ as the first dot
+        rname: String,
+        /// version of the zone, in serial number arithmetic (RFC 1982)
+        serial: u32,
+        /// seconds between secondaries checking for a new serial
+        refresh: u32,
+        /// seconds before a secondary retries a failed refresh
+        retry: u32,
+        /// seconds a secondary keeps serving without reaching the primary
+        expire: u32,
+        /// the TTL of negative answers (RFC 2308 4)
+        minimum: u32,
+        /// seconds the record may be cached
+        ttl: u32,
+    }, //6
+    /// maps an address under in-addr.arpa or ip6.arpa back to a name
+    PTR {
+        /// the owner name
+        domain: String,
+        /// the name the address belongs to
+        host: String,
+        /// seconds the record may be cached
+        ttl: u32,
+    }, //12
+    /// a mail server of `domain`
     MX {
+        /// the owner name
         domain: String,
+        /// lower is preferred
         priority: u16,
+        /// the mail server
         host: String,
+        /// seconds the record may be cached
         ttl: u32,
     }, //15
+    /// arbitrary text, such as SPF or DKIM data
+    TXT {
+        /// the owner name
+        domain: String,
+        /// the character-strings, each at most 255 bytes
+        data: Vec<Vec<u8>>,
+        /// seconds the record may be cached
+        ttl: u32,
+    }, //16
+    /// an IPv6 address (RFC 3596)
     AAAA {
+        /// the owner name
         domain: String,
+        /// the address
         addr: Ipv6Addr,
+        /// seconds the record may be cached
         ttl: u32,
     }, //28
+    /// where a service runs (RFC 2782)
+    SRV {
+        /// the owner name
+        domain: String,
+        /// lower is tried first
+        priority: u16,
+        /// share of the traffic among targets of the same priority
+        weight: u16,
+        /// the port the service listens on
+        port: u16,
+        /// the root means the service isnt available at this domain
+        host: String,
+        /// seconds the record may be cached
+        ttl: u32,
+    }, //33
+    /// redirects every name below `domain` (RFC 6672)
+    DNAME {
+        /// the owner name
+        domain: String,
+        /// what the owner name is replaced with in names below it
+        target: String,
+        /// seconds the record may be cached
+        ttl: u32,
+    }, //39
+    /// digest of a child zone key, kept in the parent (RFC 4034 5)
+    DS {
+        /// the owner name
+        domain: String,
+        /// tag of the DNSKEY the digest is of
+        key_tag: u16,
+        /// algorithm of that DNSKEY
+        algorithm: u8,
+        /// the hash used, such as SHA-256
+        digest_type: u8,
+        /// the digest of owner name and DNSKEY RDATA
+        digest: Vec<u8>,
+        /// seconds the record may be cached
+        ttl: u32,
+    }, //43
+    /// a signature over an RRset (RFC 4034 3)
+    RRSIG {
+        /// the owner name
+        domain: String,
+        /// the type of the RRset signed
+        type_covered: QueryType,
+        /// the signing algorithm
+        algorithm: u8,
+        /// labels of the owner name the signature was made for, fewer means a wildcard
+        labels: u8,
+        /// the TTL of the RRset when it was signed
+        original_ttl: u32,
+        /// seconds since 1970, in serial number arithmetic
+        expiration: u32,
+        /// seconds since 1970 from which the signature is valid
+        inception: u32,
+        /// tag of the DNSKEY that made the signature
+        key_tag: u16,
+        /// the zone that signed
+        signer: String,
+        /// the signature itself
+        signature: Vec<u8>,
+        /// seconds the record may be cached
+        ttl: u32,
+    }, //46
+    /// the next name of a signed zone and the types at this one (RFC 4034 4)
+    NSEC {
+        /// the owner name
+        domain: String,
+        /// the next owner name of the zone in canonical order
+        next: String,
+        /// the types present at `domain`
+        types: Vec<QueryType>,
+        /// seconds the record may be cached
+        ttl: u32,
+    }, //47
+    /// a public key of a zone (RFC 4034 2)
+    DNSKEY {
+        /// the owner name
+        domain: String,
+        /// 0x0100 marks a zone key, 0x0001 a key signing key
+        flags: u16,
+        /// always 3
+        protocol: u8,
+        /// the signing algorithm
+        algorithm: u8,
+        /// the key in the format of its algorithm
+        public_key: Vec<u8>,
+        /// seconds the record may be cached
+        ttl: u32,
+    }, //48
+    /// NSEC over hashed names (RFC 5155 3)
+    NSEC3 {
+        /// the owner name
+        domain: String,
+        /// 1 is SHA-1, the only one defined
+        hash_algorithm: u8,
+        /// 0x01 is opt-out
+        flags: u8,
+        /// extra rounds of hashing
+        iterations: u16,
+        /// appended to the name before every round
+        salt: Vec<u8>,
+        /// the raw hash of the next name, not its base32 form
+        next_hashed: Vec<u8>,
+        /// the types present at the hashed name
+        types: Vec<QueryType>,
+        /// seconds the record may be cached
+        ttl: u32,
+    }, //50
+    /// the NSEC3 parameters a zone uses (RFC 5155 4)
+    NSEC3PARAM {
+        /// the owner name
+        domain: String,
+        /// 1 is SHA-1, the only one defined
+        hash_algorithm: u8,
+        /// always 0 for NSEC3PARAM
+        flags: u8,
+        /// extra rounds of hashing
+        iterations: u16,
+        /// appended to the name before every round
+        salt: Vec<u8>,
+        /// seconds the record may be cached
+        ttl: u32,
+    }, //51
+    /// where and how to reach a service (RFC 9460)
+    SVCB {
+        /// the owner name
+        domain: String,
+        /// 0 makes the record an alias for `target`
+        priority: u16,
+        /// the root stands for the owner name
+        target: String,
+        /// the SvcParams, ordered by key
+        params: Vec<SvcParam>,
+        /// seconds the record may be cached
+        ttl: u32,
+    }, //64
+    /// SVCB for https origins (RFC 9460 9)
+    HTTPS {
+        /// the owner name
+        domain: String,
+        /// 0 makes the record an alias for `target`
+        priority: u16,
+        /// the root stands for the owner name
+        target: String,
+        /// the SvcParams, ordered by key
+        params: Vec<SvcParam>,
+        /// seconds the record may be cached
+        ttl: u32,
+    }, //65
+    /// which certificate authorities may issue for `domain` (RFC 8659)
+    CAA {
+        /// the owner name
+        domain: String,
+        /// 0x80 is the issuer critical flag
+        flags: u8,
+        /// letters and digits only, like issue, issuewild or iodef
+        tag: String,
+        /// what the tag says, such as the domain of a CA
+        value: Vec<u8>,
+        /// seconds the record may be cached
+        ttl: u32,
+    }, //257
 }
 
 impl DnsRecord {
-    pub fn read(packet: &mut BytePacketBuffer) -> Result<DnsRecord, Box<dyn std::error::Error>> {
+    /// a TXT record holding `text`, split into as many character-strings as it takes
+    pub fn txt(domain: &str, text: &[u8], ttl: u32) -> DnsRecord {
+        let data = if text.is_empty() {
+            vec![Vec::new()]
+        } else {
+            text.chunks(MAX_CHARACTER_STRING)
+                .map(<[u8]>::to_vec)
+                .collect()
+        };
+        DnsRecord::TXT {
+            domain: domain.to_string(),
+            data,
+            ttl,
+        }
+    }
+
+    /// the owner name of the record
+    pub fn domain(&self) -> &str {
+        match self {
+            DnsRecord::Unknown { domain, .. }
+            | DnsRecord::A { domain, .. }
+            | DnsRecord::NS { domain, .. }
+            | DnsRecord::CNAME { domain, .. }
+            | DnsRecord::SOA { domain, .. }
+            | DnsRecord::PTR { domain, .. }
+            | DnsRecord::MX { domain, .. }
+            | DnsRecord::TXT { domain, .. }
+            | DnsRecord::AAAA { domain, .. }
+            | DnsRecord::SRV { domain, .. }
+            | DnsRecord::DNAME { domain, .. }
+            | DnsRecord::DS { domain, .. }
+            | DnsRecord::RRSIG { domain, .. }
+            | DnsRecord::NSEC { domain, .. }
+            | DnsRecord::DNSKEY { domain, .. }
+            | DnsRecord::NSEC3 { domain, .. }
+            | DnsRecord::NSEC3PARAM { domain, .. }
+            | DnsRecord::SVCB { domain, .. }
+            | DnsRecord::HTTPS { domain, .. }
+            | DnsRecord::CAA { domain, .. } => domain,
+        }
+    }
+
+    /// the type of the record
+    pub fn qtype(&self) -> QueryType {
+        match self {
+            DnsRecord::Unknown { qtype, .. } => QueryType::from(*qtype),
+            DnsRecord::A { .. } => QueryType::A,
+            DnsRecord::NS { .. } => QueryType::NS,
+            DnsRecord::CNAME { .. } => QueryType::CNAME,
+            DnsRecord::SOA { .. } => QueryType::SOA,
+            DnsRecord::PTR { .. } => QueryType::PTR,
+            DnsRecord::MX { .. } => QueryType::MX,
+            DnsRecord::TXT { .. } => QueryType::TXT,
+            DnsRecord::AAAA { .. } => QueryType::AAAA,
+            DnsRecord::SRV { .. } => QueryType::SRV,
+            DnsRecord::DNAME { .. } => QueryType::DNAME,
+            DnsRecord::DS { .. } => QueryType::DS,
+            DnsRecord::RRSIG { .. } => QueryType::RRSIG,
+            DnsRecord::NSEC { .. } => QueryType::NSEC,
+            DnsRecord::DNSKEY { .. } => QueryType::DNSKEY,
+            DnsRecord::NSEC3 { .. } => QueryType::NSEC3,
+            DnsRecord::NSEC3PARAM { .. } => QueryType::NSEC3PARAM,
+            DnsRecord::SVCB { .. } => QueryType::SVCB,
+            DnsRecord::HTTPS { .. } => QueryType::HTTPS,
+            DnsRecord::CAA { .. } => QueryType::CAA,
+        }
+    }
+
+    /// seconds the record may be cached
+    pub fn ttl(&self) -> u32 {
+        match self {
+            DnsRecord::Unknown { ttl, .. }
+            | DnsRecord::A { ttl, .. }
+            | DnsRecord::NS { ttl, .. }
+            | DnsRecord::CNAME { ttl, .. }
+            | DnsRecord::SOA { ttl, .. }
+            | DnsRecord::PTR { ttl, .. }
+            | DnsRecord::MX { ttl, .. }
+            | DnsRecord::TXT { ttl, .. }
+            | DnsRecord::AAAA { ttl, .. }
+            | DnsRecord::SRV { ttl, .. }
+            | DnsRecord::DNAME { ttl, .. }
+            | DnsRecord::DS { ttl, .. }
+            | DnsRecord::RRSIG { ttl, .. }
+            | DnsRecord::NSEC { ttl, .. }
+            | DnsRecord::DNSKEY { ttl, .. }
+            | DnsRecord::NSEC3 { ttl, .. }
+            | DnsRecord::NSEC3PARAM { ttl, .. }
+            | DnsRecord::SVCB { ttl, .. }
+            | DnsRecord::HTTPS { ttl, .. }
+            | DnsRecord::CAA { ttl, .. } => *ttl,
+        }
+    }
+
+    /// replaces the TTL, as when counting it down in a cache
+    pub fn set_ttl(&mut self, new_ttl: u32) {
+        match self {
+            DnsRecord::Unknown { ttl, .. }
+            | DnsRecord::A { ttl, .. }
+            | DnsRecord::NS { ttl, .. }
+            | DnsRecord::CNAME { ttl, .. }
+            | DnsRecord::SOA { ttl, .. }
+            | DnsRecord::PTR { ttl, .. }
+            | DnsRecord::MX { ttl, .. }
+            | DnsRecord::TXT { ttl, .. }
+            | DnsRecord::AAAA { ttl, .. }
+            | DnsRecord::SRV { ttl, .. }
+            | DnsRecord::DNAME { ttl, .. }
+            | DnsRecord::DS { ttl, .. }
+            | DnsRecord::RRSIG { ttl, .. }
+            | DnsRecord::NSEC { ttl, .. }
+            | DnsRecord::DNSKEY { ttl, .. }
+            | DnsRecord::NSEC3 { ttl, .. }
+            | DnsRecord::NSEC3PARAM { ttl, .. }
+            | DnsRecord::SVCB { ttl, .. }
+            | DnsRecord::HTTPS { ttl, .. }
+            | DnsRecord::CAA { ttl, .. } => *ttl = new_ttl,
+        }
+    }
+
+    /// moves the record to another owner, the way wildcard answers are synthesized
+    pub fn set_domain(&mut self, new_domain: &str) {
+        match self {
+            DnsRecord::Unknown { domain, .. }
+            | DnsRecord::A { domain, .. }
+            | DnsRecord::NS { domain, .. }
+            | DnsRecord::CNAME { domain, .. }
+            | DnsRecord::SOA { domain, .. }
+            | DnsRecord::PTR { domain, .. }
+            | DnsRecord::MX { domain, .. }
+            | DnsRecord::TXT { domain, .. }
+            | DnsRecord::AAAA { domain, .. }
+            | DnsRecord::SRV { domain, .. }
+            | DnsRecord::DNAME { domain, .. }
+            | DnsRecord::DS { domain, .. }
+            | DnsRecord::RRSIG { domain, .. }
+            | DnsRecord::NSEC { domain, .. }
+            | DnsRecord::DNSKEY { domain, .. }
+            | DnsRecord::NSEC3 { domain, .. }
+            | DnsRecord::NSEC3PARAM { domain, .. }
+            | DnsRecord::SVCB { domain, .. }
+            | DnsRecord::HTTPS { domain, .. }
+            | DnsRecord::CAA { domain, .. } => *domain = new_domain.to_string(),
+        }
+    }
+
+    /// reads a whole record, checking that its RDATA fills exactly RDLENGTH bytes
+    pub fn read<T: PacketBuffer>(packet: &mut T) -> Result<DnsRecord, DnsError> {
         let mut domain = String::new();
         packet.read_qname(&mut domain)?;
 
         let qtype = QueryType::from(packet.read_u16()?);
 
-        let _ = packet.read_u16()?;
+        let class = packet.read_u16()?;
         let ttl = packet.read_u32()?;
         let data_len = packet.read_u16()?;
 
-        match qtype {
+        let rdata_start = packet.pos();
+        let record = match qtype {
             QueryType::A => {
                 let raw_addr = packet.read_u32()?;
                 let addr = Ipv4Addr::new(
                     ((raw_addr >> 24) & 0xFF) as u8,
                     ((raw_addr >> 16) & 0xFF) as u8,
                     ((raw_addr >> 8) & 0xFF) as u8,
-                    ((raw_addr >> 0) & 0xFF) as u8,
+                    (raw_addr & 0xFF) as u8,
                 );
                 Ok(DnsRecord::A { domain, addr, ttl })
             }
@@ -70,13 +459,13 @@ impl DnsRecord {
                 let raw_addr4 = packet.read_u32()?;
                 let addr = Ipv6Addr::new(
                     ((raw_addr1 >> 16) & 0xFFFF) as u16,
-                    ((raw_addr1 >> 0) & 0xFFFF) as u16,
+                    (raw_addr1 & 0xFFFF) as u16,
                     ((raw_addr2 >> 16) & 0xFFFF) as u16,
-                    ((raw_addr2 >> 0) & 0xFFFF) as u16,
+                    (raw_addr2 & 0xFFFF) as u16,
                     ((raw_addr3 >> 16) & 0xFFFF) as u16,
-                    ((raw_addr3 >> 0) & 0xFFFF) as u16,
+                    (raw_addr3 & 0xFFFF) as u16,
                     ((raw_addr4 >> 16) & 0xFFFF) as u16,
-                    ((raw_addr4 >> 0) & 0xFFFF) as u16,
+                    (raw_addr4 & 0xFFFF) as u16,
                 );
                 Ok(DnsRecord::AAAA { domain, addr, ttl })
             }
@@ -100,6 +489,36 @@ impl DnsRecord {
                     ttl,
                 })
             }
+            QueryType::SOA => {
+                let mut mname = String::new();
+                packet.read_qname(&mut mname)?;
+                let mut rname = String::new();
+                packet.read_qname(&mut rname)?;
+
+                let serial = packet.read_u32()?;
+                let refresh = packet.read_u32()?;
+                let retry = packet.read_u32()?;
+                let expire = packet.read_u32()?;
+                let minimum = packet.read_u32()?;
+
+                Ok(DnsRecord::SOA {
+                    domain,
+                    mname,
+                    rname,
+                    serial,
+                    refresh,
+                    retry,
+                    expire,
+                    minimum,
+                    ttl,
+                })
+            }
+            QueryType::PTR => {
+                let mut host = String::new();
+                packet.read_qname(&mut host)?;
+
+                Ok(DnsRecord::PTR { domain, host, ttl })
+            }
             QueryType::MX => {
                 let priority = packet.read_u16()?;
                 let mut mx = String::new();
@@ -113,21 +532,232 @@ impl DnsRecord {
                     ttl,
                 })
             }
-            QueryType::Unknown(_) => {
-                packet.step(data_len as usize);
+            QueryType::TXT => {
+                let end = rdata_start + data_len as usize;
+                let mut data = Vec::new();
+                while packet.pos() < end {
+                    let len = packet.read()? as usize;
+                    data.push(packet.get_range(packet.pos(), len)?.to_vec());
+                    packet.step(len)?;
+                }
+
+                Ok(DnsRecord::TXT { domain, data, ttl })
+            }
+            QueryType::SRV => {
+                let priority = packet.read_u16()?;
+                let weight = packet.read_u16()?;
+                let port = packet.read_u16()?;
+                let mut host = String::new();
+                packet.read_qname(&mut host)?;
+
+                Ok(DnsRecord::SRV {
+                    domain,
+                    priority,
+                    weight,
+                    port,
+                    host,
+                    ttl,
+                })
+            }
+            QueryType::CAA => {
+                let flags = packet.read()?;
+                let tag_len = packet.read()? as usize;
+                let tag = packet.get_range(packet.pos(), tag_len)?;
+                if !valid_caa_tag(tag) {
+                    return Err(DnsError::BadRdata {
+                        offset: packet.pos(),
+                        qtype: qtype.into(),
+                        reason: "CAA tag has to be letters and digits",
+                    });
+                }
+                let tag = String::from_utf8_lossy(tag).into_owned();
+                packet.step(tag_len)?;
+
+                let value = read_rest(packet, rdata_start + data_len as usize)?;
+
+                Ok(DnsRecord::CAA {
+                    domain,
+                    flags,
+                    tag,
+                    value,
+                    ttl,
+                })
+            }
+            QueryType::SVCB => {
+                let end = rdata_start + data_len as usize;
+                let (priority, target, params) = svcb::read(packet, end, qtype.into())?;
+
+                Ok(DnsRecord::SVCB {
+                    domain,
+                    priority,
+                    target,
+                    params,
+                    ttl,
+                })
+            }
+            QueryType::HTTPS => {
+                let end = rdata_start + data_len as usize;
+                let (priority, target, params) = svcb::read(packet, end, qtype.into())?;
+
+                Ok(DnsRecord::HTTPS {
+                    domain,
+                    priority,
+                    target,
+                    params,
+                    ttl,
+                })
+            }
+            QueryType::DS => {
+                let end = rdata_start + data_len as usize;
+                let key_tag = packet.read_u16()?;
+                let algorithm = packet.read()?;
+                let digest_type = packet.read()?;
+                let digest = read_rest(packet, end)?;
+
+                Ok(DnsRecord::DS {
+                    domain,
+                    key_tag,
+                    algorithm,
+                    digest_type,
+                    digest,
+                    ttl,
+                })
+            }
+            QueryType::RRSIG => {
+                let end = rdata_start + data_len as usize;
+                let type_covered = QueryType::from(packet.read_u16()?);
+                let algorithm = packet.read()?;
+                let labels = packet.read()?;
+                let original_ttl = packet.read_u32()?;
+                let expiration = packet.read_u32()?;
+                let inception = packet.read_u32()?;
+                let key_tag = packet.read_u16()?;
+                let mut signer = String::new();
+                packet.read_qname(&mut signer)?;
+                let signature = read_rest(packet, end)?;
+
+                Ok(DnsRecord::RRSIG {
+                    domain,
+                    type_covered,
+                    algorithm,
+                    labels,
+                    original_ttl,
+                    expiration,
+                    inception,
+                    key_tag,
+                    signer,
+                    signature,
+                    ttl,
+                })
+            }
+            QueryType::NSEC => {
+                let end = rdata_start + data_len as usize;
+                let mut next = String::new();
+                packet.read_qname(&mut next)?;
+                let types = dnssec::read_type_bitmap(packet, end, qtype.into())?;
+
+                Ok(DnsRecord::NSEC {
+                    domain,
+                    next,
+                    types,
+                    ttl,
+                })
+            }
+            QueryType::DNSKEY => {
+                let end = rdata_start + data_len as usize;
+                let flags = packet.read_u16()?;
+                let protocol = packet.read()?;
+                let algorithm = packet.read()?;
+                let public_key = read_rest(packet, end)?;
+
+                Ok(DnsRecord::DNSKEY {
+                    domain,
+                    flags,
+                    protocol,
+                    algorithm,
+                    public_key,
+                    ttl,
+                })
+            }
+            QueryType::NSEC3 => {
+                let end = rdata_start + data_len as usize;
+                let hash_algorithm = packet.read()?;
+                let flags = packet.read()?;
+                let iterations = packet.read_u16()?;
+                let salt_len = packet.read()? as usize;
+                let salt = packet.get_range(packet.pos(), salt_len)?.to_vec();
+                packet.step(salt_len)?;
+                let hash_len = packet.read()? as usize;
+                let next_hashed = packet.get_range(packet.pos(), hash_len)?.to_vec();
+                packet.step(hash_len)?;
+                let types = dnssec::read_type_bitmap(packet, end, qtype.into())?;
+
+                Ok(DnsRecord::NSEC3 {
+                    domain,
+                    hash_algorithm,
+                    flags,
+                    iterations,
+                    salt,
+                    next_hashed,
+                    types,
+                    ttl,
+                })
+            }
+            QueryType::NSEC3PARAM => {
+                let hash_algorithm = packet.read()?;
+                let flags = packet.read()?;
+                let iterations = packet.read_u16()?;
+                let salt_len = packet.read()? as usize;
+                let salt = packet.get_range(packet.pos(), salt_len)?.to_vec();
+                packet.step(salt_len)?;
+
+                Ok(DnsRecord::NSEC3PARAM {
+                    domain,
+                    hash_algorithm,
+                    flags,
+                    iterations,
+                    salt,
+                    ttl,
+                })
+            }
+            QueryType::DNAME => {
+                let mut target = String::new();
+                packet.read_qname(&mut target)?;
+
+                Ok(DnsRecord::DNAME {
+                    domain,
+                    target,
+                    ttl,
+                })
+            }
+            QueryType::Unknown(_) | QueryType::OPT | QueryType::IXFR | QueryType::AXFR => {
+                let data = packet.get_range(packet.pos(), data_len as usize)?.to_vec();
+                packet.step(data_len as usize)?;
                 Ok(DnsRecord::Unknown {
                     domain,
                     qtype: qtype.into(),
-                    data_len,
+                    class,
+                    data,
                     ttl,
                 })
             }
+        }?;
+
+        // how much RDATA a type takes up has to agree with RDLENGTH
+        let consumed = packet.pos() - rdata_start;
+        if consumed != data_len as usize {
+            return Err(DnsError::BadRdataLength {
+                offset: rdata_start,
+                qtype: qtype.into(),
+                expected: data_len as usize,
+                actual: consumed,
+            });
         }
+        Ok(record)
     }
-    pub fn write(
-        &self,
-        packet: &mut BytePacketBuffer,
-    ) -> Result<usize, Box<dyn std::error::Error>> {
+
+    /// writes the record, returning how many bytes it took
+    pub fn write<T: PacketBuffer>(&self, packet: &mut T) -> Result<usize, DnsError> {
         let start_pos = packet.pos();
         match *self {
             DnsRecord::A {
@@ -135,7 +765,7 @@ impl DnsRecord {
                 addr,
                 ttl,
             } => {
-                packet.write_qname(&domain)?;
+                packet.write_qname(domain)?;
                 packet.write_u16(u16::from(QueryType::A))?;
                 packet.write_u16(1)?;
                 packet.write_u32(ttl)?;
@@ -152,7 +782,7 @@ impl DnsRecord {
                 ref host,
                 ttl,
             } => {
-                packet.write_qname(&domain)?;
+                packet.write_qname(domain)?;
                 packet.write_u16(u16::from(QueryType::NS))?;
                 packet.write_u16(1)?;
                 packet.write_u32(ttl)?;
@@ -161,11 +791,11 @@ impl DnsRecord {
                 let pos = packet.pos();
                 packet.write_u16(0)?;
 
-                packet.write_qname(&host)?;
+                packet.write_qname(host)?;
 
                 let size = packet.pos() - (pos + 2);
                 // now we set the size
-                packet.set_u16(pos, size as u16);
+                packet.set_u16(pos, size as u16)?;
             }
             DnsRecord::CNAME {
                 ref domain,
@@ -183,7 +813,55 @@ impl DnsRecord {
                 packet.write_qname(host)?;
 
                 let size = packet.pos() - (pos + 2);
-                packet.set_u16(pos, size as u16);
+                packet.set_u16(pos, size as u16)?;
+            }
+            DnsRecord::SOA {
+                ref domain,
+                ref mname,
+                ref rname,
+                serial,
+                refresh,
+                retry,
+                expire,
+                minimum,
+                ttl,
+            } => {
+                packet.write_qname(domain)?;
+                packet.write_u16(u16::from(QueryType::SOA))?;
+                packet.write_u16(1)?;
+                packet.write_u32(ttl)?;
+
+                let pos = packet.pos();
+                packet.write_u16(0)?;
+
+                packet.write_qname(mname)?;
+                packet.write_qname(rname)?;
+                packet.write_u32(serial)?;
+                packet.write_u32(refresh)?;
+                packet.write_u32(retry)?;
+                packet.write_u32(expire)?;
+                packet.write_u32(minimum)?;
+
+                let size = packet.pos() - (pos + 2);
+                packet.set_u16(pos, size as u16)?;
+            }
+            DnsRecord::PTR {
+                ref domain,
+                ref host,
+                ttl,
+            } => {
+                packet.write_qname(domain)?;
+                packet.write_u16(u16::from(QueryType::PTR))?;
+                packet.write_u16(1)?;
+                packet.write_u32(ttl)?;
+
+                let pos = packet.pos();
+                packet.write_u16(0)?;
+
+                packet.write_qname(host)?;
+
+                let size = packet.pos() - (pos + 2);
+                packet.set_u16(pos, size as u16)?;
             }
             DnsRecord::MX {
                 ref domain,
@@ -203,7 +881,7 @@ impl DnsRecord {
                 packet.write_qname(host)?;
 
                 let size = packet.pos() - (pos + 2);
-                packet.set_u16(pos, size as u16);
+                packet.set_u16(pos, size as u16)?;
             }
             DnsRecord::AAAA {
                 ref domain,
@@ -220,10 +898,1076 @@ impl DnsRecord {
                     packet.write_u16(*octet)?;
                 }
             }
-            DnsRecord::Unknown { .. } => {
-                println!("Skipping record: {:?}", self);
+            DnsRecord::TXT {
+                ref domain,
+                ref data,
+                ttl,
+            } => {
+                packet.write_qname(domain)?;
+                packet.write_u16(u16::from(QueryType::TXT))?;
+                packet.write_u16(1)?;
+                packet.write_u32(ttl)?;
+
+                let pos = packet.pos();
+                packet.write_u16(0)?;
+
+                for string in data {
+                    if string.len() > MAX_CHARACTER_STRING {
+                        return Err(DnsError::BadRdata {
+                            offset: packet.pos(),
+                            qtype: QueryType::TXT.into(),
+                            reason: "character-string longer than 255 bytes",
+                        });
+                    }
+                    packet.write(string.len() as u8)?;
+                    for b in string {
+                        packet.write(*b)?;
+                    }
+                }
+
+                let size = packet.pos() - (pos + 2);
+                packet.set_u16(pos, size as u16)?;
+            }
+            DnsRecord::SRV {
+                ref domain,
+                priority,
+                weight,
+                port,
+                ref host,
+                ttl,
+            } => {
+                packet.write_qname(domain)?;
+                packet.write_u16(u16::from(QueryType::SRV))?;
+                packet.write_u16(1)?;
+                packet.write_u32(ttl)?;
+
+                let pos = packet.pos();
+                packet.write_u16(0)?;
+
+                packet.write_u16(priority)?;
+                packet.write_u16(weight)?;
+                packet.write_u16(port)?;
+                // SRV came after RFC 1035, its target must not be compressed (RFC 2782)
+                packet.write_qname_uncompressed(host)?;
+
+                let size = packet.pos() - (pos + 2);
+                packet.set_u16(pos, size as u16)?;
+            }
+            DnsRecord::CAA {
+                ref domain,
+                flags,
+                ref tag,
+                ref value,
+                ttl,
+            } => {
+                packet.write_qname(domain)?;
+                packet.write_u16(u16::from(QueryType::CAA))?;
+                packet.write_u16(1)?;
+                packet.write_u32(ttl)?;
+
+                let pos = packet.pos();
+                packet.write_u16(0)?;
+
+                if !valid_caa_tag(tag.as_bytes()) {
+                    return Err(DnsError::BadRdata {
+                        offset: packet.pos(),
+                        qtype: QueryType::CAA.into(),
+                        reason: "CAA tag has to be letters and digits",
+                    });
+                }
+                packet.write(flags)?;
+                packet.write(tag.len() as u8)?;
+                for b in tag.bytes().chain(value.iter().copied()) {
+                    packet.write(b)?;
+                }
+
+                let size = packet.pos() - (pos + 2);
+                packet.set_u16(pos, size as u16)?;
+            }
+            DnsRecord::SVCB {
+                ref domain,
+                priority,
+                ref target,
+                ref params,
+                ttl,
+            } => {
+                packet.write_qname(domain)?;
+                packet.write_u16(u16::from(QueryType::SVCB))?;
+                packet.write_u16(1)?;
+                packet.write_u32(ttl)?;
+
+                let pos = packet.pos();
+                packet.write_u16(0)?;
+
+                svcb::write(packet, priority, target, params, QueryType::SVCB.into())?;
+
+                let size = packet.pos() - (pos + 2);
+                packet.set_u16(pos, size as u16)?;
+            }
+            DnsRecord::HTTPS {
+                ref domain,
+                priority,
+                ref target,
+                ref params,
+                ttl,
+            } => {
+                packet.write_qname(domain)?;
+                packet.write_u16(u16::from(QueryType::HTTPS))?;
+                packet.write_u16(1)?;
+                packet.write_u32(ttl)?;
+
+                let pos = packet.pos();
+                packet.write_u16(0)?;
+
+                svcb::write(packet, priority, target, params, QueryType::HTTPS.into())?;
+
+                let size = packet.pos() - (pos + 2);
+                packet.set_u16(pos, size as u16)?;
+            }
+            DnsRecord::DS {
+                ref domain,
+                key_tag,
+                algorithm,
+                digest_type,
+                ref digest,
+                ttl,
+            } => {
+                packet.write_qname(domain)?;
+                packet.write_u16(u16::from(QueryType::DS))?;
+                packet.write_u16(1)?;
+                packet.write_u32(ttl)?;
+
+                let pos = packet.pos();
+                packet.write_u16(0)?;
+
+                packet.write_u16(key_tag)?;
+                packet.write(algorithm)?;
+                packet.write(digest_type)?;
+                for b in digest {
+                    packet.write(*b)?;
+                }
+
+                let size = packet.pos() - (pos + 2);
+                packet.set_u16(pos, size as u16)?;
+            }
+            DnsRecord::RRSIG {
+                ref domain,
+                type_covered,
+                algorithm,
+                labels,
+                original_ttl,
+                expiration,
+                inception,
+                key_tag,
+                ref signer,
+                ref signature,
+                ttl,
+            } => {
+                packet.write_qname(domain)?;
+                packet.write_u16(u16::from(QueryType::RRSIG))?;
+                packet.write_u16(1)?;
+                packet.write_u32(ttl)?;
+
+                let pos = packet.pos();
+                packet.write_u16(0)?;
+
+                packet.write_u16(type_covered.into())?;
+                packet.write(algorithm)?;
+                packet.write(labels)?;
+                packet.write_u32(original_ttl)?;
+                packet.write_u32(expiration)?;
+                packet.write_u32(inception)?;
+                packet.write_u16(key_tag)?;
+                packet.write_qname_uncompressed(signer)?;
+                for b in signature {
+                    packet.write(*b)?;
+                }
+
+                let size = packet.pos() - (pos + 2);
+                packet.set_u16(pos, size as u16)?;
+            }
+            DnsRecord::NSEC {
+                ref domain,
+                ref next,
+                ref types,
+                ttl,
+            } => {
+                packet.write_qname(domain)?;
+                packet.write_u16(u16::from(QueryType::NSEC))?;
+                packet.write_u16(1)?;
+                packet.write_u32(ttl)?;
+
+                let pos = packet.pos();
+                packet.write_u16(0)?;
+
+                packet.write_qname_uncompressed(next)?;
+                dnssec::write_type_bitmap(packet, types)?;
+
+                let size = packet.pos() - (pos + 2);
+                packet.set_u16(pos, size as u16)?;
+            }
+            DnsRecord::DNSKEY {
+                ref domain,
+                flags,
+                protocol,
+                algorithm,
+                ref public_key,
+                ttl,
+            } => {
+                packet.write_qname(domain)?;
+                packet.write_u16(u16::from(QueryType::DNSKEY))?;
+                packet.write_u16(1)?;
+                packet.write_u32(ttl)?;
+
+                let pos = packet.pos();
+                packet.write_u16(0)?;
+
+                packet.write_u16(flags)?;
+                packet.write(protocol)?;
+                packet.write(algorithm)?;
+                for b in public_key {
+                    packet.write(*b)?;
+                }
+
+                let size = packet.pos() - (pos + 2);
+                packet.set_u16(pos, size as u16)?;
+            }
+            DnsRecord::NSEC3 {
+                ref domain,
+                hash_algorithm,
+                flags,
+                iterations,
+                ref salt,
+                ref next_hashed,
+                ref types,
+                ttl,
+            } => {
+                packet.write_qname(domain)?;
+                packet.write_u16(u16::from(QueryType::NSEC3))?;
+                packet.write_u16(1)?;
+                packet.write_u32(ttl)?;
+
+                let pos = packet.pos();
+                packet.write_u16(0)?;
+
+                packet.write(hash_algorithm)?;
+                packet.write(flags)?;
+                packet.write_u16(iterations)?;
+                packet.write(salt.len() as u8)?;
+                for b in salt {
+                    packet.write(*b)?;
+                }
+                packet.write(next_hashed.len() as u8)?;
+                for b in next_hashed {
+                    packet.write(*b)?;
+                }
+                dnssec::write_type_bitmap(packet, types)?;
+
+                let size = packet.pos() - (pos + 2);
+                packet.set_u16(pos, size as u16)?;
+            }
+            DnsRecord::NSEC3PARAM {
+                ref domain,
+                hash_algorithm,
+                flags,
+                iterations,
+                ref salt,
+                ttl,
+            } => {
+                packet.write_qname(domain)?;
+                packet.write_u16(u16::from(QueryType::NSEC3PARAM))?;
+                packet.write_u16(1)?;
+                packet.write_u32(ttl)?;
+
+                let pos = packet.pos();
+                packet.write_u16(0)?;
+
+                packet.write(hash_algorithm)?;
+                packet.write(flags)?;
+                packet.write_u16(iterations)?;
+                packet.write(salt.len() as u8)?;
+                for b in salt {
+                    packet.write(*b)?;
+                }
+
+                let size = packet.pos() - (pos + 2);
+                packet.set_u16(pos, size as u16)?;
+            }
+            DnsRecord::DNAME {
+                ref domain,
+                ref target,
+                ttl,
+            } => {
+                packet.write_qname(domain)?;
+                packet.write_u16(u16::from(QueryType::DNAME))?;
+                packet.write_u16(1)?;
+                packet.write_u32(ttl)?;
+
+                let pos = packet.pos();
+                packet.write_u16(0)?;
+
+                // the target must not be compressed (RFC 6672 2.5)
+                packet.write_qname_uncompressed(target)?;
+
+                let size = packet.pos() - (pos + 2);
+                packet.set_u16(pos, size as u16)?;
+            }
+            DnsRecord::Unknown {
+                ref domain,
+                qtype,
+                class,
+                ref data,
+                ttl,
+            } => {
+                packet.write_qname(domain)?;
+                packet.write_u16(qtype)?;
+                packet.write_u16(class)?;
+                packet.write_u32(ttl)?;
+                packet.write_u16(data.len() as u16)?;
+
+                for b in data {
+                    packet.write(*b)?;
+                }
             }
         }
-        Ok(packet.pos - start_pos)
+        Ok(packet.pos() - start_pos)
+    }
+
+    /// the record in the canonical form signatures are computed over (RFC 4034 6.2):
+    /// names uncompressed and lowercased, and the TTL replaced by the `original_ttl`
+    /// the RRSIG covering it carries.
+    /// names are lowercased on reading as well, so the case of an NSEC next name
+    /// isnt kept the way RFC 6840 5.1 would have it
+    pub fn canonical_form(&self, original_ttl: u32) -> Result<Vec<u8>, DnsError> {
+        let mut record = self.clone();
+        record.set_ttl(original_ttl);
+
+        let mut buffer = VectorPacketBuffer::uncompressed();
+        record.write(&mut buffer)?;
+        Ok(buffer.as_bytes().to_vec())
+    }
+}
+
+/// a name as it appears in zone files, fully qualified with the trailing dot. bytes
+/// that arent printable ASCII are written as `\DDD`, the ones a zone file would take
+/// for something else get a backslash in front (RFC 1035 5.1)
+pub fn fqdn(name: &str) -> String {
+    let name = name.trim_end_matches('.');
+    let mut text = String::with_capacity(name.len() + 1);
+    for b in name.bytes() {
+        match b {
+            b'"' | b'(' | b')' | b';' | b'@' | b'$' | b'\\' => {
+                text.push('\\');
+                text.push(b as char);
+            }
+            0x21..=0x7E => text.push(b as char),
+            _ => text.push_str(&format!("\\{:03}", b)),
+        }
+    }
+    text.push('.');
+    text
+}
+
+/// the name to look up PTR records of `addr` under, the octets of an IPv4 address
+/// below in-addr.arpa (RFC 1035 3.5) or the nibbles of an IPv6 address below ip6.arpa
+/// (RFC 3596 2.5), both least significant first
+pub fn reverse_name(addr: IpAddr) -> String {
+    match addr {
+        IpAddr::V4(addr) => {
+            let [a, b, c, d] = addr.octets();
+            format!("{}.{}.{}.{}.in-addr.arpa", d, c, b, a)
+        }
+        IpAddr::V6(addr) => {
+            let mut name = String::with_capacity(72);
+            for byte in addr.octets().iter().rev() {
+                name.push_str(&format!("{:x}.{:x}.", byte & 0x0F, byte >> 4));
+            }
+            name.push_str("ip6.arpa");
+            name
+        }
+    }
+}
+
+/// whether SOA serial `a` is newer than `b`. serials wrap around, so a serial is newer
+/// when it is less than half the number space ahead (RFC 1982 3.2)
+pub fn serial_newer(a: u32, b: u32) -> bool {
+    a != b && a.wrapping_sub(b) < 1 << 31
+}
+
+/// whatever is left of the RDATA up to `end`, for the field that comes last
+fn read_rest<T: PacketBuffer>(packet: &mut T, end: usize) -> Result<Vec<u8>, DnsError> {
+    let len = end.saturating_sub(packet.pos());
+    let data = packet.get_range(packet.pos(), len)?.to_vec();
+    packet.step(len)?;
+    Ok(data)
+}
+
+/// NSEC3 salts are hex, an empty one is written as "-" (RFC 5155 3.3)
+fn salt_text(salt: &[u8]) -> String {
+    if salt.is_empty() {
+        "-".to_string()
+    } else {
+        hex_encode(salt)
+    }
+}
+
+/// CAA tags are made of ASCII letters and digits, at least one of them (RFC 8659 4.1)
+fn valid_caa_tag(tag: &[u8]) -> bool {
+    !tag.is_empty() && tag.iter().all(u8::is_ascii_alphanumeric)
+}
+
+/// writes `data` as a quoted character-string. quotes and backslashes are escaped
+/// with a backslash, anything unprintable as \DDD (RFC 1035 5.1)
+pub(crate) fn write_character_string(f: &mut fmt::Formatter<'_>, data: &[u8]) -> fmt::Result {
+    write!(f, "\"")?;
+    for &b in data {
+        match b {
+            b'"' | b'\\' => write!(f, "\\{}", b as char)?,
+            0x20..=0x7E => write!(f, "{}", b as char)?,
+            _ => write!(f, "\\{:03}", b)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+/// parses whitespace separated character-strings in presentation format, the way
+/// TXT records are written in zone files. strings may be quoted to hold whitespace
+/// and use the same escapes `Display` writes
+pub fn parse_character_strings(text: &str) -> Result<Vec<Vec<u8>>, DnsError> {
+    let bytes = text.as_bytes();
+    let mut strings = Vec::new();
+    let mut i = 0;
+    loop {
+        while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        if i == bytes.len() {
+            return Ok(strings);
+        }
+
+        let start = i;
+        let quoted = bytes[i] == b'"';
+        if quoted {
+            i += 1;
+        }
+        let mut string = Vec::new();
+        loop {
+            let Some(&b) = bytes.get(i) else {
+                if quoted {
+                    return Err(DnsError::BadText {
+                        offset: start,
+                        reason: "unterminated quoted string",
+                    });
+                }
+                break;
+            };
+            i += 1;
+            match b {
+                b'"' if quoted => break,
+                b'"' => {
+                    return Err(DnsError::BadText {
+                        offset: i - 1,
+                        reason: "quote inside an unquoted string",
+                    })
+                }
+                b'\\' => {
+                    let (value, len) = parse_escape(&bytes[i..]).ok_or(DnsError::BadText {
+                        offset: i - 1,
+                        reason: "invalid escape",
+                    })?;
+                    string.push(value);
+                    i += len;
+                }
+                _ if !quoted && b.is_ascii_whitespace() => break,
+                _ => string.push(b),
+            }
+        }
+
+        if string.len() > MAX_CHARACTER_STRING {
+            return Err(DnsError::BadText {
+                offset: start,
+                reason: "character-string longer than 255 bytes",
+            });
+        }
+        strings.push(string);
+    }
+}
+
+/// the byte an escape stands for and how many bytes after the backslash it took up,
+/// either \X for X itself or \DDD for a decimal value
+pub(crate) fn parse_escape(rest: &[u8]) -> Option<(u8, usize)> {
+    match rest {
+        [a, b, c, ..] if a.is_ascii_digit() && b.is_ascii_digit() && c.is_ascii_digit() => {
+            let value = (a - b'0') as u16 * 100 + (b - b'0') as u16 * 10 + (c - b'0') as u16;
+            u8::try_from(value).ok().map(|value| (value, 3))
+        }
+        [a, ..] if a.is_ascii_digit() => None,
+        [a, ..] => Some((*a, 1)),
+        [] => None,
+    }
+}
+
+/// the mnemonic of a class, `CLASS<n>` for the ones without one (RFC 3597 5)
+pub fn class_name(class: u16) -> String {
+    match class {
+        1 => "IN".to_string(),
+        3 => "CH".to_string(),
+        4 => "HS".to_string(),
+        _ => format!("CLASS{}", class),
+    }
+}
+
+/// presentation format, one record per line as found in zone files
+impl fmt::Display for DnsRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let class = match self {
+            DnsRecord::Unknown { class, .. } => *class,
+            _ => 1,
+        };
+        write!(
+            f,
+            "{}\t{}\t{}\t{}\t",
+            fqdn(self.domain()),
+            self.ttl(),
+            class_name(class),
+            self.qtype()
+        )?;
+
+        match self {
+            DnsRecord::A { addr, .. } => write!(f, "{}", addr),
+            DnsRecord::AAAA { addr, .. } => write!(f, "{}", addr),
+            DnsRecord::NS { host, .. }
+            | DnsRecord::CNAME { host, .. }
+            | DnsRecord::PTR { host, .. } => {
+                write!(f, "{}", fqdn(host))
+            }
+            DnsRecord::MX { priority, host, .. } => write!(f, "{} {}", priority, fqdn(host)),
+            DnsRecord::SOA {
+                mname,
+                rname,
+                serial,
+                refresh,
+                retry,
+                expire,
+                minimum,
+                ..
+            } => write!(
+                f,
+                "{} {} {} {} {} {} {}",
+                fqdn(mname),
+                fqdn(rname),
+                serial,
+                refresh,
+                retry,
+                expire,
+                minimum
+            ),
+            DnsRecord::TXT { data, .. } => {
+                for (i, string) in data.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    write_character_string(f, string)?;
+                }
+                Ok(())
+            }
+            DnsRecord::SRV {
+                priority,
+                weight,
+                port,
+                host,
+                ..
+            } => write!(f, "{} {} {} {}", priority, weight, port, fqdn(host)),
+            DnsRecord::CAA {
+                flags, tag, value, ..
+            } => {
+                write!(f, "{} {} ", flags, tag)?;
+                write_character_string(f, value)
+            }
+            DnsRecord::SVCB {
+                priority,
+                target,
+                params,
+                ..
+            } => {
+                write!(f, "{} {}", priority, fqdn(target))?;
+                for param in params {
+                    write!(f, " {}", param)?;
+                }
+                Ok(())
+            }
+            DnsRecord::HTTPS {
+                priority,
+                target,
+                params,
+                ..
+            } => {
+                write!(f, "{} {}", priority, fqdn(target))?;
+                for param in params {
+                    write!(f, " {}", param)?;
+                }
+                Ok(())
+            }
+            DnsRecord::DS {
+                key_tag,
+                algorithm,
+                digest_type,
+                digest,
+                ..
+            } => write!(
+                f,
+                "{} {} {} {}",
+                key_tag,
+                algorithm,
+                digest_type,
+                hex_encode(digest)
+            ),
+            DnsRecord::RRSIG {
+                type_covered,
+                algorithm,
+                labels,
+                original_ttl,
+                expiration,
+                inception,
+                key_tag,
+                signer,
+                signature,
+                ..
+            } => write!(
+                f,
+                "{} {} {} {} {} {} {} {} {}",
+                type_covered,
+                algorithm,
+                labels,
+                original_ttl,
+                format_timestamp(*expiration),
+                format_timestamp(*inception),
+                key_tag,
+                fqdn(signer),
+                base64_encode(signature)
+            ),
+            DnsRecord::NSEC { next, types, .. } => {
+                write!(f, "{}", fqdn(next))?;
+                for t in types {
+                    write!(f, " {}", t)?;
+                }
+                Ok(())
+            }
+            DnsRecord::DNSKEY {
+                flags,
+                protocol,
+                algorithm,
+                public_key,
+                ..
+            } => write!(
+                f,
+                "{} {} {} {}",
+                flags,
+                protocol,
+                algorithm,
+                base64_encode(public_key)
+            ),
+            DnsRecord::NSEC3 {
+                hash_algorithm,
+                flags,
+                iterations,
+                salt,
+                next_hashed,
+                types,
+                ..
+            } => {
+                write!(
+                    f,
+                    "{} {} {} {} {}",
+                    hash_algorithm,
+                    flags,
+                    iterations,
+                    salt_text(salt),
+                    base32hex_encode(next_hashed)
+                )?;
+                for t in types {
+                    write!(f, " {}", t)?;
+                }
+                Ok(())
+            }
+            DnsRecord::NSEC3PARAM {
+                hash_algorithm,
+                flags,
+                iterations,
+                salt,
+                ..
+            } => write!(
+                f,
+                "{} {} {} {}",
+                hash_algorithm,
+                flags,
+                iterations,
+                salt_text(salt)
+            ),
+            DnsRecord::DNAME { target, .. } => write!(f, "{}", fqdn(target)),
+            // the generic \# <length> <hex> form
+            DnsRecord::Unknown { data, .. } => {
+                write!(f, "\\# {}", data.len())?;
+                if !data.is_empty() {
+                    write!(f, " ")?;
+                    for b in data {
+                        write!(f, "{:02x}", b)?;
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `record` as written into a message of its own, names written in full
+    fn wire(record: &DnsRecord) -> Vec<u8> {
+        let mut buffer = VectorPacketBuffer::uncompressed();
+        record.write(&mut buffer).unwrap();
+        buffer.as_bytes().to_vec()
+    }
+
+    fn read(bytes: &[u8]) -> DnsRecord {
+        let mut buffer = VectorPacketBuffer::from_bytes(bytes);
+        let record = DnsRecord::read(&mut buffer).unwrap();
+        assert_eq!(buffer.pos(), bytes.len());
+        record
+    }
+
+    fn soa() -> DnsRecord {
+        DnsRecord::SOA {
+            domain: "example.com".to_string(),
+            mname: "ns1.example.com".to_string(),
+            rname: "hostmaster.example.com".to_string(),
+            serial: 2024010101,
+            refresh: 7200,
+            retry: 3600,
+            expire: 1209600,
+            minimum: 300,
+            ttl: 3600,
+        }
+    }
+
+    /// example.com. 3600 IN SOA ns1.example.com. hostmaster.example.com.
+    /// 2024010101 7200 3600 1209600 300
+    const SOA_WIRE: [u8; 84] = [
+        0x07, 0x65, 0x78, 0x61, 0x6d, 0x70, 0x6c, 0x65, 0x03, 0x63, 0x6f, 0x6d, 0x00, 0x00, 0x06,
+        0x00, 0x01, 0x00, 0x00, 0x0e, 0x10, 0x00, 0x3d, 0x03, 0x6e, 0x73, 0x31, 0x07, 0x65, 0x78,
+        0x61, 0x6d, 0x70, 0x6c, 0x65, 0x03, 0x63, 0x6f, 0x6d, 0x00, 0x0a, 0x68, 0x6f, 0x73, 0x74,
+        0x6d, 0x61, 0x73, 0x74, 0x65, 0x72, 0x07, 0x65, 0x78, 0x61, 0x6d, 0x70, 0x6c, 0x65, 0x03,
+        0x63, 0x6f, 0x6d, 0x00, 0x78, 0xa3, 0xf1, 0x75, 0x00, 0x00, 0x1c, 0x20, 0x00, 0x00, 0x0e,
+        0x10, 0x00, 0x12, 0x75, 0x00, 0x00, 0x00, 0x01, 0x2c,
+    ];
+
+    #[test]
+    fn unknown_types_round_trip() {
+        // example.com. 300 CLASS32 TYPE65280 \# 4 deadbeef
+        let bytes = [
+            0x07, 0x65, 0x78, 0x61, 0x6d, 0x70, 0x6c, 0x65, 0x03, 0x63, 0x6f, 0x6d, 0x00, 0xff,
+            0x00, 0x00, 0x20, 0x00, 0x00, 0x01, 0x2c, 0x00, 0x04, 0xde, 0xad, 0xbe, 0xef,
+        ];
+        let record = DnsRecord::Unknown {
+            domain: "example.com".to_string(),
+            qtype: 65280,
+            class: 32,
+            data: vec![0xde, 0xad, 0xbe, 0xef],
+            ttl: 300,
+        };
+        assert_eq!(read(&bytes), record);
+        assert_eq!(wire(&record), bytes);
+        assert_eq!(
+            record.to_string(),
+            "example.com.\t300\tCLASS32\tTYPE65280\t\\# 4 deadbeef"
+        );
+
+        let empty = DnsRecord::Unknown {
+            domain: "example.com".to_string(),
+            qtype: 65280,
+            class: 32,
+            data: Vec::new(),
+            ttl: 300,
+        };
+        assert_eq!(
+            empty.to_string(),
+            "example.com.\t300\tCLASS32\tTYPE65280\t\\# 0"
+        );
+    }
+
+    #[test]
+    fn known_types_must_fill_rdlength() {
+        // an MX record whose RDLENGTH claims one byte more than preference and exchange take
+        let mut bytes = wire(&DnsRecord::MX {
+            domain: "example.com".to_string(),
+            priority: 10,
+            host: "mail.example.com".to_string(),
+            ttl: 300,
+        });
+        let rdlength = bytes.len() - 13 - 10;
+        bytes[21..23].copy_from_slice(&(rdlength as u16 + 1).to_be_bytes());
+        bytes.push(0);
+
+        let err = DnsRecord::read(&mut VectorPacketBuffer::from_bytes(&bytes)).unwrap_err();
+        assert_eq!(
+            err,
+            DnsError::BadRdataLength {
+                offset: 23,
+                qtype: 15,
+                expected: rdlength + 1,
+                actual: rdlength,
+            }
+        );
+    }
+
+    #[test]
+    fn soa_round_trip() {
+        assert_eq!(read(&SOA_WIRE), soa());
+        assert_eq!(wire(&soa()), SOA_WIRE);
+
+        // the names in the RDATA may be compressed against the owner
+        let mut buffer = VectorPacketBuffer::new();
+        soa().write(&mut buffer).unwrap();
+        assert!(buffer.as_bytes().len() < SOA_WIRE.len());
+        assert_eq!(read(buffer.as_bytes()), soa());
+
+        assert_eq!(
+            soa().to_string(),
+            "example.com.\t3600\tIN\tSOA\tns1.example.com. hostmaster.example.com. \
+             2024010101 7200 3600 1209600 300"
+        );
+    }
+
+    /// example.com. 300 IN CAA with `rdata`
+    fn caa_wire(rdata: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![
+            0x07, 0x65, 0x78, 0x61, 0x6d, 0x70, 0x6c, 0x65, 0x03, 0x63, 0x6f, 0x6d, 0x00, 0x01,
+            0x01, 0x00, 0x01, 0x00, 0x00, 0x01, 0x2c,
+        ];
+        bytes.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+        bytes.extend_from_slice(rdata);
+        bytes
+    }
+
+    fn caa(flags: u8, tag: &str, value: &[u8]) -> DnsRecord {
+        DnsRecord::CAA {
+            domain: "example.com".to_string(),
+            flags,
+            tag: tag.to_string(),
+            value: value.to_vec(),
+            ttl: 300,
+        }
+    }
+
+    /// 0 issue "letsencrypt.org"
+    const CAA_ISSUE: [u8; 22] = [
+        0x00, 0x05, 0x69, 0x73, 0x73, 0x75, 0x65, 0x6c, 0x65, 0x74, 0x73, 0x65, 0x6e, 0x63, 0x72,
+        0x79, 0x70, 0x74, 0x2e, 0x6f, 0x72, 0x67,
+    ];
+    /// 0 iodef "mailto:security@example.com"
+    const CAA_IODEF: [u8; 34] = [
+        0x00, 0x05, 0x69, 0x6f, 0x64, 0x65, 0x66, 0x6d, 0x61, 0x69, 0x6c, 0x74, 0x6f, 0x3a, 0x73,
+        0x65, 0x63, 0x75, 0x72, 0x69, 0x74, 0x79, 0x40, 0x65, 0x78, 0x61, 0x6d, 0x70, 0x6c, 0x65,
+        0x2e, 0x63, 0x6f, 0x6d,
+    ];
+    /// 128 tbs "Unknown"
+    const CAA_CRITICAL: [u8; 12] = [
+        0x80, 0x03, 0x74, 0x62, 0x73, 0x55, 0x6e, 0x6b, 0x6e, 0x6f, 0x77, 0x6e,
+    ];
+    /// 0 issuewild "", nobody may issue wildcard certificates
+    const CAA_EMPTY: [u8; 11] = [
+        0x00, 0x09, 0x69, 0x73, 0x73, 0x75, 0x65, 0x77, 0x69, 0x6c, 0x64,
+    ];
+
+    #[test]
+    fn caa_round_trip() {
+        let cases = [
+            (&CAA_ISSUE[..], caa(0, "issue", b"letsencrypt.org")),
+            (
+                &CAA_IODEF[..],
+                caa(0, "iodef", b"mailto:security@example.com"),
+            ),
+            (&CAA_CRITICAL[..], caa(128, "tbs", b"Unknown")),
+            (&CAA_EMPTY[..], caa(0, "issuewild", b"")),
+        ];
+        for (rdata, record) in cases {
+            let bytes = caa_wire(rdata);
+            assert_eq!(read(&bytes), record);
+            assert_eq!(wire(&record), bytes);
+        }
+
+        assert_eq!(
+            caa(0, "issue", b"letsencrypt.org").to_string(),
+            "example.com.\t300\tIN\tCAA\t0 issue \"letsencrypt.org\""
+        );
+        assert_eq!(
+            caa(128, "tbs", b"Unknown").to_string(),
+            "example.com.\t300\tIN\tCAA\t128 tbs \"Unknown\""
+        );
+        assert_eq!(
+            caa(0, "issuewild", b"").to_string(),
+            "example.com.\t300\tIN\tCAA\t0 issuewild \"\""
+        );
+    }
+
+    #[test]
+    fn caa_tag_has_to_be_alphanumeric() {
+        let mut rdata = CAA_ISSUE;
+        rdata[2] = b'-';
+        let mut buffer = VectorPacketBuffer::from_bytes(&caa_wire(&rdata));
+        assert!(matches!(
+            DnsRecord::read(&mut buffer),
+            Err(DnsError::BadRdata { qtype: 257, .. })
+        ));
+        assert!(caa(0, "is-sue", b"").canonical_form(0).is_err());
+    }
+
+    /// example.com. 300 IN TXT with `data`
+    fn txt(data: &[&[u8]]) -> DnsRecord {
+        DnsRecord::TXT {
+            domain: "example.com".to_string(),
+            data: data.iter().map(|s| s.to_vec()).collect(),
+            ttl: 300,
+        }
+    }
+
+    /// the text of a TXT record after the type
+    fn txt_text(record: &DnsRecord) -> String {
+        record.to_string().split('\t').nth(4).unwrap().to_string()
+    }
+
+    #[test]
+    fn txt_round_trip() {
+        let record = txt(&[b"v=spf1", b"-all"]);
+        let bytes = wire(&record);
+        // RDLENGTH, then each string with its length in front
+        assert_eq!(&bytes[21..], b"\x00\x0c\x06v=spf1\x04-all");
+        assert_eq!(read(&bytes), record);
+        assert_eq!(txt_text(&record), "\"v=spf1\" \"-all\"");
+        assert_eq!(
+            parse_character_strings(&txt_text(&record)).unwrap(),
+            vec![b"v=spf1".to_vec(), b"-all".to_vec()]
+        );
+
+        // an empty string is still one string, a lone zero length byte
+        let empty = txt(&[b""]);
+        let bytes = wire(&empty);
+        assert_eq!(&bytes[21..], b"\x00\x01\x00");
+        assert_eq!(read(&bytes), empty);
+        assert_eq!(txt_text(&empty), "\"\"");
+        assert_eq!(parse_character_strings("\"\"").unwrap(), vec![Vec::new()]);
+    }
+
+    #[test]
+    fn txt_strings_fit_in_a_length_byte() {
+        let longest = vec![b'x'; 255];
+        let record = txt(&[&longest]);
+        assert_eq!(read(&wire(&record)), record);
+        let text = txt_text(&record);
+        assert_eq!(parse_character_strings(&text).unwrap(), vec![longest]);
+
+        let mut buffer = VectorPacketBuffer::new();
+        assert!(matches!(
+            txt(&[&[b'x'; 256]]).write(&mut buffer),
+            Err(DnsError::BadRdata { qtype: 16, .. })
+        ));
+        assert!(matches!(
+            parse_character_strings(&"x".repeat(256)),
+            Err(DnsError::BadText { offset: 0, .. })
+        ));
+        // the helper splits long text instead
+        let split = DnsRecord::txt("example.com", &[b'x'; 256], 300);
+        assert_eq!(split, txt(&[&[b'x'; 255], b"x"]));
+    }
+
+    #[test]
+    fn txt_escapes_round_trip() {
+        // not UTF-8, and quotes, backslashes and control bytes in the text
+        let record = txt(&[b"\xff\xfe", b"say \"hi\"\\", b"tab\there\x00"]);
+        assert_eq!(read(&wire(&record)), record);
+
+        let text = txt_text(&record);
+        assert_eq!(
+            text,
+            "\"\\255\\254\" \"say \\\"hi\\\"\\\\\" \"tab\\009here\\000\""
+        );
+        let DnsRecord::TXT { data, .. } = &record else {
+            unreachable!()
+        };
+        assert_eq!(&parse_character_strings(&text).unwrap(), data);
+
+        // unquoted strings take escapes too
+        assert_eq!(
+            parse_character_strings("a\\032b \\\"").unwrap(),
+            vec![b"a b".to_vec(), b"\"".to_vec()]
+        );
+    }
+
+    #[test]
+    fn reverse_names() {
+        assert_eq!(
+            reverse_name("192.0.2.1".parse().unwrap()),
+            "1.2.0.192.in-addr.arpa"
+        );
+        // the example of RFC 3596 2.5
+        assert_eq!(
+            reverse_name("4321:0:1:2:3:4:567:89ab".parse().unwrap()),
+            "b.a.9.8.7.6.5.0.4.0.0.0.3.0.0.0.2.0.0.0.1.0.0.0.0.0.0.0.1.2.3.4.ip6.arpa"
+        );
+        assert_eq!(
+            reverse_name("::".parse().unwrap()),
+            format!("{}ip6.arpa", "0.".repeat(32))
+        );
+    }
+
+    #[test]
+    fn ptr_round_trip() {
+        let ptr = DnsRecord::PTR {
+            domain: reverse_name("192.0.2.1".parse().unwrap()),
+            host: "www.example.com".to_string(),
+            ttl: 300,
+        };
+        let bytes = wire(&ptr);
+        // owner, type 12, class IN, TTL, RDLENGTH 17 and the host name in full
+        assert_eq!(
+            &bytes[..24],
+            b"\x011\x012\x010\x03192\x07in-addr\x04arpa\x00"
+        );
+        assert_eq!(&bytes[24..34], &[0, 12, 0, 1, 0, 0, 1, 44, 0, 17]);
+        assert_eq!(&bytes[34..], b"\x03www\x07example\x03com\x00");
+        assert_eq!(read(&bytes), ptr);
+
+        assert_eq!(
+            ptr.to_string(),
+            "1.2.0.192.in-addr.arpa.\t300\tIN\tPTR\twww.example.com."
+        );
+    }
+
+    #[test]
+    fn serial_arithmetic() {
+        assert!(serial_newer(2, 1));
+        assert!(!serial_newer(1, 2));
+        // equal serials are not newer either way
+        assert!(!serial_newer(7, 7));
+        assert!(!serial_newer(0, 0));
+
+        // serials wrap around
+        assert!(serial_newer(0, u32::MAX));
+        assert!(serial_newer(5, u32::MAX - 5));
+        assert!(!serial_newer(u32::MAX, 0));
+
+        // up to just under half the number space ahead is newer
+        assert!(serial_newer((1 << 31) - 1, 0));
+        assert!(!serial_newer(0, (1 << 31) - 1));
+        assert!(serial_newer(10 + (1 << 31) - 1, 10));
+
+        // exactly half way round the comparison is undefined (RFC 1982 3.2),
+        // we call neither one newer
+        assert!(!serial_newer(1 << 31, 0));
+        assert!(!serial_newer(0, 1 << 31));
+        assert!(!serial_newer(u32::MAX, (1 << 31) - 1));
+        assert!(!serial_newer((1 << 31) - 1, u32::MAX));
     }
 }
@@ -0,0 +1,1278 @@
+use std::{
+    collections::HashSet,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use dns::{
+    client::{self, random_id},
+    dnsmsg::{is_subdomain, parent, substitute_dname, DnsPackets},
+    dnssec::{self, Security},
+    edns::{Edns, DEFAULT_UDP_PAYLOAD},
+    encoding::hex_decode,
+    header::ResultCode,
+    question::{DnsQuestion, QueryType},
+    record::DnsRecord,
+};
+
+use crate::cache::{Cache, CacheKey, Trust};
+
+/// The root name servers as published by IANA (named.root)
+/// name, IPv4 address, IPv6 address
+pub const ROOT_HINTS: [(&str, Ipv4Addr, Ipv6Addr); 13] = [
+    (
+        "a.root-servers.net",
+        Ipv4Addr::new(198, 41, 0, 4),
+        Ipv6Addr::new(0x2001, 0x503, 0xba3e, 0, 0, 0, 0x2, 0x30),
+    ),
+    (
+        "b.root-servers.net",
+        Ipv4Addr::new(170, 247, 170, 2),
+        Ipv6Addr::new(0x2801, 0x1b8, 0x10, 0, 0, 0, 0, 0xb),
+    ),
+    (
+        "c.root-servers.net",
+        Ipv4Addr::new(192, 33, 4, 12),
+        Ipv6Addr::new(0x2001, 0x500, 0x2, 0, 0, 0, 0, 0xc),
+    ),
+    (
+        "d.root-servers.net",
+        Ipv4Addr::new(199, 7, 91, 13),
+        Ipv6Addr::new(0x2001, 0x500, 0x2d, 0, 0, 0, 0, 0xd),
+    ),
+    (
+        "e.root-servers.net",
+        Ipv4Addr::new(192, 203, 230, 10),
+        Ipv6Addr::new(0x2001, 0x500, 0xa8, 0, 0, 0, 0, 0xe),
+    ),
+    (
+        "f.root-servers.net",
+        Ipv4Addr::new(192, 5, 5, 241),
+        Ipv6Addr::new(0x2001, 0x500, 0x2f, 0, 0, 0, 0, 0xf),
+    ),
+    (
+        "g.root-servers.net",
+        Ipv4Addr::new(192, 112, 36, 4),
+        Ipv6Addr::new(0x2001, 0x500, 0x12, 0, 0, 0, 0, 0xd0d),
+    ),
+    (
+        "h.root-servers.net",
+        Ipv4Addr::new(198, 97, 190, 53),
+        Ipv6Addr::new(0x2001, 0x500, 0x1, 0, 0, 0, 0, 0x53),
+    ),
+    (
+        "i.root-servers.net",
+        Ipv4Addr::new(192, 36, 148, 17),
+        Ipv6Addr::new(0x2001, 0x7fe, 0, 0, 0, 0, 0, 0x53),
+    ),
+    (
+        "j.root-servers.net",
+        Ipv4Addr::new(192, 58, 128, 30),
+        Ipv6Addr::new(0x2001, 0x503, 0xc27, 0, 0, 0, 0x2, 0x30),
+    ),
+    (
+        "k.root-servers.net",
+        Ipv4Addr::new(193, 0, 14, 129),
+        Ipv6Addr::new(0x2001, 0x7fd, 0, 0, 0, 0, 0, 0x1),
+    ),
+    (
+        "l.root-servers.net",
+        Ipv4Addr::new(199, 7, 83, 42),
+        Ipv6Addr::new(0x2001, 0x500, 0x9f, 0, 0, 0, 0, 0x42),
+    ),
+    (
+        "m.root-servers.net",
+        Ipv4Addr::new(202, 12, 27, 33),
+        Ipv6Addr::new(0x2001, 0xdc3, 0, 0, 0, 0, 0, 0x35),
+    ),
+];
+
+/// DS records of the root key signing keys as published by IANA (root-anchors.xml):
+/// key tag, algorithm, digest type, digest
+pub const ROOT_TRUST_ANCHORS: [(u16, u8, u8, &str); 2] = [
+    (
+        20326,
+        8,
+        2,
+        "E06D44B80B8F1D39A95C0B0D7C65D08458E880409BBC683457104237C7F8EC8D",
+    ),
+    (
+        38696,
+        8,
+        2,
+        "683D2D0ACB8C9B712A1948B27F741219298D0A450D612C483AF444A4C0FB2B16",
+    ),
+];
+
+/// how many referrals we follow for a single name before giving up
+const MAX_REFERRALS: usize = 16;
+/// how deep we go when resolving name server names that came without glue
+const MAX_DEPTH: usize = 6;
+/// number of record sets kept in the cache unless configured otherwise
+const DEFAULT_CACHE_SIZE: usize = 10_000;
+/// how many CNAMEs we chase into other zones unless configured otherwise
+const DEFAULT_CNAME_CHAIN: usize = 8;
+/// we only ever ask for class IN
+const CLASS_IN: u16 = 1;
+
+/// An iterative resolver.
+/// Starts at the root servers and walks down the delegation chain by following
+/// the NS referrals in the authority section of every response.
+/// Everything learned along the way is kept in a cache shared by all queries.
+/// Answers are validated with DNSSEC as long as there is a trust anchor for the root.
+#[derive(Debug)]
+pub struct Resolver {
+    root_servers: Vec<SocketAddr>,
+    port: u16,
+    timeout: Duration,
+    cache: Mutex<Cache>,
+    /// DS records for the root zone
+    trust_anchors: Vec<DnsRecord>,
+    max_cname_chain: usize,
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Resolver {
+    /// resolver using the built-in root hints
+    pub fn new() -> Self {
+        // IPv4 first, plenty of hosts dont have IPv6 connectivity
+        let root_servers = ROOT_HINTS
+            .iter()
+            .map(|(_, v4, _)| SocketAddr::new(IpAddr::V4(*v4), 53))
+            .chain(
+                ROOT_HINTS
+                    .iter()
+                    .map(|(_, _, v6)| SocketAddr::new(IpAddr::V6(*v6), 53)),
+            )
+            .collect();
+
+        let mut resolver = Self::with_root_hints(root_servers, 53);
+        resolver.set_trust_anchors(
+            ROOT_TRUST_ANCHORS
+                .iter()
+                .map(|(key_tag, algorithm, digest_type, digest)| DnsRecord::DS {
+                    domain: String::new(),
+                    key_tag: *key_tag,
+                    algorithm: *algorithm,
+                    digest_type: *digest_type,
+                    digest: hex_decode(digest).unwrap_or_default(),
+                    ttl: 0,
+                })
+                .collect(),
+        );
+        resolver
+    }
+
+    /// resolver starting from custom root servers.
+    /// `port` is used to reach every name server learned through referrals
+    /// which lets us run the whole hierarchy on loopback.
+    /// there is no trust anchor for custom roots, see [`Resolver::set_trust_anchors`]
+    pub fn with_root_hints(root_servers: Vec<SocketAddr>, port: u16) -> Self {
+        Self {
+            root_servers,
+            port,
+            timeout: client::DEFAULT_TIMEOUT,
+            cache: Mutex::new(Cache::new(DEFAULT_CACHE_SIZE)),
+            trust_anchors: Vec::new(),
+            max_cname_chain: DEFAULT_CNAME_CHAIN,
+        }
+    }
+
+    /// the DS records the chain of trust starts from, they have to belong to the root.
+    /// without any, answers arent validated at all
+    pub fn set_trust_anchors(&mut self, anchors: Vec<DnsRecord>) {
+        self.trust_anchors = anchors;
+    }
+
+    /// how long we wait for a single upstream server
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// how many CNAMEs we follow through the cache, and how many pointing to names
+    /// the answer doesnt cover we look up, before giving up on a chain
+    pub fn set_max_cname_chain(&mut self, max_cname_chain: usize) {
+        self.max_cname_chain = max_cname_chain;
+    }
+
+    /// maximum number of record sets kept in the cache
+    pub fn set_cache_size(&mut self, max_entries: usize) {
+        self.cache_mut().set_max_entries(max_entries);
+    }
+
+    /// drops the cached records that outlived their TTL
+    pub fn purge_cache(&self) {
+        self.cache_mut().purge_expired();
+    }
+
+    fn cache_mut(&self) -> std::sync::MutexGuard<'_, Cache> {
+        // a panic while holding the lock cant leave the cache in a state worse than stale
+        self.cache
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// resolves `qname` starting from the root. secure answers come back with the AD bit
+    /// set, bogus ones as an error (RFC 4035 4.3)
+    pub fn resolve(
+        &self,
+        qname: &str,
+        qtype: QueryType,
+    ) -> Result<DnsPackets, Box<dyn std::error::Error>> {
+        let mut response = self.resolve_unvalidated(qname, qtype)?;
+        if self.trust_anchors.is_empty() {
+            return Ok(response);
+        }
+
+        match self.validate(&response, qname, qtype, unix_time()) {
+            Security::Secure => response.header.authed_data = true,
+            Security::Insecure => {}
+            Security::Bogus(reason) => {
+                return Err(format!("{} {} is bogus: {}", qname, qtype, reason).into())
+            }
+        }
+        Ok(response)
+    }
+
+    /// resolves `qname` without checking any signatures,
+    /// for clients that set CD and validate for themselves
+    pub fn resolve_unvalidated(
+        &self,
+        qname: &str,
+        qtype: QueryType,
+    ) -> Result<DnsPackets, Box<dyn std::error::Error>> {
+        let mut response = self.resolve_chain(qname, qtype)?;
+        response.header.authed_data = false;
+        Ok(response)
+    }
+
+    /// resolves `qname` and follows the CNAME chain of the answer into whatever zones
+    /// it leads to. the whole chain goes into the answer section ahead of the final
+    /// records, the rest of the response is about the name at its end (RFC 1034 3.6.2)
+    fn resolve_chain(
+        &self,
+        qname: &str,
+        qtype: QueryType,
+    ) -> Result<DnsPackets, Box<dyn std::error::Error>> {
+        let mut response = self.resolve_with_depth(qname, qtype, 0)?;
+        if qtype == QueryType::CNAME {
+            return Ok(response);
+        }
+
+        let mut name = qname.trim_end_matches('.').to_lowercase();
+        // the name the last query went out for
+        let mut asked = name.clone();
+        let mut seen = HashSet::from([name.clone()]);
+        let mut chased = 0;
+        loop {
+            let answered = response
+                .answers
+                .iter()
+                .any(|rec| rec.domain() == name && rec.qtype() == qtype);
+            if answered {
+                return Ok(response);
+            }
+
+            // the part of the chain the answer already covers
+            let target = response.answers.iter().find_map(|rec| match rec {
+                DnsRecord::CNAME { domain, host, .. } if *domain == name => Some(host.clone()),
+                _ => None,
+            });
+            if let Some(target) = target {
+                if !seen.insert(target.clone()) {
+                    return Err(format!("CNAME loop at {}", target).into());
+                }
+                name = target;
+                continue;
+            }
+
+            // a DNAME above the name, without the CNAME it stands for (RFC 6672 3.4)
+            let dname = response.answers.iter().find_map(|rec| match rec {
+                DnsRecord::DNAME {
+                    domain,
+                    target,
+                    ttl,
+                } if *domain != name && is_subdomain(&name, domain) => {
+                    Some((substitute_dname(&name, domain, target), *ttl))
+                }
+                _ => None,
+            });
+            match dname {
+                Some((Some(host), ttl)) => {
+                    response.answers.push(DnsRecord::CNAME {
+                        domain: name.clone(),
+                        host,
+                        ttl,
+                    });
+                    continue;
+                }
+                Some((None, _)) => {
+                    response.header.rescode = ResultCode::YXDomain;
+                    return Ok(response);
+                }
+                None => {}
+            }
+
+            // we already asked for the end of the chain, or it doesnt exist
+            if name == asked || response.header.rescode != ResultCode::NoError {
+                return Ok(response);
+            }
+            chased += 1;
+            if chased > self.max_cname_chain {
+                return Err(format!("CNAME chain of {} too long", qname).into());
+            }
+
+            let next = self.resolve_with_depth(&name, qtype, 0)?;
+            asked = name.clone();
+            response.header.rescode = next.header.rescode;
+            response.answers.extend(next.answers);
+            response.authoritiees = next.authoritiees;
+            response.resources = next.resources;
+        }
+    }
+
+    fn resolve_with_depth(
+        &self,
+        qname: &str,
+        qtype: QueryType,
+        depth: usize,
+    ) -> Result<DnsPackets, Box<dyn std::error::Error>> {
+        if depth > MAX_DEPTH {
+            return Err("Name server resolution nested too deep".into());
+        }
+
+        let cached = self
+            .cache_mut()
+            .get_answer(qname, qtype, CLASS_IN, self.max_cname_chain);
+        if let Some(cached) = cached {
+            let mut response = DnsPackets::new();
+            response.header.response = true;
+            response.header.rescode = cached.rescode;
+            response
+                .questions
+                .push(DnsQuestion::new(qname.to_string(), qtype));
+            response.answers = cached.answers;
+            response.authoritiees = cached.authorities;
+            return Ok(response);
+        }
+
+        // the zone the current servers are authoritative for. a DS lives on the
+        // parent side of the zone cut, the child's servers dont have it (RFC 4035 4.2)
+        let (mut zone, mut servers) = if qtype == QueryType::DS {
+            self.closest_servers(parent(qname))
+        } else {
+            self.closest_servers(qname)
+        };
+
+        for _ in 0..MAX_REFERRALS {
+            let response = self.query_any(qname, qtype, &servers)?;
+            self.cache_response(&response, &zone);
+            self.cache_negative(&response, qname, qtype, &zone);
+
+            // we got our answer, or the name doesnt exist at all
+            if (!response.answers.is_empty() && response.header.rescode == ResultCode::NoError)
+                || response.header.rescode == ResultCode::NXDomain
+            {
+                return Ok(response);
+            }
+
+            // only follow referrals that move us further down the tree,
+            // anything else is a lame delegation or the final (empty) answer
+            let next_zone = match response.get_referral(qname) {
+                Some(next) if next != zone && is_subdomain(next, &zone) => next.to_string(),
+                _ => return Ok(response),
+            };
+
+            // glue records in the additional section save us a lookup
+            let glue = response.get_resolved_ns(&next_zone);
+            if !glue.is_empty() {
+                servers = self.to_servers(glue);
+                zone = next_zone;
+                continue;
+            }
+
+            // no glue, we have to look up the name servers ourselves
+            let mut resolved = Vec::new();
+            for host in response.get_unresolved_ns(&next_zone) {
+                if let Ok(ns_response) = self.resolve_with_depth(host, QueryType::A, depth + 1) {
+                    resolved = ns_response.get_addrs(host);
+                }
+                if !resolved.is_empty() {
+                    break;
+                }
+            }
+            if resolved.is_empty() {
+                return Ok(response);
+            }
+            servers = self.to_servers(resolved);
+            zone = next_zone;
+        }
+
+        Err("Too many referrals".into())
+    }
+
+    /// the deepest zone above `qname` whose name servers we have cached addresses for,
+    /// falling back to the root
+    fn closest_servers(&self, qname: &str) -> (String, Vec<SocketAddr>) {
+        let mut cache = self.cache_mut();
+        let mut name = qname.trim_end_matches('.');
+        loop {
+            if let Some(ns_records) = cache.get(&CacheKey::new(name, QueryType::NS, CLASS_IN)) {
+                let mut addrs = Vec::new();
+                for rec in ns_records {
+                    if let DnsRecord::NS { host, .. } = rec {
+                        for qtype in [QueryType::A, QueryType::AAAA] {
+                            let glue = cache.get(&CacheKey::new(&host, qtype, CLASS_IN));
+                            addrs.extend(glue.into_iter().flatten().filter_map(
+                                |glue| match glue {
+                                    DnsRecord::A { addr, .. } => Some(IpAddr::V4(addr)),
+                                    DnsRecord::AAAA { addr, .. } => Some(IpAddr::V6(addr)),
+                                    _ => None,
+                                },
+                            ));
+                        }
+                    }
+                }
+                if !addrs.is_empty() {
+                    addrs.sort_by_key(|addr| addr.is_ipv6());
+                    return (name.to_string(), self.to_servers(addrs));
+                }
+            }
+            if name.is_empty() {
+                break;
+            }
+            name = parent(name);
+        }
+
+        (String::new(), self.root_servers.clone())
+    }
+
+    /// remembers every record in the response the servers of `zone` are allowed to tell us about,
+    /// ranked by the section it came in so glue never replaces an answer
+    fn cache_response(&self, response: &DnsPackets, zone: &str) {
+        let mut cache = self.cache_mut();
+        for (section, trust) in [
+            (&response.answers, Trust::Answer),
+            (&response.authoritiees, Trust::Authority),
+            (&response.resources, Trust::Additional),
+        ] {
+            let in_bailiwick: Vec<DnsRecord> = section
+                .iter()
+                .filter(|rec| is_subdomain(rec.domain(), zone))
+                .filter(|rec| !matches!(rec, DnsRecord::Unknown { .. }))
+                .cloned()
+                .collect();
+            cache.insert_records(&in_bailiwick, CLASS_IN, trust);
+        }
+    }
+
+    /// remembers NXDOMAIN and NODATA answers, provided they come with the zone's SOA
+    fn cache_negative(&self, response: &DnsPackets, qname: &str, qtype: QueryType, zone: &str) {
+        // a CNAME chain in the answer moves the question to its target
+        let name = if qtype == QueryType::CNAME {
+            qname
+        } else {
+            response.resolve_cname(qname)
+        };
+        let nxdomain = match response.header.rescode {
+            ResultCode::NXDomain => true,
+            ResultCode::NoError => {
+                let answered = response
+                    .answers
+                    .iter()
+                    .any(|rec| rec.qtype() == qtype && rec.domain().eq_ignore_ascii_case(name));
+                if answered {
+                    return;
+                }
+                false
+            }
+            _ => return,
+        };
+
+        let soa = response.authoritiees.iter().find(|rec| {
+            matches!(rec, DnsRecord::SOA { .. })
+                && is_subdomain(rec.domain(), zone)
+                && is_subdomain(name, rec.domain())
+        });
+        // the NSEC records and signatures go along, they are what proves the answer
+        let proof = response
+            .authoritiees
+            .iter()
+            .filter(|rec| {
+                matches!(
+                    rec,
+                    DnsRecord::NSEC { .. } | DnsRecord::NSEC3 { .. } | DnsRecord::RRSIG { .. }
+                ) && is_subdomain(rec.domain(), zone)
+            })
+            .cloned()
+            .collect();
+        if let Some(soa) = soa {
+            self.cache_mut()
+                .insert_negative(name, qtype, CLASS_IN, nxdomain, soa.clone(), proof);
+        }
+    }
+
+    fn to_servers(&self, addrs: Vec<IpAddr>) -> Vec<SocketAddr> {
+        addrs
+            .into_iter()
+            .map(|ip| SocketAddr::new(ip, self.port))
+            .collect()
+    }
+
+    /// asks the servers one after another until one of them responds
+    fn query_any(
+        &self,
+        qname: &str,
+        qtype: QueryType,
+        servers: &[SocketAddr],
+    ) -> Result<DnsPackets, Box<dyn std::error::Error>> {
+        let mut last_err: Box<dyn std::error::Error> = "No servers to query".into();
+        for server in servers {
+            match self.lookup(qname, qtype, *server) {
+                Ok(response) => match response.header.rescode {
+                    // try the next one if this server is broken or refuses to talk to us
+                    ResultCode::ServFail | ResultCode::Refused | ResultCode::NOTimP => {
+                        last_err =
+                            format!("{} answered {:?}", server, response.header.rescode).into();
+                    }
+                    _ => return Ok(response),
+                },
+                Err(e) => last_err = e,
+            }
+        }
+        Err(last_err)
+    }
+
+    /// sends a single non-recursive query to `server`
+    pub fn lookup(
+        &self,
+        qname: &str,
+        qtype: QueryType,
+        server: SocketAddr,
+    ) -> Result<DnsPackets, Box<dyn std::error::Error>> {
+        let mut edns = Edns::new(DEFAULT_UDP_PAYLOAD);
+        edns.dnssec_ok = true;
+        let mut query = DnsPackets::query(random_id(), qname, qtype).with_edns(edns);
+        query.header.recursion_desired = false;
+        client::exchange(&query, server, self.timeout)
+    }
+
+    /// how far `response` to `qname` can be trusted. every RRset of the answer is checked on
+    /// its own since a CNAME chain can cross zones, and a negative answer has to come with
+    /// the proof of what is missing
+    fn validate(&self, response: &DnsPackets, qname: &str, qtype: QueryType, now: u32) -> Security {
+        let mut security = Security::Secure;
+        for (rrset, rrsigs) in dnssec::rrsets(&response.answers) {
+            // a CNAME synthesized from a DNAME is unsigned, it is as good as the DNAME
+            // it was made from (RFC 6672 5.3.1)
+            if rrsigs.is_empty() && synthesized_from_dname(&rrset, &response.answers) {
+                continue;
+            }
+            let rrset_security = self.validate_rrset(&rrset, &rrsigs, &response.authoritiees, now);
+            security = security.and(rrset_security);
+        }
+
+        let name = if qtype == QueryType::CNAME {
+            qname
+        } else {
+            response.resolve_cname(qname)
+        };
+        let answered = response.answers_for(name, qtype).next().is_some();
+        match response.header.rescode {
+            ResultCode::NXDomain => {
+                security.and(self.validate_negative(response, name, qtype, now))
+            }
+            ResultCode::NoError if !answered => {
+                security.and(self.validate_negative(response, name, qtype, now))
+            }
+            _ => security,
+        }
+    }
+
+    /// checks the signatures over an RRset against the keys of the zone that signed it.
+    /// without signatures the RRset is only fine if its zone is provably unsigned
+    fn validate_rrset(
+        &self,
+        rrset: &[DnsRecord],
+        rrsigs: &[DnsRecord],
+        authority: &[DnsRecord],
+        now: u32,
+    ) -> Security {
+        let owner = rrset[0].domain();
+        let is_ds = rrset[0].qtype() == QueryType::DS;
+        // a DS is signed by the parent, anything else may be signed by the zone at its owner
+        let signer = rrsigs.iter().find_map(|rrsig| match rrsig {
+            DnsRecord::RRSIG { signer, .. }
+                if is_subdomain(owner, signer)
+                    && !(is_ds && signer.eq_ignore_ascii_case(owner)) =>
+            {
+                Some(signer.as_str())
+            }
+            _ => None,
+        });
+
+        let Some(signer) = signer else {
+            let zone = self.zone_of(if is_ds { parent(owner) } else { owner });
+            return match zone.map(|zone| self.zone_keys(&zone, now)) {
+                Some(ZoneKeys::Insecure) => Security::Insecure,
+                Some(ZoneKeys::Bogus(reason)) => Security::Bogus(reason),
+                Some(ZoneKeys::Secure(_)) => {
+                    Security::Bogus("RRset in a signed zone is not signed")
+                }
+                None => Security::Bogus("could not find the zone of an unsigned RRset"),
+            };
+        };
+
+        match self.zone_keys(signer, now) {
+            ZoneKeys::Secure(keys) => match dnssec::verify_rrset(rrset, rrsigs, &keys, signer, now)
+            {
+                Ok(labels) if (labels as usize) < dnssec::label_count(owner) => {
+                    dnssec::verify_wildcard(authority, owner, labels)
+                }
+                Ok(_) => Security::Secure,
+                Err(reason) => Security::Bogus(reason),
+            },
+            ZoneKeys::Insecure => Security::Insecure,
+            ZoneKeys::Bogus(reason) => Security::Bogus(reason),
+        }
+    }
+
+    /// checks an NXDOMAIN or NODATA answer for `name`: the SOA and the NSEC or NSEC3
+    /// records in the authority section have to be signed by the zone and prove the denial
+    fn validate_negative(
+        &self,
+        response: &DnsPackets,
+        name: &str,
+        qtype: QueryType,
+        now: u32,
+    ) -> Security {
+        let soa = response
+            .authoritiees
+            .iter()
+            .find(|rec| matches!(rec, DnsRecord::SOA { .. }) && is_subdomain(name, rec.domain()));
+        let zone = match soa {
+            Some(soa) => soa.domain().to_string(),
+            None => match self.zone_of(if qtype == QueryType::DS {
+                parent(name)
+            } else {
+                name
+            }) {
+                Some(zone) => zone,
+                None => return Security::Bogus("could not find the zone of a negative answer"),
+            },
+        };
+        if qtype == QueryType::DS && zone.eq_ignore_ascii_case(name) && !name.is_empty() {
+            return Security::Bogus("DS denial from the child zone");
+        }
+
+        let keys = match self.zone_keys(&zone, now) {
+            ZoneKeys::Secure(keys) => keys,
+            ZoneKeys::Insecure => return Security::Insecure,
+            ZoneKeys::Bogus(reason) => return Security::Bogus(reason),
+        };
+        if soa.is_none() {
+            return Security::Bogus("negative answer without SOA");
+        }
+
+        // only what the zone signed counts towards the proof
+        let mut proof = Vec::new();
+        for (rrset, rrsigs) in dnssec::rrsets(&response.authoritiees) {
+            if !matches!(
+                rrset[0].qtype(),
+                QueryType::SOA | QueryType::NSEC | QueryType::NSEC3
+            ) {
+                continue;
+            }
+            if let Err(reason) = dnssec::verify_rrset(&rrset, &rrsigs, &keys, &zone, now) {
+                return Security::Bogus(reason);
+            }
+            proof.extend(rrset);
+        }
+        let nxdomain = response.header.rescode == ResultCode::NXDomain;
+        dnssec::verify_denial(&proof, name, qtype, nxdomain)
+    }
+
+    /// the keys of `zone` if they can be trusted: the root's have to match a trust anchor,
+    /// everyone else's a DS the parent signed. a zone whose parent proves there is no DS
+    /// is unsigned (RFC 4035 5.2)
+    fn zone_keys(&self, zone: &str, now: u32) -> ZoneKeys {
+        let ds_set = if zone.is_empty() {
+            self.trust_anchors.clone()
+        } else {
+            let Ok(response) = self.resolve_with_depth(zone, QueryType::DS, 0) else {
+                return ZoneKeys::Bogus("could not look up the DS records");
+            };
+            let ds_set: Vec<DnsRecord> =
+                response.answers_for(zone, QueryType::DS).cloned().collect();
+            if ds_set.is_empty() {
+                if response.header.rescode != ResultCode::NoError {
+                    return ZoneKeys::Bogus("signer zone does not exist");
+                }
+                return match self.validate_negative(&response, zone, QueryType::DS, now) {
+                    Security::Bogus(reason) => ZoneKeys::Bogus(reason),
+                    // a signer that isnt a zone cut cant get out of signing that way
+                    Security::Secure if !dnssec::is_delegation(&response.authoritiees, zone) => {
+                        ZoneKeys::Bogus("signer is not a zone")
+                    }
+                    _ => ZoneKeys::Insecure,
+                };
+            }
+
+            let rrsigs: Vec<DnsRecord> = response
+                .answers_for(zone, QueryType::RRSIG)
+                .cloned()
+                .collect();
+            match self.validate_rrset(&ds_set, &rrsigs, &response.authoritiees, now) {
+                Security::Secure => ds_set,
+                Security::Insecure => return ZoneKeys::Insecure,
+                Security::Bogus(reason) => return ZoneKeys::Bogus(reason),
+            }
+        };
+
+        let ds_set: Vec<DnsRecord> = ds_set.into_iter().filter(dnssec::ds_supported).collect();
+        if ds_set.is_empty() {
+            return ZoneKeys::Insecure;
+        }
+
+        let Ok(response) = self.resolve_with_depth(zone, QueryType::DNSKEY, 0) else {
+            return ZoneKeys::Bogus("could not look up the DNSKEY records");
+        };
+        let keys: Vec<DnsRecord> = response
+            .answers_for(zone, QueryType::DNSKEY)
+            .cloned()
+            .collect();
+        let rrsigs: Vec<DnsRecord> = response
+            .answers_for(zone, QueryType::RRSIG)
+            .cloned()
+            .collect();
+        // the key set has to be signed by one of the keys the DS records point at
+        let trusted: Vec<DnsRecord> = keys
+            .iter()
+            .filter(|key| ds_set.iter().any(|ds| dnssec::ds_matches(ds, key)))
+            .cloned()
+            .collect();
+        if trusted.is_empty() {
+            return ZoneKeys::Bogus("no DNSKEY matches the DS records");
+        }
+        match dnssec::verify_rrset(&keys, &rrsigs, &trusted, zone, now) {
+            Ok(_) => ZoneKeys::Secure(keys),
+            Err(reason) => ZoneKeys::Bogus(reason),
+        }
+    }
+
+    /// the zone `name` belongs to, known by the SOA that comes back when asking for it.
+    /// a name that only has a CNAME gets the SOA of the target, so we go up until one fits
+    fn zone_of(&self, name: &str) -> Option<String> {
+        let mut name = name;
+        while !name.is_empty() {
+            let response = self.resolve_with_depth(name, QueryType::SOA, 0).ok()?;
+            let soa = response
+                .answers
+                .iter()
+                .chain(&response.authoritiees)
+                .find(|rec| {
+                    matches!(rec, DnsRecord::SOA { .. }) && is_subdomain(name, rec.domain())
+                });
+            if let Some(soa) = soa {
+                return Some(soa.domain().to_string());
+            }
+            name = parent(name);
+        }
+        Some(String::new())
+    }
+}
+
+/// what we know about the keys of a zone
+enum ZoneKeys {
+    /// the DNSKEY set, validated
+    Secure(Vec<DnsRecord>),
+    Insecure,
+    Bogus(&'static str),
+}
+
+/// whether `rrset` is a CNAME that a DNAME in `answers` stands for
+fn synthesized_from_dname(rrset: &[DnsRecord], answers: &[DnsRecord]) -> bool {
+    let [DnsRecord::CNAME { domain, host, .. }] = rrset else {
+        return false;
+    };
+    answers.iter().any(|rec| match rec {
+        DnsRecord::DNAME {
+            domain: owner,
+            target,
+            ..
+        } => {
+            domain != owner
+                && is_subdomain(domain, owner)
+                && substitute_dname(domain, owner, target).as_ref() == Some(host)
+        }
+        _ => false,
+    })
+}
+
+/// seconds since 1970, what RRSIG validity periods are counted in
+fn unix_time() -> u32 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs() as u32)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::UdpSocket,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        thread,
+    };
+
+    use dns::{edns::Edns, packet::VectorPacketBuffer};
+    use ring::{
+        rand::SystemRandom,
+        signature::{Ed25519KeyPair, KeyPair},
+    };
+
+    use super::*;
+    use crate::server::{build_response, Server};
+
+    /// a name server on loopback answering every query with whatever `answer` makes of it,
+    /// along with the number of queries it got
+    fn stand_in<F>(answer: F) -> (SocketAddr, Arc<AtomicUsize>)
+    where
+        F: Fn(&DnsPackets, usize) -> DnsPackets + Send + 'static,
+    {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap();
+        let queries = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&queries);
+        thread::spawn(move || loop {
+            let mut raw = [0u8; 4096];
+            let Ok((len, source)) = socket.recv_from(&mut raw) else {
+                return;
+            };
+            let mut buffer = VectorPacketBuffer::from_bytes(&raw[..len]);
+            let request = DnsPackets::from_buffer(&mut buffer).unwrap();
+            let seen = counter.fetch_add(1, Ordering::SeqCst);
+            let mut response = answer(&request, seen);
+            let buffer = response.write_with_limit(4096).unwrap();
+            socket.send_to(buffer.as_bytes(), source).unwrap();
+        });
+        (addr, queries)
+    }
+
+    fn a(domain: &str, addr: [u8; 4]) -> DnsRecord {
+        DnsRecord::A {
+            domain: domain.to_string(),
+            addr: addr.into(),
+            ttl: 300,
+        }
+    }
+
+    #[test]
+    fn follows_a_referral_to_the_answer() {
+        // the root refers us to example.com, whose server is the same stand-in
+        let (addr, queries) = stand_in(|request, seen| {
+            assert!(!request.header.recursion_desired);
+            let question = &request.questions[0];
+            assert_eq!(question.name, "www.example.com");
+            assert_eq!(question.qtype, QueryType::A);
+            let response = DnsPackets::response_to(request);
+            if seen == 0 {
+                return response
+                    .with_authority(DnsRecord::NS {
+                        domain: "example.com".to_string(),
+                        host: "ns.example.com".to_string(),
+                        ttl: 300,
+                    })
+                    .with_resource(a("ns.example.com", [127, 0, 0, 1]));
+            }
+            let mut response = response.with_answer(a("www.example.com", [192, 0, 2, 1]));
+            response.header.authorative_answer = true;
+            response
+        });
+        let resolver = Resolver::with_root_hints(vec![addr], addr.port());
+
+        let response = resolver.resolve("www.example.com", QueryType::A).unwrap();
+        assert_eq!(response.header.rescode, ResultCode::NoError);
+        assert_eq!(response.answers, vec![a("www.example.com", [192, 0, 2, 1])]);
+        assert_eq!(queries.load(Ordering::SeqCst), 2);
+
+        // the second time around it comes from the cache
+        let response = resolver.resolve("www.example.com.", QueryType::A).unwrap();
+        assert_eq!(response.answers.len(), 1);
+        assert_eq!(response.answers[0].domain(), "www.example.com");
+        assert_eq!(queries.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn returns_nxdomain_from_the_zone() {
+        let (addr, _) = stand_in(|request, _| {
+            DnsPackets::response_to(request).with_rescode(ResultCode::NXDomain)
+        });
+        let resolver = Resolver::with_root_hints(vec![addr], addr.port());
+        let response = resolver.resolve("missing.example", QueryType::A).unwrap();
+        assert_eq!(response.header.rescode, ResultCode::NXDomain);
+        assert!(response.answers.is_empty());
+    }
+
+    fn cname(domain: &str, host: &str) -> DnsRecord {
+        DnsRecord::CNAME {
+            domain: domain.to_string(),
+            host: host.to_string(),
+            ttl: 300,
+        }
+    }
+
+    /// a server for several zones whose CNAMEs point into one another, each answer
+    /// holds only what the zone of the name asked for has. `cN.test` is a chain that
+    /// takes 4 - N more lookups to reach an address
+    fn aliasing_resolver() -> (Resolver, Arc<AtomicUsize>) {
+        let (addr, queries) = stand_in(|request, _| {
+            let name = request.questions[0].name.as_str();
+            let record = match name {
+                "www.a.test" => cname(name, "www.b.test"),
+                "www.b.test" => a(name, [192, 0, 2, 1]),
+                "loop.a.test" => cname(name, "loop.b.test"),
+                "loop.b.test" => cname(name, "loop.a.test"),
+                "c4.test" => a(name, [192, 0, 2, 4]),
+                _ => {
+                    let n: u8 = name[1..2].parse().unwrap();
+                    cname(name, &format!("c{}.test", n + 1))
+                }
+            };
+            let mut response = DnsPackets::response_to(request).with_answer(record);
+            response.header.authorative_answer = true;
+            response
+        });
+        let mut resolver = Resolver::with_root_hints(vec![addr], addr.port());
+        resolver.set_max_cname_chain(3);
+        (resolver, queries)
+    }
+
+    #[test]
+    fn follows_cnames_into_other_zones() {
+        let (resolver, queries) = aliasing_resolver();
+        let response = resolver.resolve("www.a.test", QueryType::A).unwrap();
+        assert_eq!(response.header.rescode, ResultCode::NoError);
+        assert_eq!(
+            response.answers,
+            vec![
+                cname("www.a.test", "www.b.test"),
+                a("www.b.test", [192, 0, 2, 1]),
+            ]
+        );
+        assert_eq!(queries.load(Ordering::SeqCst), 2);
+
+        // asked for the alias itself, there is nothing to follow
+        let response = resolver.resolve("www.a.test", QueryType::CNAME).unwrap();
+        assert_eq!(response.answers, vec![cname("www.a.test", "www.b.test")]);
+    }
+
+    #[test]
+    fn gives_up_on_cname_loops_across_zones() {
+        let (resolver, queries) = aliasing_resolver();
+        assert!(resolver.resolve("loop.a.test", QueryType::A).is_err());
+        assert_eq!(queries.load(Ordering::SeqCst), 2);
+
+        // which the client sees as SERVFAIL
+        let server = Server {
+            zones: Default::default(),
+            resolver,
+            transfer_acl: Default::default(),
+            notify: Default::default(),
+        };
+        let query = DnsPackets::query(random_id(), "loop.a.test", QueryType::A);
+        let response = build_response(&query, &server);
+        assert_eq!(response.header.rescode, ResultCode::ServFail);
+        assert!(response.answers.is_empty());
+    }
+
+    #[test]
+    fn limits_how_long_a_chain_is_followed() {
+        // as many lookups past the first as the limit allows
+        let (resolver, queries) = aliasing_resolver();
+        let response = resolver.resolve("c1.test", QueryType::A).unwrap();
+        assert_eq!(response.answers.len(), 4);
+        assert_eq!(response.answers[3], a("c4.test", [192, 0, 2, 4]));
+        assert_eq!(queries.load(Ordering::SeqCst), 4);
+
+        // one more is too many
+        let (resolver, queries) = aliasing_resolver();
+        assert!(resolver.resolve("c0.test", QueryType::A).is_err());
+        assert_eq!(queries.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn synthesizes_cnames_from_dnames() {
+        // an old server that sends the DNAME without the CNAME it stands for
+        let (addr, queries) = stand_in(|request, _| {
+            let name = request.questions[0].name.as_str();
+            let mut response = DnsPackets::response_to(request);
+            response.header.authorative_answer = true;
+            if name == "www.new.test" {
+                return response.with_answer(a(name, [192, 0, 2, 1]));
+            }
+            let target = if name.ends_with(".long.test") {
+                let label = "x".repeat(63);
+                format!("{0}.{0}.{0}", label)
+            } else {
+                "new.test".to_string()
+            };
+            let owner = name.split_once('.').unwrap().1.to_string();
+            response.with_answer(DnsRecord::DNAME {
+                domain: owner,
+                target,
+                ttl: 600,
+            })
+        });
+        let resolver = Resolver::with_root_hints(vec![addr], addr.port());
+
+        let response = resolver.resolve("www.old.test", QueryType::A).unwrap();
+        assert_eq!(response.header.rescode, ResultCode::NoError);
+        assert_eq!(
+            response.answers[1..],
+            [
+                DnsRecord::CNAME {
+                    domain: "www.old.test".to_string(),
+                    host: "www.new.test".to_string(),
+                    ttl: 600,
+                },
+                a("www.new.test", [192, 0, 2, 1]),
+            ]
+        );
+        assert_eq!(response.answers[0].qtype(), QueryType::DNAME);
+        assert_eq!(queries.load(Ordering::SeqCst), 2);
+
+        let qname = format!("{}.long.test", "y".repeat(62));
+        let response = resolver.resolve(&qname, QueryType::A).unwrap();
+        assert_eq!(response.header.rescode, ResultCode::YXDomain);
+        assert_eq!(response.answers.len(), 1);
+    }
+
+    /// the records of the root zone, every RRset signed by a single Ed25519 key whose DS
+    /// is the trust anchor. the address of `bogus` has a signature that doesnt verify, the one
+    /// of `expired` ran out an hour ago, and `insecure` is delegated to an unsigned zone
+    fn signed_root() -> (Vec<DnsRecord>, DnsRecord) {
+        use QueryType::*;
+
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        let pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+        let dnskey = DnsRecord::DNSKEY {
+            domain: String::new(),
+            flags: dnssec::ZONE_KEY | 1,
+            protocol: 3,
+            algorithm: dnssec::ED25519,
+            public_key: pair.public_key().as_ref().to_vec(),
+            ttl: 3600,
+        };
+        let key_tag = dnssec::key_tag(&dnskey).unwrap();
+        let anchor = DnsRecord::DS {
+            domain: String::new(),
+            key_tag,
+            algorithm: dnssec::ED25519,
+            digest_type: dnssec::DIGEST_SHA256,
+            digest: dnssec::ds_digest(&dnskey, dnssec::DIGEST_SHA256).unwrap(),
+            ttl: 0,
+        };
+        let nsec = |domain: &str, next: &str, types: &[QueryType]| DnsRecord::NSEC {
+            domain: domain.to_string(),
+            next: next.to_string(),
+            types: types.to_vec(),
+            ttl: 300,
+        };
+
+        let records = vec![
+            DnsRecord::SOA {
+                domain: String::new(),
+                mname: "ns".to_string(),
+                rname: "hostmaster".to_string(),
+                serial: 1,
+                refresh: 3600,
+                retry: 600,
+                expire: 86400,
+                minimum: 300,
+                ttl: 300,
+            },
+            dnskey,
+            nsec("", "bogus", &[SOA, RRSIG, NSEC, DNSKEY]),
+            a("bogus", [192, 0, 2, 2]),
+            nsec("bogus", "expired", &[A, RRSIG, NSEC]),
+            a("expired", [192, 0, 2, 3]),
+            nsec("expired", "insecure", &[A, RRSIG, NSEC]),
+            DnsRecord::NS {
+                domain: "insecure".to_string(),
+                host: "ns.insecure".to_string(),
+                ttl: 300,
+            },
+            nsec("insecure", "www", &[NS, RRSIG, NSEC]),
+            a("www", [192, 0, 2, 1]),
+            nsec("www", "", &[A, RRSIG, NSEC]),
+        ];
+
+        let now = unix_time();
+        let mut signed = records.clone();
+        for (rrset, _) in dnssec::rrsets(&records) {
+            // the parent doesnt sign the NS set of a delegation (RFC 4035 2.2)
+            if rrset[0].qtype() == NS {
+                continue;
+            }
+            let (owner, qtype) = (rrset[0].domain(), rrset[0].qtype());
+            let (inception, expiration) = match (owner, qtype) {
+                ("expired", A) => (now - 7200, now - 3600),
+                _ => (now - 3600, now + 3600),
+            };
+            let mut rrsig = DnsRecord::RRSIG {
+                domain: owner.to_string(),
+                type_covered: qtype,
+                algorithm: dnssec::ED25519,
+                labels: dnssec::label_count(owner) as u8,
+                original_ttl: rrset[0].ttl(),
+                expiration,
+                inception,
+                key_tag,
+                signer: String::new(),
+                signature: Vec::new(),
+                ttl: rrset[0].ttl(),
+            };
+            let mut data = dnssec::signed_data(&rrsig, &rrset).unwrap();
+            if (owner, qtype) == ("bogus", A) {
+                data.push(0);
+            }
+            if let DnsRecord::RRSIG { signature, .. } = &mut rrsig {
+                *signature = pair.sign(&data).as_ref().to_vec();
+            }
+            signed.push(rrsig);
+        }
+        (signed, anchor)
+    }
+
+    /// a server resolving through a stand-in for the root that also serves the
+    /// unsigned zone insecure, the resolver trusts the key of the root
+    fn validating_server() -> Server {
+        let (root, anchor) = signed_root();
+        let insecure = vec![
+            DnsRecord::SOA {
+                domain: "insecure".to_string(),
+                mname: "ns.insecure".to_string(),
+                rname: "hostmaster.insecure".to_string(),
+                serial: 1,
+                refresh: 3600,
+                retry: 600,
+                expire: 86400,
+                minimum: 300,
+                ttl: 300,
+            },
+            a("www.insecure", [192, 0, 2, 4]),
+        ];
+
+        let (addr, _) = stand_in(move |request, _| {
+            let question = &request.questions[0];
+            let (name, qtype) = (question.name.as_str(), question.qtype);
+            // the DS of the child lives in the parent
+            let zone = if is_subdomain(name, "insecure")
+                && !(name == "insecure" && qtype == QueryType::DS)
+            {
+                &insecure
+            } else {
+                &root
+            };
+            let covers = |rec: &DnsRecord, qtype: QueryType| match rec {
+                DnsRecord::RRSIG { type_covered, .. } => *type_covered == qtype,
+                _ => rec.qtype() == qtype,
+            };
+
+            let mut response = DnsPackets::response_to(request);
+            response.header.authorative_answer = true;
+            response.answers = zone
+                .iter()
+                .filter(|rec| rec.domain() == name && covers(rec, qtype))
+                .cloned()
+                .collect();
+            if response.answers.is_empty() {
+                if !zone.iter().any(|rec| rec.domain() == name) {
+                    response.header.rescode = ResultCode::NXDomain;
+                }
+                // the whole NSEC chain proves any denial there is
+                response.authoritiees = zone
+                    .iter()
+                    .filter(|rec| covers(rec, QueryType::SOA) || covers(rec, QueryType::NSEC))
+                    .cloned()
+                    .collect();
+            }
+            response
+        });
+
+        let mut resolver = Resolver::with_root_hints(vec![addr], addr.port());
+        resolver.set_trust_anchors(vec![anchor]);
+        Server {
+            zones: Default::default(),
+            resolver,
+            transfer_acl: Default::default(),
+            notify: Default::default(),
+        }
+    }
+
+    /// what the server answers to a query for `name` from a client that speaks DNSSEC
+    fn ask(server: &Server, name: &str, qtype: QueryType, checking_disabled: bool) -> DnsPackets {
+        let mut edns = Edns::new(DEFAULT_UDP_PAYLOAD);
+        edns.dnssec_ok = true;
+        let mut query = DnsPackets::query(random_id(), name, qtype).with_edns(edns);
+        query.header.checking_disabled = checking_disabled;
+        build_response(&query, server)
+    }
+
+    #[test]
+    fn sets_ad_on_secure_answers() {
+        let server = validating_server();
+
+        let response = ask(&server, "www", QueryType::A, false);
+        assert_eq!(response.header.rescode, ResultCode::NoError);
+        assert!(response.header.authed_data);
+        assert_eq!(response.answers_for("www", QueryType::A).count(), 1);
+        assert_eq!(response.answers_for("www", QueryType::RRSIG).count(), 1);
+
+        // denials are proven by the NSEC chain
+        let response = ask(&server, "missing", QueryType::A, false);
+        assert_eq!(response.header.rescode, ResultCode::NXDomain);
+        assert!(response.header.authed_data);
+        let response = ask(&server, "www", QueryType::AAAA, false);
+        assert_eq!(response.header.rescode, ResultCode::NoError);
+        assert!(response.answers.is_empty());
+        assert!(response.header.authed_data);
+    }
+
+    #[test]
+    fn fails_bogus_answers_unless_checking_is_disabled() {
+        let server = validating_server();
+
+        for name in ["bogus", "expired"] {
+            let response = ask(&server, name, QueryType::A, false);
+            assert_eq!(response.header.rescode, ResultCode::ServFail, "{}", name);
+            assert!(response.answers.is_empty());
+
+            // with CD the client gets the data to validate for itself
+            let response = ask(&server, name, QueryType::A, true);
+            assert_eq!(response.header.rescode, ResultCode::NoError, "{}", name);
+            assert!(!response.header.authed_data);
+            assert_eq!(response.answers_for(name, QueryType::A).count(), 1);
+        }
+    }
+
+    #[test]
+    fn answers_from_insecure_delegations_without_ad() {
+        let server = validating_server();
+
+        let response = ask(&server, "www.insecure", QueryType::A, false);
+        assert_eq!(response.header.rescode, ResultCode::NoError);
+        assert!(!response.header.authed_data);
+        assert_eq!(response.answers, vec![a("www.insecure", [192, 0, 2, 4])]);
+    }
+}
@@ -0,0 +1,700 @@
+use std::{
+    collections::HashSet,
+    fs,
+    net::{SocketAddr, TcpStream},
+    path::PathBuf,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    time::{Duration, SystemTime},
+};
+
+use dns::{
+    client::{self, random_id, DEFAULT_TIMEOUT},
+    dnsmsg::DnsPackets,
+    header::ResultCode,
+    packet::VectorPacketBuffer,
+    question::QueryType,
+    record::{serial_newer, DnsRecord},
+    tcp,
+};
+
+use crate::{
+    server::Server,
+    zone::{soa_serial, Zone},
+};
+
+/// how long we wait before trying again while we dont have the SOA to tell us
+const DEFAULT_RETRY: Duration = Duration::from_secs(60);
+
+/// a zone we keep a copy of, transferred from its primary (RFC 1034 4.3.5)
+#[derive(Debug)]
+pub struct Secondary {
+    origin: String,
+    primary: SocketAddr,
+    /// where the copy is kept between runs
+    file: Option<PathBuf>,
+    timeout: Duration,
+    /// when the primary last confirmed our copy is current
+    refreshed: Option<SystemTime>,
+    /// a NOTIFY from the primary cuts the wait for the next refresh short
+    notified: Receiver<()>,
+    notifier: Sender<()>,
+}
+
+/// the refresh, retry and expire intervals of a SOA
+struct Timers {
+    refresh: Duration,
+    retry: Duration,
+    expire: Duration,
+}
+
+impl Timers {
+    fn of(soa: &DnsRecord) -> Option<Timers> {
+        // a zero interval would have us spin
+        let secs = |secs: u32| Duration::from_secs(secs.max(1) as u64);
+        match soa {
+            DnsRecord::SOA {
+                refresh,
+                retry,
+                expire,
+                ..
+            } => Some(Timers {
+                refresh: secs(*refresh),
+                retry: secs(*retry),
+                expire: secs(*expire),
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl Secondary {
+    pub fn new(origin: &str, primary: SocketAddr, file: Option<PathBuf>) -> Self {
+        let (notifier, notified) = mpsc::channel();
+        Self {
+            origin: origin.trim_end_matches('.').to_lowercase(),
+            primary,
+            file,
+            timeout: DEFAULT_TIMEOUT,
+            refreshed: None,
+            notified,
+            notifier,
+        }
+    }
+
+    pub fn origin(&self) -> &str {
+        &self.origin
+    }
+
+    pub fn primary(&self) -> SocketAddr {
+        self.primary
+    }
+
+    /// what to send on to have the secondary refresh right away
+    pub fn notifier(&self) -> Sender<()> {
+        self.notifier.clone()
+    }
+
+    /// keeps the zone up to date for as long as the server runs
+    pub fn run(mut self, server: Arc<Server>) {
+        if let Err(e) = self.load(&server) {
+            eprintln!("no saved copy of {}: {}", self.origin, e);
+        }
+        loop {
+            let wait = self.refresh(&server);
+            if self.notified.recv_timeout(wait).is_ok() {
+                // NOTIFY messages that came in meanwhile are answered by one refresh
+                while self.notified.try_recv().is_ok() {}
+            }
+        }
+    }
+
+    /// serves the copy an earlier run saved, as long as it hasnt expired since.
+    /// it counts as refreshed when it was written
+    pub fn load(&mut self, server: &Server) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(file) = &self.file else {
+            return Ok(());
+        };
+        if !file.exists() {
+            return Ok(());
+        }
+        let zone = Zone::load(file, &self.origin)?;
+        let refreshed = fs::metadata(file)?.modified()?;
+        let expired = Timers::of(zone.soa())
+            .is_some_and(|timers| refreshed.elapsed().unwrap_or_default() >= timers.expire);
+
+        let mut zones = server.zones_mut();
+        zones.insert(zone);
+        zones.set_expired(&self.origin, expired);
+        self.refreshed = Some(refreshed);
+        Ok(())
+    }
+
+    /// asks the primary whether the zone changed and transfers it if so. returns how
+    /// long to wait until the next refresh: the refresh interval of the SOA after a
+    /// success, its retry interval after a failure. once the expire interval passes
+    /// without a success the zone is no longer answered from
+    pub fn refresh(&mut self, server: &Server) -> Duration {
+        let result = self.try_refresh(server);
+        let timers = server
+            .zones()
+            .get(&self.origin)
+            .and_then(|zone| Timers::of(zone.soa()));
+        match result {
+            Ok(()) => {
+                self.refreshed = Some(SystemTime::now());
+                timers.map_or(DEFAULT_RETRY, |timers| timers.refresh)
+            }
+            Err(e) => {
+                eprintln!(
+                    "refreshing {} from {} failed: {}",
+                    self.origin, self.primary, e
+                );
+                let Some(timers) = timers else {
+                    return DEFAULT_RETRY;
+                };
+                let age = self.refreshed.map_or(Duration::MAX, |refreshed| {
+                    refreshed.elapsed().unwrap_or_default()
+                });
+                if age >= timers.expire && !server.zones().is_expired(&self.origin) {
+                    eprintln!("zone {} expired", self.origin);
+                    server.zones_mut().set_expired(&self.origin, true);
+                }
+                timers.retry
+            }
+        }
+    }
+
+    fn try_refresh(&self, server: &Server) -> Result<(), Box<dyn std::error::Error>> {
+        let primary_serial = self.primary_serial()?;
+        let current = server.zones().get(&self.origin).map(|zone| zone.serial());
+
+        let zone = match current {
+            Some(serial) if !serial_newer(primary_serial, serial) => {
+                // nothing changed, the copy we have is good for another while
+                server.zones_mut().set_expired(&self.origin, false);
+                return Ok(());
+            }
+            Some(serial) => self.incremental(server, serial).or_else(|e| {
+                eprintln!(
+                    "IXFR of {} failed, falling back to AXFR: {}",
+                    self.origin, e
+                );
+                self.full()
+            })?,
+            None => self.full()?,
+        };
+
+        println!(
+            "Transferred zone {} at serial {}.",
+            zone.origin(),
+            zone.serial()
+        );
+        if let Err(e) = self.save(&zone) {
+            eprintln!("couldnt save zone {}: {}", self.origin, e);
+        }
+        // our own secondaries want the new version too
+        let soa = zone.soa().clone();
+        server.zones_mut().insert(zone);
+        server.notify.send(&soa);
+        Ok(())
+    }
+
+    /// the zone with the changes since version `serial` applied
+    fn incremental(
+        &self,
+        server: &Server,
+        serial: u32,
+    ) -> Result<Zone, Box<dyn std::error::Error>> {
+        let response = self.transfer(QueryType::IXFR, Some(serial))?;
+        let records = match server.zones().get(&self.origin) {
+            Some(zone) => apply_ixfr(zone, response)?,
+            None => return Err(format!("lost zone {} during the transfer", self.origin).into()),
+        };
+        Zone::new(&self.origin, records)
+    }
+
+    /// the whole zone over AXFR
+    fn full(&self) -> Result<Zone, Box<dyn std::error::Error>> {
+        let mut records = self.transfer(QueryType::AXFR, None)?;
+        // the SOA comes around a second time to end it
+        records.pop();
+        Zone::new(&self.origin, records)
+    }
+
+    /// the serial the primary is at, asked for over UDP like any other question
+    fn primary_serial(&self) -> Result<u32, Box<dyn std::error::Error>> {
+        let mut query = DnsPackets::query(random_id(), &self.origin, QueryType::SOA);
+        query.header.recursion_desired = false;
+        let response = client::exchange(&query, self.primary, self.timeout)?;
+        if response.header.rescode != ResultCode::NoError || !response.header.authorative_answer {
+            return Err(format!(
+                "{} isnt authoritative for {}: {:?}",
+                self.primary, self.origin, response.header.rescode
+            )
+            .into());
+        }
+        response
+            .answers
+            .iter()
+            .find_map(|rec| match rec {
+                DnsRecord::SOA { domain, serial, .. }
+                    if domain.eq_ignore_ascii_case(&self.origin) =>
+                {
+                    Some(*serial)
+                }
+                _ => None,
+            })
+            .ok_or_else(|| format!("{} didnt answer with a SOA", self.primary).into())
+    }
+
+    /// the records of a zone transfer, over as many messages as the primary sends them in.
+    /// an IXFR carries the serial we have in the authority section (RFC 1995 3)
+    fn transfer(
+        &self,
+        qtype: QueryType,
+        serial: Option<u32>,
+    ) -> Result<Vec<DnsRecord>, Box<dyn std::error::Error>> {
+        let mut query = DnsPackets::query(random_id(), &self.origin, qtype);
+        query.header.recursion_desired = false;
+        if let Some(serial) = serial {
+            query.authoritiees.push(DnsRecord::SOA {
+                domain: self.origin.clone(),
+                mname: String::new(),
+                rname: String::new(),
+                serial,
+                refresh: 0,
+                retry: 0,
+                expire: 0,
+                minimum: 0,
+                ttl: 0,
+            });
+        }
+
+        let mut stream = TcpStream::connect_timeout(&self.primary, self.timeout)?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+        let mut req_buff = VectorPacketBuffer::new();
+        query.write(&mut req_buff)?;
+        tcp::write_message(&mut stream, req_buff.as_bytes())?;
+
+        let mut records = Vec::new();
+        loop {
+            let message = tcp::read_message(&mut stream)?;
+            let response = DnsPackets::from_buffer(&mut VectorPacketBuffer::from_bytes(&message))?;
+            if response.header.id != query.header.id || !response.header.response {
+                return Err(format!("{} answered with a mismatched message", self.primary).into());
+            }
+            if response.header.rescode != ResultCode::NoError {
+                return Err(format!(
+                    "{} refused the transfer of {}: {:?}",
+                    self.primary, self.origin, response.header.rescode
+                )
+                .into());
+            }
+            if records.is_empty()
+                && !matches!(response.answers.first(), Some(DnsRecord::SOA { .. }))
+            {
+                return Err(format!("transfer of {} didnt start with a SOA", self.origin).into());
+            }
+            records.extend(response.answers);
+            if transfer_complete(qtype, &records) {
+                return Ok(records);
+            }
+        }
+    }
+
+    /// writes the zone out as a master file. through a temporary file, so a crash
+    /// halfway leaves the previous copy in place
+    fn save(&self, zone: &Zone) -> std::io::Result<()> {
+        let Some(file) = &self.file else {
+            return Ok(());
+        };
+        let mut text = format!("{}\n", zone.soa());
+        for rec in zone.records() {
+            text.push_str(&format!("{}\n", rec));
+        }
+        let temp = file.with_extension("tmp");
+        fs::write(&temp, text)?;
+        fs::rename(&temp, file)
+    }
+}
+
+/// whether `records` are the whole transfer. an IXFR answer of a lone SOA means we are
+/// up to date, otherwise it ends with the SOA it started with. that SOA also ends the
+/// last change of an incremental transfer, it is only the end when it makes the number
+/// of SOAs even: the opening one, two for every change and the closing one
+fn transfer_complete(qtype: QueryType, records: &[DnsRecord]) -> bool {
+    let serial = |rec: &DnsRecord| match rec {
+        DnsRecord::SOA { serial, .. } => Some(*serial),
+        _ => None,
+    };
+    let (Some(first), Some(last)) = (records.first(), records.last()) else {
+        return false;
+    };
+    if records.len() == 1 {
+        return qtype == QueryType::IXFR;
+    }
+    let soas = records.iter().filter_map(serial).count();
+    serial(last).is_some() && serial(last) == serial(first) && soas % 2 == 0
+}
+
+/// the records of `zone` after the changes of an IXFR response (RFC 1995 4).
+/// a response in the AXFR format replaces them all
+fn apply_ixfr(
+    zone: &Zone,
+    mut response: Vec<DnsRecord>,
+) -> Result<Vec<DnsRecord>, Box<dyn std::error::Error>> {
+    // the primary thinks we are up to date after it told us otherwise
+    if response.len() == 1 {
+        return Err("IXFR answer without any changes".into());
+    }
+    // the closing SOA
+    response.pop();
+    if !matches!(response.get(1), Some(DnsRecord::SOA { .. })) {
+        return Ok(response);
+    }
+
+    let mut records: Vec<DnsRecord> = std::iter::once(zone.soa())
+        .chain(zone.records())
+        .cloned()
+        .collect();
+    let mut removed = HashSet::new();
+    let mut deleting = false;
+    for rec in response.into_iter().skip(1) {
+        match (&rec, deleting) {
+            (DnsRecord::SOA { serial, .. }, false) => {
+                // every change has to start from the version we are at
+                if Some(*serial) != records.first().map(soa_serial) {
+                    return Err(format!("IXFR change from serial {} doesnt fit", serial).into());
+                }
+                deleting = true;
+            }
+            (DnsRecord::SOA { .. }, true) => {
+                records.retain(|rec| !removed.contains(&rec.canonical_form(0).ok()));
+                removed.clear();
+                records[0] = rec;
+                deleting = false;
+            }
+            (_, true) => {
+                removed.insert(rec.canonical_form(0).ok());
+            }
+            (_, false) => records.push(rec),
+        }
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{TcpListener, UdpSocket},
+        sync::Mutex,
+        thread,
+    };
+
+    use dns::record::fqdn;
+
+    use super::*;
+    use crate::{notify::Notify, resolver::Resolver, transfer::Acl};
+
+    fn soa(serial: u32) -> DnsRecord {
+        DnsRecord::SOA {
+            domain: "example".to_string(),
+            mname: "ns.example".to_string(),
+            rname: "hostmaster.example".to_string(),
+            serial,
+            refresh: 3600,
+            retry: 600,
+            // a second, so the test doesnt have to wait long for the zone to expire
+            expire: 1,
+            minimum: 300,
+            ttl: 300,
+        }
+    }
+
+    fn a(domain: &str, addr: [u8; 4]) -> DnsRecord {
+        DnsRecord::A {
+            domain: domain.to_string(),
+            addr: addr.into(),
+            ttl: 300,
+        }
+    }
+
+    /// the records of version `serial` of example, some with names and strings
+    /// that only survive a zone file when they are escaped
+    fn version(serial: u32) -> Vec<DnsRecord> {
+        let mut records = vec![
+            soa(serial),
+            DnsRecord::NS {
+                domain: "example".to_string(),
+                host: "ns.example".to_string(),
+                ttl: 300,
+            },
+            a("ns.example", [192, 0, 2, 53]),
+            a("www.example", [192, 0, 2, serial as u8]),
+            DnsRecord::TXT {
+                domain: "with space;(\"quoted\").example".to_string(),
+                data: vec![b"semi;colon (paren)".to_vec(), b"caf\xc3\xa9".to_vec()],
+                ttl: 300,
+            },
+        ];
+        if serial >= 3 {
+            records.push(a("caf\u{e9}.example", [192, 0, 2, 80]));
+        }
+        records
+    }
+
+    /// the records of a zone sorted, so versions can be compared no matter the order
+    fn sorted(records: impl IntoIterator<Item = DnsRecord>) -> Vec<String> {
+        let mut records: Vec<String> = records.into_iter().map(|rec| rec.to_string()).collect();
+        records.sort();
+        records
+    }
+
+    fn zone_records(server: &Server) -> Vec<String> {
+        let zones = server.zones();
+        let zone = zones.get("example").unwrap();
+        sorted(std::iter::once(zone.soa()).chain(zone.records()).cloned())
+    }
+
+    fn empty_server() -> Server {
+        Server {
+            zones: Default::default(),
+            resolver: Resolver::with_root_hints(Vec::new(), 53),
+            transfer_acl: Acl::default(),
+            notify: Notify::default(),
+        }
+    }
+
+    /// what the stand-in primary serves
+    #[derive(Default)]
+    struct Primary {
+        /// every version of the zone it had, the current one last
+        versions: Vec<Vec<DnsRecord>>,
+        /// IXFR is answered with NOTIMP unless set
+        ixfr: bool,
+        /// SERVFAIL for everything unless set
+        up: bool,
+        /// the types of the transfers asked for
+        transfers: Vec<QueryType>,
+    }
+
+    /// the changes from version `from` up to the current one, in the IXFR format
+    fn ixfr_answer(versions: &[Vec<DnsRecord>], from: u32) -> Option<Vec<DnsRecord>> {
+        let start = versions.iter().position(|v| soa_serial(&v[0]) == from)?;
+        let current = versions.last()?;
+        let mut answer = vec![current[0].clone()];
+        for pair in versions[start..].windows(2) {
+            let (old, new) = (&pair[0], &pair[1]);
+            answer.push(old[0].clone());
+            answer.extend(old[1..].iter().filter(|rec| !new.contains(rec)).cloned());
+            answer.push(new[0].clone());
+            answer.extend(new[1..].iter().filter(|rec| !old.contains(rec)).cloned());
+        }
+        answer.push(current[0].clone());
+        Some(answer)
+    }
+
+    /// a primary on loopback, SOA queries over UDP and transfers over TCP on the same port
+    fn primary(state: Arc<Mutex<Primary>>) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let socket = UdpSocket::bind(addr).unwrap();
+
+        let udp_state = Arc::clone(&state);
+        thread::spawn(move || loop {
+            let mut raw = [0u8; 512];
+            let Ok((len, source)) = socket.recv_from(&mut raw) else {
+                return;
+            };
+            let request =
+                DnsPackets::from_buffer(&mut VectorPacketBuffer::from_bytes(&raw[..len])).unwrap();
+            let mut response = DnsPackets::response_to(&request);
+            let state = udp_state.lock().unwrap();
+            if state.up {
+                response.header.authorative_answer = true;
+                response
+                    .answers
+                    .push(state.versions.last().unwrap()[0].clone());
+            } else {
+                response.header.rescode = ResultCode::ServFail;
+            }
+            let buffer = response.write_with_limit(512).unwrap();
+            socket.send_to(buffer.as_bytes(), source).unwrap();
+        });
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let message = tcp::read_message(&mut stream).unwrap();
+                let request =
+                    DnsPackets::from_buffer(&mut VectorPacketBuffer::from_bytes(&message)).unwrap();
+                let qtype = request.questions[0].qtype;
+                let mut response = DnsPackets::response_to(&request);
+                response.header.authorative_answer = true;
+
+                let mut state = state.lock().unwrap();
+                state.transfers.push(qtype);
+                let current = state.versions.last().unwrap().clone();
+                match qtype {
+                    QueryType::IXFR if !state.ixfr => response.header.rescode = ResultCode::NOTimP,
+                    QueryType::IXFR => {
+                        let from = soa_serial(&request.authoritiees[0]);
+                        response.answers = ixfr_answer(&state.versions, from).unwrap();
+                    }
+                    _ => {
+                        response.answers = current.clone();
+                        response.answers.push(current[0].clone());
+                    }
+                }
+                let buffer = response.write_with_limit(u16::MAX as usize).unwrap();
+                tcp::write_message(&mut stream, buffer.as_bytes()).unwrap();
+            }
+        });
+        addr
+    }
+
+    #[test]
+    fn pulls_and_refreshes_from_a_loopback_primary() {
+        let state = Arc::new(Mutex::new(Primary {
+            versions: vec![version(1)],
+            ixfr: true,
+            up: true,
+            ..Default::default()
+        }));
+        let dir = std::env::temp_dir().join(format!("dns-secondary-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("example.zone");
+        let mut secondary =
+            Secondary::new("example.", primary(Arc::clone(&state)), Some(file.clone()));
+        let server = empty_server();
+
+        // without a copy the whole zone comes over AXFR
+        assert_eq!(secondary.refresh(&server), Duration::from_secs(3600));
+        assert_eq!(zone_records(&server), sorted(version(1)));
+
+        // a new version comes as the difference to the one we have
+        state.lock().unwrap().versions.push(version(2));
+        secondary.refresh(&server);
+        assert_eq!(zone_records(&server), sorted(version(2)));
+
+        // nothing changed, nothing to transfer
+        secondary.refresh(&server);
+
+        // a primary that doesnt do IXFR still gets us there over AXFR
+        {
+            let mut state = state.lock().unwrap();
+            state.versions.push(version(3));
+            state.ixfr = false;
+        }
+        secondary.refresh(&server);
+        assert_eq!(zone_records(&server), sorted(version(3)));
+        assert_eq!(
+            state.lock().unwrap().transfers,
+            [
+                QueryType::AXFR,
+                QueryType::IXFR,
+                QueryType::IXFR,
+                QueryType::AXFR
+            ]
+        );
+        assert!(!server.zones().is_expired("example"));
+
+        // the copy is saved in a form that reads back the same
+        let saved = fs::read_to_string(&file).unwrap();
+        assert!(saved.contains(&fqdn("caf\u{e9}.example")));
+        let mut reloaded = Secondary::new("example", secondary.primary(), Some(file));
+        let other = empty_server();
+        reloaded.load(&other).unwrap();
+        assert_eq!(zone_records(&other), sorted(version(3)));
+
+        // once the primary cant be reached for longer than the expire interval
+        // the zone is no longer answered from
+        state.lock().unwrap().up = false;
+        thread::sleep(Duration::from_millis(1100));
+        assert_eq!(secondary.refresh(&server), Duration::from_secs(600));
+        assert!(server.zones().is_expired("example"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn tells_when_a_transfer_is_complete() {
+        let rec = a("www.example", [192, 0, 2, 1]);
+        let axfr = [soa(1), rec.clone(), soa(1)];
+        assert!(transfer_complete(QueryType::AXFR, &axfr));
+        assert!(!transfer_complete(QueryType::AXFR, &axfr[..2]));
+        assert!(!transfer_complete(QueryType::AXFR, &axfr[..1]));
+        assert!(!transfer_complete(QueryType::AXFR, &[]));
+
+        // up to date
+        assert!(transfer_complete(QueryType::IXFR, &[soa(3)]));
+        // from 1 to 2 to 3, the SOA of 3 also ends the second change
+        let ixfr = [
+            soa(3),
+            soa(1),
+            rec.clone(),
+            soa(2),
+            soa(2),
+            rec.clone(),
+            soa(3),
+            rec,
+            soa(3),
+        ];
+        assert!(transfer_complete(QueryType::IXFR, &ixfr));
+        assert!(!transfer_complete(QueryType::IXFR, &ixfr[..7]));
+        assert!(!transfer_complete(QueryType::IXFR, &ixfr[..8]));
+    }
+
+    #[test]
+    fn applies_incremental_transfers() {
+        let zone = Zone::new("example", version(1)).unwrap();
+        let changes = ixfr_answer(&[version(1), version(2), version(3)], 1).unwrap();
+        assert_eq!(
+            sorted(apply_ixfr(&zone, changes).unwrap()),
+            sorted(version(3))
+        );
+
+        // an answer in the AXFR format replaces the zone
+        let mut axfr = version(2);
+        axfr.push(soa(2));
+        assert_eq!(sorted(apply_ixfr(&zone, axfr).unwrap()), sorted(version(2)));
+
+        // changes that start from a version we dont have
+        let changes = ixfr_answer(&[version(2), version(3)], 2).unwrap();
+        assert!(apply_ixfr(&zone, changes).is_err());
+        assert!(apply_ixfr(&zone, vec![soa(1)]).is_err());
+    }
+
+    /// a primary on loopback answering SOA queries over UDP with `rescode`
+    fn soa_stand_in(rescode: ResultCode) -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap();
+        thread::spawn(move || loop {
+            let mut raw = [0u8; 512];
+            let Ok((len, source)) = socket.recv_from(&mut raw) else {
+                return;
+            };
+            let request =
+                DnsPackets::from_buffer(&mut VectorPacketBuffer::from_bytes(&raw[..len])).unwrap();
+            let mut response = DnsPackets::response_to(&request).with_rescode(rescode);
+            response.header.authorative_answer = true;
+            let buffer = response.write_with_limit(512).unwrap();
+            socket.send_to(buffer.as_bytes(), source).unwrap();
+        });
+        addr
+    }
+
+    #[test]
+    fn refuses_primaries_that_arent_authoritative() {
+        // NOTAUTH (RFC 2136 2.2) has no name of its own here, it is a failure all the same
+        for rescode in [ResultCode::Unknown(9), ResultCode::Refused] {
+            let secondary = Secondary::new("example", soa_stand_in(rescode), None);
+            let err = secondary.primary_serial().unwrap_err();
+            assert!(err.to_string().contains("isnt authoritative"), "{}", err);
+        }
+    }
+}
@@ -0,0 +1,661 @@
+use std::{
+    net::{IpAddr, SocketAddr, TcpListener, TcpStream, UdpSocket},
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard,
+    },
+    thread,
+    time::Duration,
+};
+
+use dns::{
+    dnsmsg::DnsPackets,
+    edns::{Edns, DEFAULT_UDP_PAYLOAD, EDNS_VERSION},
+    error::DnsError,
+    header::{DnsHeader, ResultCode, OPCODE_NOTIFY, OPCODE_QUERY},
+    packet::{VectorPacketBuffer, MAX_MESSAGE_SIZE, UDP_MESSAGE_SIZE},
+    record::{serial_newer, DnsRecord},
+    tcp,
+};
+
+use crate::{
+    notify::{self, Notify},
+    resolver::Resolver,
+    transfer::{self, Acl},
+    zone::{Catalog, Zone},
+};
+
+/// how long a TCP connection may sit without sending a query (RFC 7766 6.2.3)
+const TCP_IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+/// queries of a single TCP connection we work on at the same time.
+/// anything beyond that is answered in order before reading more
+const MAX_PIPELINED: usize = 16;
+/// TCP connections we serve at the same time, each one takes a thread.
+/// connections beyond that are closed right away
+const MAX_TCP_CONNECTIONS: usize = 256;
+/// UDP queries we work on at the same time, each one takes a thread.
+/// anything beyond that is answered before receiving more
+const MAX_UDP_IN_FLIGHT: usize = 128;
+
+/// what queries are answered from: the zones we are authoritative for,
+/// anything outside of them is resolved
+#[derive(Debug)]
+pub struct Server {
+    /// secondary zones are replaced while we serve them
+    pub zones: RwLock<Catalog>,
+    pub resolver: Resolver,
+    /// who may transfer our zones
+    pub transfer_acl: Acl,
+    pub notify: Notify,
+}
+
+impl Server {
+    pub fn zones(&self) -> RwLockReadGuard<'_, Catalog> {
+        // whoever panicked while holding the lock left a whole catalog behind, zones are
+        // only ever swapped in completely
+        self.zones
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn zones_mut(&self) -> RwLockWriteGuard<'_, Catalog> {
+        self.zones
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// reads the primary zone at `origin` from `path` again and serves the new version,
+    /// its secondaries are notified. a version whose serial didnt go up is left alone,
+    /// the secondaries would never pick it up. returns whether the zone was replaced
+    pub fn reload_zone(
+        &self,
+        path: &Path,
+        origin: &str,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        let zone = Zone::load(path, origin)?;
+        let current = self.zones().get(zone.origin()).map(Zone::serial);
+        if current.is_some_and(|serial| !serial_newer(zone.serial(), serial)) {
+            eprintln!(
+                "{} changed but the serial of {} didnt go up, keeping the version we have",
+                path.display(),
+                zone.origin()
+            );
+            return Ok(false);
+        }
+
+        println!(
+            "Reloaded zone {} at serial {}.",
+            zone.origin(),
+            zone.serial()
+        );
+        let soa = zone.soa().clone();
+        self.zones_mut().insert(zone);
+        self.notify.send(&soa);
+        Ok(true)
+    }
+}
+
+/// answers the question of `request` from our zones or by resolving it,
+/// and builds the response for it
+pub fn build_response(request: &DnsPackets, server: &Server) -> DnsPackets {
+    let mut request = request.clone();
+
+    // creating a dnspacket as a response
+    let mut res_packet = DnsPackets::new();
+    res_packet.header.id = request.header.id;
+    res_packet.header.recursion_desired = true;
+    res_packet.header.recursion_available = true;
+    res_packet.header.response = true;
+    res_packet.header.checking_disabled = request.header.checking_disabled;
+
+    // answer EDNS with EDNS
+    let dnssec_ok = request.edns.as_ref().is_some_and(|edns| edns.dnssec_ok);
+    if let Some(edns) = &request.edns {
+        let mut res_edns = Edns::new(DEFAULT_UDP_PAYLOAD);
+        res_edns.dnssec_ok = dnssec_ok;
+        res_packet.edns = Some(res_edns);
+
+        // we only speak version 0 (RFC 6891 6.1.3)
+        if edns.version > EDNS_VERSION {
+            res_packet.header.rescode = ResultCode::BadVers;
+            return res_packet;
+        }
+    }
+
+    // cosnidering one question..
+    if let Some(question) = request.questions.pop() {
+        println!("Received a query: {:?}", question);
+
+        // with CD set the client validates for itself and wants the data even if it is bogus
+        let authoritative = server.zones().lookup(&question.name, question.qtype);
+        let result = match authoritative {
+            Some(answer) => Ok(answer),
+            None if request.header.checking_disabled => server
+                .resolver
+                .resolve_unvalidated(&question.name, question.qtype),
+            None => server.resolver.resolve(&question.name, question.qtype),
+        };
+
+        //if query fails (or turns out bogus), SERVFAIL will be returned
+        //otherwise question and response records are copied into our response
+        if let Ok(result) = result {
+            // AD only for clients that show they understand it (RFC 6840 5.7)
+            res_packet.header.authed_data =
+                result.header.authed_data && (dnssec_ok || request.header.authed_data);
+            res_packet.header.rescode = result.header.rescode;
+            res_packet.header.authorative_answer = result.header.authorative_answer;
+
+            // the DNSSEC records are only for clients that asked for them (RFC 4035 3.2.1)
+            let wanted = |rec: &DnsRecord| {
+                dnssec_ok
+                    || rec.qtype() == question.qtype
+                    || !matches!(
+                        rec,
+                        DnsRecord::RRSIG { .. } | DnsRecord::NSEC { .. } | DnsRecord::NSEC3 { .. }
+                    )
+            };
+            for rec in result.answers.into_iter().filter(wanted) {
+                println!("Answer: {:?}", rec);
+                res_packet.answers.push(rec);
+            }
+            for rec in result.authoritiees.into_iter().filter(wanted) {
+                println!("Authority: {:?}", rec);
+                res_packet.authoritiees.push(rec);
+            }
+            for rec in result.resources.into_iter().filter(wanted) {
+                println!("Resource: {:?}", rec);
+                res_packet.resources.push(rec);
+            }
+            res_packet.questions.push(question);
+        } else {
+            res_packet.header.rescode = ResultCode::ServFail;
+        }
+    } else {
+        // if a question is not present we return FORMERR
+        // indicates that sender made a mistake
+        res_packet.header.rescode = ResultCode::FormerR;
+    }
+    res_packet
+}
+
+/// the response to anything but a zone transfer, depending on what kind of request it is
+fn respond(request: &DnsPackets, server: &Server, peer: IpAddr) -> DnsPackets {
+    match request.header.opcode {
+        OPCODE_QUERY => build_response(request, server),
+        OPCODE_NOTIFY => notify::answer(request, server, peer),
+        // inverse queries are gone (RFC 3425) and we dont take dynamic updates
+        _ => DnsPackets::response_to(request).with_rescode(ResultCode::NOTimP),
+    }
+}
+
+/// receives queries over UDP forever. each one is answered on its own thread
+/// so a slow resolution doesnt hold up the queries behind it
+pub fn run_udp(socket: UdpSocket, server: Arc<Server>) {
+    let socket = Arc::new(socket);
+    let in_flight = Arc::new(AtomicUsize::new(0));
+    // a buffer to read from socket onto
+    let mut raw = [0u8; MAX_MESSAGE_SIZE];
+
+    loop {
+        // block until we receive a bytepacket
+        let (len, source) = match socket.recv_from(&mut raw) {
+            Ok(received) => received,
+            Err(e) => {
+                eprintln!("failed to receive a udp query: {:?}", e);
+                continue;
+            }
+        };
+        let message = raw[..len].to_vec();
+
+        if in_flight.load(Ordering::SeqCst) >= MAX_UDP_IN_FLIGHT {
+            if let Err(e) = answer_udp_query(&message, source, &socket, &server) {
+                eprintln!("failed to answer over udp: {:?}", e);
+            }
+            continue;
+        }
+
+        in_flight.fetch_add(1, Ordering::SeqCst);
+        let socket = Arc::clone(&socket);
+        let server = Arc::clone(&server);
+        let in_flight = Arc::clone(&in_flight);
+        thread::spawn(move || {
+            if let Err(e) = answer_udp_query(&message, source, &socket, &server) {
+                eprintln!("failed to answer over udp: {:?}", e);
+            }
+            in_flight.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+}
+
+/// answers a single query that came in over UDP from `source`
+fn answer_udp_query(
+    message: &[u8],
+    source: SocketAddr,
+    socket: &UdpSocket,
+    server: &Server,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut req_buff = VectorPacketBuffer::from_bytes(message);
+
+    //parsing the msg into a dns packet
+    let request_packet = match DnsPackets::from_buffer(&mut req_buff) {
+        Ok(request) => request,
+        Err(e) => {
+            if let Some(mut res_packet) = error_response(message, &e) {
+                let res_buffer = res_packet.write_with_limit(UDP_MESSAGE_SIZE)?;
+                socket.send_to(res_buffer.as_bytes(), source)?;
+            }
+            return Ok(());
+        }
+    };
+    let mut res_packet = if transfer::is_transfer(&request_packet) {
+        transfer::answer_udp(&request_packet, server, source.ip())
+    } else {
+        respond(&request_packet, server, source.ip())
+    };
+
+    // encode and send the response
+    // anything bigger than the client can take gets truncated
+    let res_buffer = res_packet.write_with_limit(udp_payload_size(&request_packet))?;
+    socket.send_to(res_buffer.as_bytes(), source)?;
+
+    Ok(())
+}
+
+/// the response to a request that couldnt be parsed. nothing is sent back when
+/// not even the header could be read, the client couldnt match it up anyway
+fn error_response(message: &[u8], e: &DnsError) -> Option<DnsPackets> {
+    eprintln!("malformed request: {}", e);
+
+    let mut header = DnsHeader::new();
+    header
+        .read(&mut VectorPacketBuffer::from_bytes(message))
+        .ok()?;
+    if header.response {
+        return None;
+    }
+
+    let mut res_packet = DnsPackets::new();
+    res_packet.header.id = header.id;
+    res_packet.header.opcode = header.opcode;
+    res_packet.header.recursion_desired = header.recursion_desired;
+    res_packet.header.recursion_available = true;
+    res_packet.header.response = true;
+    res_packet.header.rescode = e.rescode();
+    Some(res_packet)
+}
+
+/// the largest UDP response `request` allows for: 512 bytes for plain DNS, the advertised
+/// EDNS payload size otherwise, but never more than we are willing to send
+fn udp_payload_size(request: &DnsPackets) -> usize {
+    match &request.edns {
+        Some(edns) => edns
+            .udp_payload_size
+            .clamp(UDP_MESSAGE_SIZE as u16, DEFAULT_UDP_PAYLOAD) as usize,
+        None => UDP_MESSAGE_SIZE,
+    }
+}
+
+/// accepts TCP connections forever, each one is served on its own thread
+pub fn run_tcp(listener: TcpListener, server: Arc<Server>) {
+    let connections = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("failed to accept a tcp connection: {:?}", e);
+                continue;
+            }
+        };
+
+        // idle clients would otherwise tie up a thread each until they time out
+        let Some(slot) = ConnectionSlot::take(&connections) else {
+            eprintln!(
+                "too many tcp connections, closing one from {:?}",
+                stream.peer_addr()
+            );
+            continue;
+        };
+        let server = Arc::clone(&server);
+        thread::spawn(move || {
+            if let Err(e) = handle_tcp_connection(stream, server, Arc::new(slot), TCP_IDLE_TIMEOUT)
+            {
+                eprintln!("tcp connection failed: {:?}", e);
+            }
+        });
+    }
+}
+
+/// one of the [`MAX_TCP_CONNECTIONS`], given back when dropped. the connection and
+/// every query of it still being answered hold on to it
+struct ConnectionSlot(Arc<AtomicUsize>);
+
+impl ConnectionSlot {
+    fn take(connections: &Arc<AtomicUsize>) -> Option<ConnectionSlot> {
+        connections
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |open| {
+                (open < MAX_TCP_CONNECTIONS).then_some(open + 1)
+            })
+            .ok()?;
+        Some(ConnectionSlot(Arc::clone(connections)))
+    }
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// serves every query a client sends over one connection.
+/// queries are resolved concurrently and answered as soon as they are ready,
+/// so responses can go out in a different order than the queries came in (RFC 7766 6.2.1.1).
+/// the connection is closed once it sat for `idle_timeout` without sending a query
+fn handle_tcp_connection(
+    stream: TcpStream,
+    server: Arc<Server>,
+    slot: Arc<ConnectionSlot>,
+    idle_timeout: Duration,
+) -> Result<(), Box<dyn std::error::Error>> {
+    stream.set_read_timeout(Some(idle_timeout))?;
+    let peer = stream.peer_addr()?.ip();
+    let mut reader = stream.try_clone()?;
+    let writer = Arc::new(Mutex::new(stream));
+    let in_flight = Arc::new(AtomicUsize::new(0));
+
+    loop {
+        // a closed connection or one that went idle is simply dropped
+        let message = match tcp::read_message(&mut reader) {
+            Ok(message) => message,
+            Err(_) => return Ok(()),
+        };
+
+        let mut req_buff = VectorPacketBuffer::from_bytes(&message);
+        let request = match DnsPackets::from_buffer(&mut req_buff) {
+            Ok(request) => request,
+            Err(e) => match error_response(&message, &e) {
+                Some(mut res_packet) => {
+                    let res_buffer = res_packet.write_with_limit(MAX_MESSAGE_SIZE)?;
+                    let mut stream = writer.lock().map_err(|_| "tcp writer lock poisoned")?;
+                    tcp::write_message(&mut *stream, res_buffer.as_bytes())?;
+                    continue;
+                }
+                // not even a query header, give up on the connection
+                None => return Ok(()),
+            },
+        };
+
+        if in_flight.load(Ordering::SeqCst) >= MAX_PIPELINED {
+            answer_tcp_query(&request, &server, peer, &writer)?;
+            continue;
+        }
+
+        in_flight.fetch_add(1, Ordering::SeqCst);
+        let server = Arc::clone(&server);
+        let writer = Arc::clone(&writer);
+        let in_flight = Arc::clone(&in_flight);
+        let slot = Arc::clone(&slot);
+        thread::spawn(move || {
+            if let Err(e) = answer_tcp_query(&request, &server, peer, &writer) {
+                eprintln!("failed to answer over tcp: {:?}", e);
+            }
+            in_flight.fetch_sub(1, Ordering::SeqCst);
+            drop(slot);
+        });
+    }
+}
+
+fn answer_tcp_query(
+    request: &DnsPackets,
+    server: &Server,
+    peer: IpAddr,
+    writer: &Mutex<TcpStream>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // a zone transfer takes as many messages as it needs, sent back to back
+    if transfer::is_transfer(request) {
+        let messages = transfer::answer_tcp(request, server, peer);
+        let mut stream = writer.lock().map_err(|_| "tcp writer lock poisoned")?;
+        for mut res_packet in messages {
+            let res_buffer = res_packet.write_with_limit(MAX_MESSAGE_SIZE)?;
+            tcp::write_message(&mut *stream, res_buffer.as_bytes())?;
+        }
+        return Ok(());
+    }
+
+    let mut res_packet = respond(request, server, peer);
+    let res_buffer = res_packet.write_with_limit(MAX_MESSAGE_SIZE)?;
+
+    // one writer at a time so responses dont interleave
+    let mut stream = writer.lock().map_err(|_| "tcp writer lock poisoned")?;
+    tcp::write_message(&mut *stream, res_buffer.as_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        io::{Read, Write},
+        time::Instant,
+    };
+
+    use dns::{question::QueryType, zonefile::parse_zone};
+
+    use super::*;
+    use crate::zone::soa_serial;
+
+    /// example at `serial`, with the address of www given by `www`
+    fn zone_text(serial: u32, www: u8) -> String {
+        format!(
+            "$ORIGIN example.\n\
+             @ 300 IN SOA ns.example. hostmaster.example. {} 3600 600 86400 300\n\
+             @ 300 IN NS ns.example.\n\
+             www 300 IN A 192.0.2.{}\n",
+            serial, www
+        )
+    }
+
+    /// a server authoritative for example, with nothing to resolve through
+    fn example_server() -> Arc<Server> {
+        let records = parse_zone(&zone_text(1, 1), "example").unwrap();
+        let mut zones = Catalog::new();
+        zones.insert(Zone::new("example", records).unwrap());
+        Arc::new(Server {
+            zones: RwLock::new(zones),
+            resolver: Resolver::with_root_hints(Vec::new(), 53),
+            transfer_acl: Acl::default(),
+            notify: Notify::default(),
+        })
+    }
+
+    #[test]
+    fn answers_unknown_edns_versions_with_badvers() {
+        let mut edns = Edns::new(DEFAULT_UDP_PAYLOAD);
+        edns.version = 1;
+        let query = DnsPackets::query(7, "www.example", QueryType::A).with_edns(edns);
+
+        let mut response = build_response(&query, &example_server());
+        assert_eq!(response.header.id, 7);
+        assert_eq!(response.header.rescode, ResultCode::BadVers);
+        assert!(response.answers.is_empty());
+        let edns = response.edns.as_ref().unwrap();
+        assert_eq!(edns.version, EDNS_VERSION);
+
+        // BADVERS only fits the header with the OPT record carrying its upper bits
+        let buffer = response.write_with_limit(UDP_MESSAGE_SIZE).unwrap();
+        let read = DnsPackets::from_buffer(&mut VectorPacketBuffer::from_bytes(buffer.as_bytes()))
+            .unwrap();
+        assert_eq!(read.header.rescode, ResultCode::BadVers);
+        assert_eq!(read.edns.unwrap().extended_rcode, 1);
+    }
+
+    #[test]
+    fn clamps_the_udp_payload_size() {
+        let query = |size: Option<u16>| {
+            let query = DnsPackets::query(7, "www.example", QueryType::A);
+            match size {
+                Some(size) => query.with_edns(Edns::new(size)),
+                None => query,
+            }
+        };
+        assert_eq!(udp_payload_size(&query(None)), UDP_MESSAGE_SIZE);
+        assert_eq!(udp_payload_size(&query(Some(100))), UDP_MESSAGE_SIZE);
+        assert_eq!(udp_payload_size(&query(Some(1000))), 1000);
+        assert_eq!(
+            udp_payload_size(&query(Some(u16::MAX))),
+            DEFAULT_UDP_PAYLOAD as usize
+        );
+    }
+
+    #[test]
+    fn answers_pipelined_tcp_queries() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = example_server();
+        thread::spawn(move || run_tcp(listener, server));
+
+        // both queries go out before either answer is read
+        let mut queries = Vec::new();
+        for (id, name, qtype) in [
+            (1, "www.example", QueryType::A),
+            (2, "example", QueryType::NS),
+        ] {
+            let mut query = DnsPackets::query(id, name, qtype);
+            let buffer = query.write_with_limit(MAX_MESSAGE_SIZE).unwrap();
+            let len = buffer.as_bytes().len() as u16;
+            queries.extend_from_slice(&len.to_be_bytes());
+            queries.extend_from_slice(buffer.as_bytes());
+        }
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        stream.write_all(&queries).unwrap();
+
+        let mut responses: Vec<DnsPackets> = (0..2)
+            .map(|_| {
+                let message = tcp::read_message(&mut stream).unwrap();
+                DnsPackets::from_buffer(&mut VectorPacketBuffer::from_bytes(&message)).unwrap()
+            })
+            .collect();
+        // they may come back in either order
+        responses.sort_by_key(|response| response.header.id);
+        assert_eq!(responses[0].header.id, 1);
+        assert_eq!(
+            responses[0].answers[0].to_string(),
+            "www.example.\t300\tIN\tA\t192.0.2.1"
+        );
+        assert_eq!(responses[1].header.id, 2);
+        assert_eq!(
+            responses[1].answers[0].to_string(),
+            "example.\t300\tIN\tNS\tns.example."
+        );
+    }
+
+    #[test]
+    fn closes_idle_tcp_connections() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        let connections = Arc::new(AtomicUsize::new(0));
+        let slot = Arc::new(ConnectionSlot::take(&connections).unwrap());
+        let server = example_server();
+        let connection = thread::spawn(move || {
+            handle_tcp_connection(stream, server, slot, Duration::from_millis(200)).unwrap()
+        });
+
+        let started = Instant::now();
+        client
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        assert_eq!(client.read(&mut [0u8; 2]).unwrap(), 0);
+        assert!(started.elapsed() >= Duration::from_millis(200));
+        // the slot went back with the connection
+        connection.join().unwrap();
+        assert_eq!(connections.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn reloads_zones_and_notifies_when_the_serial_goes_up() {
+        let secondary = UdpSocket::bind("127.0.0.1:0").unwrap();
+        secondary
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let mut notify = Notify::new();
+        notify.add_target("example", secondary.local_addr().unwrap());
+
+        let file = std::env::temp_dir().join(format!("dns-reload-{}.zone", std::process::id()));
+        fs::write(&file, zone_text(1, 1)).unwrap();
+        let mut zones = Catalog::new();
+        zones.insert(Zone::load(&file, "example").unwrap());
+        let server = Server {
+            zones: RwLock::new(zones),
+            resolver: Resolver::with_root_hints(Vec::new(), 53),
+            transfer_acl: Acl::default(),
+            notify,
+        };
+        let www = |server: &Server| server.zones().lookup("www.example", QueryType::A).unwrap();
+
+        fs::write(&file, zone_text(2, 2)).unwrap();
+        assert!(server.reload_zone(&file, "example").unwrap());
+        assert_eq!(server.zones().get("example").unwrap().serial(), 2);
+        assert_eq!(
+            www(&server).answers[0].to_string(),
+            "www.example.\t300\tIN\tA\t192.0.2.2"
+        );
+
+        let mut raw = [0u8; 512];
+        let (len, source) = secondary.recv_from(&mut raw).unwrap();
+        let request =
+            DnsPackets::from_buffer(&mut VectorPacketBuffer::from_bytes(&raw[..len])).unwrap();
+        assert_eq!(request.header.opcode, OPCODE_NOTIFY);
+        assert_eq!(request.questions[0].name, "example");
+        assert_eq!(soa_serial(&request.answers[0]), 2);
+        let mut ack = DnsPackets::response_to(&request);
+        let buffer = ack.write_with_limit(512).unwrap();
+        secondary.send_to(buffer.as_bytes(), source).unwrap();
+
+        // an edit that forgot the serial isnt served
+        fs::write(&file, zone_text(2, 3)).unwrap();
+        assert!(!server.reload_zone(&file, "example").unwrap());
+        assert_eq!(
+            www(&server).answers[0].to_string(),
+            "www.example.\t300\tIN\tA\t192.0.2.2"
+        );
+
+        fs::remove_file(&file).unwrap();
+    }
+
+    #[test]
+    fn refuses_tcp_connections_over_the_limit() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = Arc::new(Server {
+            zones: Default::default(),
+            resolver: Resolver::with_root_hints(Vec::new(), 53),
+            transfer_acl: Acl::default(),
+            notify: Notify::default(),
+        });
+        thread::spawn(move || run_tcp(listener, server));
+
+        let mut open: Vec<TcpStream> = (0..MAX_TCP_CONNECTIONS)
+            .map(|_| TcpStream::connect(addr).unwrap())
+            .collect();
+        // the server closes the one too many without a word
+        let mut extra = TcpStream::connect(addr).unwrap();
+        extra
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        assert_eq!(extra.read(&mut [0u8; 2]).unwrap(), 0);
+
+        // once one goes away there is room again
+        drop(open.pop());
+        thread::sleep(Duration::from_millis(200));
+        let mut again = TcpStream::connect(addr).unwrap();
+        again
+            .set_read_timeout(Some(Duration::from_millis(500)))
+            .unwrap();
+        let err = again.read(&mut [0u8; 2]).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::WouldBlock);
+    }
+}
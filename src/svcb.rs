@@ -0,0 +1,606 @@
+use std::{
+    fmt,
+    net::{Ipv4Addr, Ipv6Addr},
+};
+
+use crate::{
+    encoding::{base64_decode, base64_encode},
+    error::DnsError,
+    packet::PacketBuffer,
+    record::{parse_escape, write_character_string},
+};
+
+// SVCB and HTTPS RDATA (RFC 9460 2.2)
+//
+//  SvcPriority  16 bits, 0 is AliasMode, anything else ServiceMode
+//  TargetName   uncompressed name, the root stands for the owner name
+//  SvcParams    {SvcParamKey (16), length (16), value} ... in strictly increasing key order
+
+/// a key=value pair of a SVCB or HTTPS record
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SvcParam {
+    /// keys a client has to understand to use the record, key 0
+    Mandatory(Vec<u16>),
+    /// protocols the service speaks, key 1
+    Alpn(Vec<Vec<u8>>),
+    /// the default protocol isnt among them, key 2
+    NoDefaultAlpn,
+    /// key 3
+    Port(u16),
+    /// key 4
+    Ipv4Hint(Vec<Ipv4Addr>),
+    /// an ECHConfigList for Encrypted Client Hello, key 5
+    Ech(Vec<u8>),
+    /// key 6
+    Ipv6Hint(Vec<Ipv6Addr>),
+    /// any other key, written as `keyNNNNN` in presentation format
+    Unknown {
+        /// the SvcParamKey
+        key: u16,
+        /// the SvcParamValue as it came
+        value: Vec<u8>,
+    },
+}
+
+impl SvcParam {
+    /// the SvcParamKey on the wire
+    pub fn key(&self) -> u16 {
+        match self {
+            SvcParam::Mandatory(_) => 0,
+            SvcParam::Alpn(_) => 1,
+            SvcParam::NoDefaultAlpn => 2,
+            SvcParam::Port(_) => 3,
+            SvcParam::Ipv4Hint(_) => 4,
+            SvcParam::Ech(_) => 5,
+            SvcParam::Ipv6Hint(_) => 6,
+            SvcParam::Unknown { key, .. } => *key,
+        }
+    }
+
+    /// `Err` holds what is wrong with the value
+    fn parse(key: u16, data: &[u8]) -> Result<SvcParam, &'static str> {
+        let param = match key {
+            0 => {
+                if data.is_empty() || !data.len().is_multiple_of(2) {
+                    return Err("malformed mandatory");
+                }
+                SvcParam::Mandatory(
+                    data.chunks(2)
+                        .map(|k| u16::from_be_bytes([k[0], k[1]]))
+                        .collect(),
+                )
+            }
+            1 => {
+                let mut ids = Vec::new();
+                let mut rest = data;
+                while let Some((&len, tail)) = rest.split_first() {
+                    if len == 0 || tail.len() < len as usize {
+                        return Err("malformed alpn");
+                    }
+                    let (id, tail) = tail.split_at(len as usize);
+                    ids.push(id.to_vec());
+                    rest = tail;
+                }
+                if ids.is_empty() {
+                    return Err("malformed alpn");
+                }
+                SvcParam::Alpn(ids)
+            }
+            2 if data.is_empty() => SvcParam::NoDefaultAlpn,
+            2 => return Err("no-default-alpn has a value"),
+            3 => match data {
+                [a, b] => SvcParam::Port(u16::from_be_bytes([*a, *b])),
+                _ => return Err("malformed port"),
+            },
+            4 => {
+                if data.is_empty() || !data.len().is_multiple_of(4) {
+                    return Err("malformed ipv4hint");
+                }
+                SvcParam::Ipv4Hint(
+                    data.chunks(4)
+                        .map(|a| Ipv4Addr::new(a[0], a[1], a[2], a[3]))
+                        .collect(),
+                )
+            }
+            5 => SvcParam::Ech(data.to_vec()),
+            6 => {
+                if data.is_empty() || !data.len().is_multiple_of(16) {
+                    return Err("malformed ipv6hint");
+                }
+                SvcParam::Ipv6Hint(
+                    data.chunks(16)
+                        .map(|a| {
+                            let mut octets = [0u8; 16];
+                            octets.copy_from_slice(a);
+                            Ipv6Addr::from(octets)
+                        })
+                        .collect(),
+                )
+            }
+            _ => SvcParam::Unknown {
+                key,
+                value: data.to_vec(),
+            },
+        };
+        Ok(param)
+    }
+
+    fn data(&self) -> Vec<u8> {
+        match self {
+            SvcParam::Mandatory(keys) => keys.iter().flat_map(|k| k.to_be_bytes()).collect(),
+            SvcParam::Alpn(ids) => {
+                let mut data = Vec::new();
+                for id in ids {
+                    data.push(id.len() as u8);
+                    data.extend_from_slice(id);
+                }
+                data
+            }
+            SvcParam::NoDefaultAlpn => Vec::new(),
+            SvcParam::Port(port) => port.to_be_bytes().to_vec(),
+            SvcParam::Ipv4Hint(addrs) => addrs.iter().flat_map(|a| a.octets()).collect(),
+            SvcParam::Ech(data) | SvcParam::Unknown { value: data, .. } => data.clone(),
+            SvcParam::Ipv6Hint(addrs) => addrs.iter().flat_map(|a| a.octets()).collect(),
+        }
+    }
+
+    /// a parameter from its presentation format, `value` with the character-string
+    /// escapes already resolved. `Err` holds what is wrong with it
+    fn from_presentation(key: &str, value: Option<&[u8]>) -> Result<SvcParam, &'static str> {
+        let key = key_from_name(key).ok_or("unknown key")?;
+        if key == 2 {
+            return match value {
+                None | Some([]) => Ok(SvcParam::NoDefaultAlpn),
+                Some(_) => Err("no-default-alpn has a value"),
+            };
+        }
+        let Some(value) = value else {
+            return match key {
+                0..=6 => Err("missing value"),
+                _ => Ok(SvcParam::Unknown {
+                    key,
+                    value: Vec::new(),
+                }),
+            };
+        };
+        let text = || std::str::from_utf8(value).map_err(|_| "invalid value");
+
+        let param = match key {
+            0 => SvcParam::Mandatory(
+                split_value_list(value)
+                    .iter()
+                    .map(|name| {
+                        std::str::from_utf8(name)
+                            .ok()
+                            .and_then(key_from_name)
+                            .ok_or("unknown key in mandatory")
+                    })
+                    .collect::<Result<_, _>>()?,
+            ),
+            1 => {
+                let ids = split_value_list(value);
+                if ids.iter().any(|id| id.is_empty() || id.len() > 255) {
+                    return Err("malformed alpn");
+                }
+                SvcParam::Alpn(ids)
+            }
+            3 => SvcParam::Port(text()?.parse().map_err(|_| "malformed port")?),
+            4 => SvcParam::Ipv4Hint(
+                text()?
+                    .split(',')
+                    .map(|a| a.parse().map_err(|_| "malformed ipv4hint"))
+                    .collect::<Result<_, _>>()?,
+            ),
+            5 => SvcParam::Ech(base64_decode(text()?).ok_or("malformed ech")?),
+            6 => SvcParam::Ipv6Hint(
+                text()?
+                    .split(',')
+                    .map(|a| a.parse().map_err(|_| "malformed ipv6hint"))
+                    .collect::<Result<_, _>>()?,
+            ),
+            _ => SvcParam::Unknown {
+                key,
+                value: value.to_vec(),
+            },
+        };
+        Ok(param)
+    }
+}
+
+impl fmt::Display for SvcParam {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", key_name(self.key()))?;
+        match self {
+            SvcParam::Mandatory(keys) => {
+                let names: Vec<String> = keys.iter().map(|k| key_name(*k)).collect();
+                write!(f, "={}", names.join(","))
+            }
+            SvcParam::Alpn(ids) => {
+                // commas and backslashes inside an id are escaped once for the list, the
+                // character-string escapes the backslashes once more (RFC 9460 A.1)
+                let mut list = Vec::new();
+                for (i, id) in ids.iter().enumerate() {
+                    if i > 0 {
+                        list.push(b',');
+                    }
+                    for &b in id {
+                        if b == b',' || b == b'\\' {
+                            list.push(b'\\');
+                        }
+                        list.push(b);
+                    }
+                }
+                write!(f, "=")?;
+                write_character_string(f, &list)
+            }
+            SvcParam::NoDefaultAlpn => Ok(()),
+            SvcParam::Port(port) => write!(f, "={}", port),
+            SvcParam::Ipv4Hint(addrs) => {
+                let addrs: Vec<String> = addrs.iter().map(|a| a.to_string()).collect();
+                write!(f, "={}", addrs.join(","))
+            }
+            SvcParam::Ech(data) => write!(f, "={}", base64_encode(data)),
+            SvcParam::Ipv6Hint(addrs) => {
+                let addrs: Vec<String> = addrs.iter().map(|a| a.to_string()).collect();
+                write!(f, "={}", addrs.join(","))
+            }
+            SvcParam::Unknown { value, .. } => {
+                write!(f, "=")?;
+                write_character_string(f, value)
+            }
+        }
+    }
+}
+
+/// the name of a key in presentation format, keyNNNNN for the ones without one
+pub fn key_name(key: u16) -> String {
+    match key {
+        0 => "mandatory".to_string(),
+        1 => "alpn".to_string(),
+        2 => "no-default-alpn".to_string(),
+        3 => "port".to_string(),
+        4 => "ipv4hint".to_string(),
+        5 => "ech".to_string(),
+        6 => "ipv6hint".to_string(),
+        _ => format!("key{}", key),
+    }
+}
+
+fn key_from_name(name: &str) -> Option<u16> {
+    let key = match name {
+        "mandatory" => 0,
+        "alpn" => 1,
+        "no-default-alpn" => 2,
+        "port" => 3,
+        "ipv4hint" => 4,
+        "ech" => 5,
+        "ipv6hint" => 6,
+        // keyNNNNN, without leading zeros and not the reserved 65535
+        _ => {
+            let digits = name.strip_prefix("key")?;
+            if digits.is_empty()
+                || (digits.len() > 1 && digits.starts_with('0'))
+                || !digits.bytes().all(|b| b.is_ascii_digit())
+            {
+                return None;
+            }
+            digits.parse().ok().filter(|&key| key != 65535)?
+        }
+    };
+    Some(key)
+}
+
+/// splits a comma separated value-list, \, and \\ stand for a literal comma and backslash
+fn split_value_list(value: &[u8]) -> Vec<Vec<u8>> {
+    let mut items = vec![Vec::new()];
+    let mut bytes = value.iter();
+    while let Some(&b) = bytes.next() {
+        match b {
+            b'\\' => {
+                if let Some(&next) = bytes.next() {
+                    items.last_mut().unwrap().push(next);
+                }
+            }
+            b',' => items.push(Vec::new()),
+            _ => items.last_mut().unwrap().push(b),
+        }
+    }
+    items
+}
+
+/// what is wrong with `params`, if anything: the keys have to be strictly increasing
+/// and the mandatory ones present, but neither mandatory itself nor listed twice
+fn check(params: &[SvcParam]) -> Option<&'static str> {
+    if params.windows(2).any(|w| w[0].key() >= w[1].key()) {
+        return Some("keys out of order or repeated");
+    }
+    if let Some(SvcParam::Mandatory(keys)) = params.first() {
+        if keys.windows(2).any(|w| w[0] >= w[1]) {
+            return Some("mandatory keys out of order or repeated");
+        }
+        if keys.contains(&0) {
+            return Some("mandatory lists itself");
+        }
+        if !keys
+            .iter()
+            .all(|k| params.iter().any(|param| param.key() == *k))
+        {
+            return Some("mandatory key missing");
+        }
+    }
+    None
+}
+
+/// reads the RDATA of an SVCB or HTTPS record ending at `end`
+pub(crate) fn read<T: PacketBuffer>(
+    packet: &mut T,
+    end: usize,
+    qtype: u16,
+) -> Result<(u16, String, Vec<SvcParam>), DnsError> {
+    let priority = packet.read_u16()?;
+    let mut target = String::new();
+    packet.read_qname(&mut target)?;
+
+    let mut params = Vec::new();
+    while packet.pos() < end {
+        let offset = packet.pos();
+        let key = packet.read_u16()?;
+        let len = packet.read_u16()? as usize;
+        if packet.pos() + len > end {
+            return Err(DnsError::BadRdata {
+                offset,
+                qtype,
+                reason: "SvcParam overruns the record",
+            });
+        }
+        let data = packet.get_range(packet.pos(), len)?;
+        let param = SvcParam::parse(key, data).map_err(|reason| DnsError::BadRdata {
+            offset,
+            qtype,
+            reason,
+        })?;
+        packet.step(len)?;
+        params.push(param);
+    }
+
+    if let Some(reason) = check(&params) {
+        return Err(DnsError::BadRdata {
+            offset: end,
+            qtype,
+            reason,
+        });
+    }
+    Ok((priority, target, params))
+}
+
+pub(crate) fn write<T: PacketBuffer>(
+    packet: &mut T,
+    priority: u16,
+    target: &str,
+    params: &[SvcParam],
+    qtype: u16,
+) -> Result<(), DnsError> {
+    if let Some(reason) = check(params) {
+        return Err(DnsError::BadRdata {
+            offset: packet.pos(),
+            qtype,
+            reason,
+        });
+    }
+
+    packet.write_u16(priority)?;
+    packet.write_qname_uncompressed(target)?;
+    for param in params {
+        let data = param.data();
+        packet.write_u16(param.key())?;
+        packet.write_u16(data.len() as u16)?;
+        for b in data {
+            packet.write(b)?;
+        }
+    }
+    Ok(())
+}
+
+/// parses SvcParams in presentation format, like `alpn=h2,h3 port=8443`.
+/// they may come in any order but are returned ordered by key
+pub fn parse_svc_params(text: &str) -> Result<Vec<SvcParam>, DnsError> {
+    let bytes = text.as_bytes();
+    let mut params = Vec::new();
+    let mut i = 0;
+    loop {
+        while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        if i == bytes.len() {
+            break;
+        }
+
+        let start = i;
+        while i < bytes.len() && !bytes[i].is_ascii_whitespace() && bytes[i] != b'=' {
+            i += 1;
+        }
+        let key = &text[start..i];
+        let value = if bytes.get(i) == Some(&b'=') {
+            i += 1;
+            Some(read_value(bytes, &mut i)?)
+        } else {
+            None
+        };
+
+        let param = SvcParam::from_presentation(key, value.as_deref()).map_err(|reason| {
+            DnsError::BadText {
+                offset: start,
+                reason,
+            }
+        })?;
+        params.push(param);
+    }
+
+    params.sort_by_key(SvcParam::key);
+    if let Some(SvcParam::Mandatory(keys)) = params.first_mut() {
+        keys.sort_unstable();
+    }
+    match check(&params) {
+        Some(reason) => Err(DnsError::BadText { offset: 0, reason }),
+        None => Ok(params),
+    }
+}
+
+/// a value, quoted or not, with the escapes resolved. leaves `i` behind it
+fn read_value(bytes: &[u8], i: &mut usize) -> Result<Vec<u8>, DnsError> {
+    let start = *i;
+    let quoted = bytes.get(*i) == Some(&b'"');
+    if quoted {
+        *i += 1;
+    }
+
+    let mut value = Vec::new();
+    loop {
+        let Some(&b) = bytes.get(*i) else {
+            if quoted {
+                return Err(DnsError::BadText {
+                    offset: start,
+                    reason: "unterminated quoted string",
+                });
+            }
+            return Ok(value);
+        };
+        *i += 1;
+        match b {
+            b'"' if quoted => return Ok(value),
+            b'\\' => {
+                let (b, len) = parse_escape(&bytes[*i..]).ok_or(DnsError::BadText {
+                    offset: *i - 1,
+                    reason: "invalid escape",
+                })?;
+                value.push(b);
+                *i += len;
+            }
+            _ if !quoted && b.is_ascii_whitespace() => {
+                *i -= 1;
+                return Ok(value);
+            }
+            _ => value.push(b),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{packet::VectorPacketBuffer, record::DnsRecord, zonefile::parse_zone};
+
+    /// RDATA with priority 1, the root as target and `params` as (key, value) pairs
+    fn rdata(params: &[(u16, &[u8])]) -> Vec<u8> {
+        let mut bytes = vec![0, 1, 0];
+        for (key, value) in params {
+            bytes.extend_from_slice(&key.to_be_bytes());
+            bytes.extend_from_slice(&(value.len() as u16).to_be_bytes());
+            bytes.extend_from_slice(value);
+        }
+        bytes
+    }
+
+    fn parse(params: &[(u16, &[u8])]) -> Result<Vec<SvcParam>, DnsError> {
+        let bytes = rdata(params);
+        read(&mut VectorPacketBuffer::from_bytes(&bytes), bytes.len(), 65).map(|(_, _, p)| p)
+    }
+
+    fn reason(result: Result<Vec<SvcParam>, DnsError>) -> &'static str {
+        match result {
+            Err(DnsError::BadRdata { reason, .. }) => reason,
+            other => panic!("expected BadRdata, got {:?}", other),
+        }
+    }
+
+    /// example.com. 300 IN HTTPS with `rdata`, as it comes off the wire
+    fn https_wire(rdata: &[u8]) -> Vec<u8> {
+        let mut bytes = b"\x07example\x03com\x00\x00\x41\x00\x01\x00\x00\x01\x2c".to_vec();
+        bytes.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+        bytes.extend_from_slice(rdata);
+        bytes
+    }
+
+    /// reads `bytes`, writes the record out as text, parses that and writes it back
+    fn round_trip(bytes: &[u8]) -> String {
+        let record = DnsRecord::read(&mut VectorPacketBuffer::from_bytes(bytes)).unwrap();
+        let text = record.to_string();
+        let parsed = parse_zone(&text, "").unwrap();
+        assert_eq!(parsed, vec![record]);
+
+        let mut buffer = VectorPacketBuffer::uncompressed();
+        parsed[0].write(&mut buffer).unwrap();
+        assert_eq!(buffer.as_bytes(), bytes);
+        text
+    }
+
+    #[test]
+    fn rejects_keys_out_of_order_or_repeated() {
+        assert_eq!(
+            reason(parse(&[(3, &[0, 53]), (1, b"\x02h2")])),
+            "keys out of order or repeated"
+        );
+        assert_eq!(
+            reason(parse(&[(3, &[0, 53]), (3, &[0, 54])])),
+            "keys out of order or repeated"
+        );
+        assert!(parse(&[(1, b"\x02h2"), (3, &[0, 53])]).is_ok());
+    }
+
+    #[test]
+    fn rejects_bad_mandatory_lists() {
+        assert_eq!(
+            reason(parse(&[(0, &[0, 0]), (3, &[0, 53])])),
+            "mandatory lists itself"
+        );
+        assert_eq!(
+            reason(parse(&[(0, &[0, 4]), (3, &[0, 53])])),
+            "mandatory key missing"
+        );
+        assert_eq!(
+            reason(parse(&[(0, &[0, 3, 0, 3]), (3, &[0, 53])])),
+            "mandatory keys out of order or repeated"
+        );
+        assert_eq!(
+            parse(&[(0, &[0, 3]), (3, &[0, 53])]).unwrap(),
+            vec![SvcParam::Mandatory(vec![3]), SvcParam::Port(53)]
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_values() {
+        assert_eq!(reason(parse(&[(4, &[192, 0, 2])])), "malformed ipv4hint");
+        assert_eq!(reason(parse(&[(4, &[])])), "malformed ipv4hint");
+        assert_eq!(reason(parse(&[(6, &[0; 15])])), "malformed ipv6hint");
+        assert_eq!(reason(parse(&[(6, &[0; 17])])), "malformed ipv6hint");
+        assert_eq!(reason(parse(&[(1, &[])])), "malformed alpn");
+        assert_eq!(reason(parse(&[(1, b"\x00")])), "malformed alpn");
+        assert_eq!(reason(parse(&[(1, b"\x03h2")])), "malformed alpn");
+        assert_eq!(reason(parse(&[(3, &[0])])), "malformed port");
+        assert_eq!(reason(parse(&[(2, b"x")])), "no-default-alpn has a value");
+    }
+
+    #[test]
+    fn service_mode_round_trip() {
+        let bytes = https_wire(&rdata(&[
+            (1, b"\x02h2\x02h3"),
+            (2, b""),
+            (3, &[0x20, 0xfb]),
+            (5, &[0xde, 0xad, 0xbe, 0xef]),
+        ]));
+        assert_eq!(
+            round_trip(&bytes),
+            "example.com.\t300\tIN\tHTTPS\t1 . alpn=\"h2,h3\" no-default-alpn port=8443 ech=3q2+7w=="
+        );
+    }
+
+    #[test]
+    fn alias_mode_round_trip() {
+        let mut rdata = vec![0, 0];
+        rdata.extend_from_slice(b"\x03svc\x07example\x03net\x00");
+        assert_eq!(
+            round_trip(&https_wire(&rdata)),
+            "example.com.\t300\tIN\tHTTPS\t0 svc.example.net."
+        );
+    }
+}
@@ -0,0 +1,32 @@
+use std::io::{Read, Write};
+
+// DNS over TCP prefixes every message with its length as a 16 bit
+// big endian integer (RFC 1035 4.2.2)
+
+/// reads one length prefixed message from the stream
+pub fn read_message<S: Read>(stream: &mut S) -> std::io::Result<Vec<u8>> {
+    let mut len = [0u8; 2];
+    stream.read_exact(&mut len)?;
+    let len = u16::from_be_bytes(len) as usize;
+
+    let mut message = vec![0u8; len];
+    stream.read_exact(&mut message)?;
+    Ok(message)
+}
+
+/// writes `message` with its length prefix in a single write,
+/// some servers choke on the prefix arriving in its own segment (RFC 7766 8)
+pub fn write_message<S: Write>(stream: &mut S, message: &[u8]) -> std::io::Result<()> {
+    let len = u16::try_from(message.len()).map_err(|_| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "Message too long for TCP framing",
+        )
+    })?;
+
+    let mut framed = Vec::with_capacity(message.len() + 2);
+    framed.extend_from_slice(&len.to_be_bytes());
+    framed.extend_from_slice(message);
+    stream.write_all(&framed)?;
+    stream.flush()
+}
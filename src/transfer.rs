@@ -0,0 +1,417 @@
+use std::net::IpAddr;
+
+use dns::{
+    dnsmsg::DnsPackets,
+    header::{ResultCode, OPCODE_QUERY},
+    packet::MAX_MESSAGE_SIZE,
+    question::QueryType,
+    record::{serial_newer, DnsRecord},
+};
+
+use crate::{server::Server, zone::Zone};
+
+/// room left in every message for the header, the question and compression gone wrong
+const MESSAGE_OVERHEAD: usize = 1024;
+
+/// the networks allowed to do something, nobody unless configured
+#[derive(Debug, Default, Clone)]
+pub struct Acl {
+    networks: Vec<(IpAddr, u8)>,
+}
+
+impl Acl {
+    /// allows `network`, an address with an optional prefix length like 192.0.2.0/24
+    pub fn allow(&mut self, network: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let (addr, prefix) = match network.split_once('/') {
+            Some((addr, prefix)) => (addr.parse::<IpAddr>()?, Some(prefix.parse::<u8>()?)),
+            None => (network.parse::<IpAddr>()?, None),
+        };
+        let bits = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = prefix.unwrap_or(bits);
+        if prefix > bits {
+            return Err(format!("prefix length of {} is too long", network).into());
+        }
+        self.networks.push((addr, prefix));
+        Ok(())
+    }
+
+    pub fn allows(&self, addr: IpAddr) -> bool {
+        let addr = unmapped(addr);
+        self.networks
+            .iter()
+            .any(|&(network, prefix)| match (network, addr) {
+                (IpAddr::V4(network), IpAddr::V4(addr)) => {
+                    let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+                    u32::from(network) & mask == u32::from(addr) & mask
+                }
+                (IpAddr::V6(network), IpAddr::V6(addr)) => {
+                    let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+                    u128::from(network) & mask == u128::from(addr) & mask
+                }
+                _ => false,
+            })
+    }
+}
+
+/// `addr` as the IPv4 address it is, IPv4 clients of a dual stack socket
+/// show up as mapped IPv6 addresses
+pub fn unmapped(addr: IpAddr) -> IpAddr {
+    match addr {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(addr, IpAddr::V4),
+        IpAddr::V4(_) => addr,
+    }
+}
+
+/// whether `request` asks for a zone transfer
+pub fn is_transfer(request: &DnsPackets) -> bool {
+    request.header.opcode == OPCODE_QUERY
+        && request
+            .questions
+            .first()
+            .is_some_and(|question| matches!(question.qtype, QueryType::AXFR | QueryType::IXFR))
+}
+
+/// the messages answering a zone transfer request from `peer` over TCP. AXFR gets the
+/// whole zone bracketed by its SOA (RFC 5936 2.2), IXFR the changes since the serial
+/// of the client or the whole zone when we dont remember that far back (RFC 1995 4)
+pub fn answer_tcp(request: &DnsPackets, server: &Server, peer: IpAddr) -> Vec<DnsPackets> {
+    let mut response = DnsPackets::response_to(request);
+    response.header.recursion_desired = false;
+    let zone = match transfer_zone(request, server, peer) {
+        Ok(zone) => zone,
+        Err(rescode) => {
+            response.header.rescode = rescode;
+            return vec![response];
+        }
+    };
+    response.header.authorative_answer = true;
+
+    let records = match request.questions[0].qtype {
+        QueryType::IXFR => match client_serial(request) {
+            Some(serial) => incremental(&zone, serial),
+            // the client has to tell us what it has (RFC 1995 3)
+            None => {
+                response.header.rescode = ResultCode::FormerR;
+                return vec![response];
+            }
+        },
+        _ => full(&zone),
+    };
+    split(response, records)
+}
+
+/// the answer to a zone transfer request over UDP. there is no AXFR over UDP, an IXFR
+/// only gets our SOA which is enough for the client to tell whether it has to come
+/// back over TCP (RFC 1995 2)
+pub fn answer_udp(request: &DnsPackets, server: &Server, peer: IpAddr) -> DnsPackets {
+    let mut response = DnsPackets::response_to(request);
+    response.header.recursion_desired = false;
+    if request.questions[0].qtype == QueryType::AXFR {
+        response.header.rescode = ResultCode::FormerR;
+        return response;
+    }
+    match transfer_zone(request, server, peer) {
+        Ok(zone) => {
+            response.header.authorative_answer = true;
+            response.answers.push(zone.soa().clone());
+        }
+        Err(rescode) => response.header.rescode = rescode,
+    }
+    response
+}
+
+/// the zone a transfer request is for, if `peer` may have it. a copy, so the zone
+/// can be replaced while the transfer is still going
+fn transfer_zone(request: &DnsPackets, server: &Server, peer: IpAddr) -> Result<Zone, ResultCode> {
+    let question = request.questions.first().ok_or(ResultCode::FormerR)?;
+    let zones = server.zones();
+    let zone = zones.get(&question.name).ok_or(ResultCode::Refused)?;
+    if !server.transfer_acl.allows(peer) {
+        eprintln!("refused a transfer of {} to {}", question.name, peer);
+        return Err(ResultCode::Refused);
+    }
+    if zones.is_expired(&question.name) {
+        return Err(ResultCode::ServFail);
+    }
+    Ok(zone.clone())
+}
+
+/// the serial of the SOA an IXFR request carries in its authority section
+fn client_serial(request: &DnsPackets) -> Option<u32> {
+    request.authoritiees.iter().find_map(|rec| match rec {
+        DnsRecord::SOA { serial, .. } => Some(*serial),
+        _ => None,
+    })
+}
+
+/// the whole zone, starting and ending with its SOA
+fn full(zone: &Zone) -> Vec<DnsRecord> {
+    let mut records = vec![zone.soa().clone()];
+    records.extend(zone.records().cloned());
+    records.push(zone.soa().clone());
+    records
+}
+
+/// the changes since version `serial` of the zone: our SOA, then for every change the old
+/// SOA and what was removed followed by the new SOA and what was added, then our SOA again
+fn incremental(zone: &Zone, serial: u32) -> Vec<DnsRecord> {
+    // the client is up to date, or ahead of us
+    if !serial_newer(zone.serial(), serial) {
+        return vec![zone.soa().clone()];
+    }
+    let Some(diffs) = zone.changes_since(serial) else {
+        return full(zone);
+    };
+
+    let mut records = vec![zone.soa().clone()];
+    for diff in diffs {
+        records.push(diff.old_soa.clone());
+        records.extend(diff.removed.iter().cloned());
+        records.push(diff.new_soa.clone());
+        records.extend(diff.added.iter().cloned());
+    }
+    records.push(zone.soa().clone());
+    records
+}
+
+/// spreads `records` over as many messages as it takes, only the first one
+/// repeats the question (RFC 5936 2.2.1)
+fn split(first: DnsPackets, records: Vec<DnsRecord>) -> Vec<DnsPackets> {
+    let mut next = first.clone();
+    next.questions.clear();
+
+    let mut messages = vec![first];
+    let mut size = 0;
+    for rec in records {
+        // the uncompressed size, compression can only make it smaller
+        let rec_size = rec.canonical_form(rec.ttl()).map_or(0, |wire| wire.len());
+        let current = messages.len() - 1;
+        if size + rec_size > MAX_MESSAGE_SIZE - MESSAGE_OVERHEAD
+            && !messages[current].answers.is_empty()
+        {
+            messages.push(next.clone());
+            size = 0;
+        }
+        size += rec_size;
+        if let Some(message) = messages.last_mut() {
+            message.answers.push(rec);
+        }
+    }
+    messages
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::RwLock;
+
+    use dns::{packet::VectorPacketBuffer, zonefile::parse_zone};
+
+    use super::*;
+    use crate::{notify::Notify, resolver::Resolver, zone::Catalog};
+
+    const PEER: IpAddr = IpAddr::V4(std::net::Ipv4Addr::new(192, 0, 2, 10));
+
+    /// version `serial` of example, www moves with every one
+    fn version(serial: u32) -> Zone {
+        let text = format!(
+            "$TTL 300\n\
+             @ SOA ns hostmaster {0} 3600 600 86400 300\n\
+             @ NS ns\n\
+             ns A 192.0.2.53\n\
+             www A 192.0.2.{0}\n",
+            serial
+        );
+        Zone::new("example", parse_zone(&text, "example").unwrap()).unwrap()
+    }
+
+    /// a server that went through versions 1 to 3 of example and lets `PEER` transfer it
+    fn server() -> Server {
+        let mut zones = Catalog::new();
+        for serial in 1..=3 {
+            zones.insert(version(serial));
+        }
+        let mut transfer_acl = Acl::default();
+        transfer_acl.allow("192.0.2.0/24").unwrap();
+        Server {
+            zones: RwLock::new(zones),
+            resolver: Resolver::with_root_hints(Vec::new(), 53),
+            transfer_acl,
+            notify: Notify::default(),
+        }
+    }
+
+    fn ixfr(serial: Option<u32>) -> DnsPackets {
+        let request = DnsPackets::query(1, "example", QueryType::IXFR);
+        match serial {
+            Some(serial) => request.with_authority(version(serial).soa().clone()),
+            None => request,
+        }
+    }
+
+    /// the answers of all messages, in order
+    fn answers(messages: Vec<DnsPackets>) -> Vec<DnsRecord> {
+        messages.into_iter().flat_map(|m| m.answers).collect()
+    }
+
+    fn www(serial: u8) -> DnsRecord {
+        DnsRecord::A {
+            domain: "www.example".to_string(),
+            addr: [192, 0, 2, serial].into(),
+            ttl: 300,
+        }
+    }
+
+    #[test]
+    fn allows_networks_by_prefix() {
+        let mut acl = Acl::default();
+        assert!(!acl.allows("192.0.2.1".parse().unwrap()));
+
+        acl.allow("192.0.2.0/24").unwrap();
+        acl.allow("198.51.100.7").unwrap();
+        acl.allow("2001:db8::/32").unwrap();
+        for allowed in [
+            "192.0.2.1",
+            "192.0.2.255",
+            "198.51.100.7",
+            "2001:db8:1::1",
+            // an IPv4 client on a dual stack socket
+            "::ffff:192.0.2.9",
+        ] {
+            assert!(acl.allows(allowed.parse().unwrap()), "{}", allowed);
+        }
+        for refused in [
+            "192.0.3.1",
+            "198.51.100.8",
+            "2001:db9::1",
+            "::ffff:192.0.3.9",
+            // not mapped, just a v6 address with the same bits at the end
+            "::c000:201",
+        ] {
+            assert!(!acl.allows(refused.parse().unwrap()), "{}", refused);
+        }
+
+        acl.allow("0.0.0.0/0").unwrap();
+        assert!(acl.allows("203.0.113.1".parse().unwrap()));
+        assert!(Acl::default().allow("192.0.2.0/33").is_err());
+        assert!(Acl::default().allow("2001:db8::/129").is_err());
+        assert!(Acl::default().allow("example").is_err());
+    }
+
+    #[test]
+    fn brackets_axfr_with_the_soa() {
+        let server = server();
+        let request = DnsPackets::query(1, "example", QueryType::AXFR);
+        let messages = answer_tcp(&request, &server, PEER);
+        assert_eq!(messages.len(), 1);
+        let message = &messages[0];
+        assert!(message.header.authorative_answer);
+        assert_eq!(message.header.rescode, ResultCode::NoError);
+        assert_eq!(message.questions.len(), 1);
+
+        let soa = version(3).soa().clone();
+        assert_eq!(message.answers.first(), Some(&soa));
+        assert_eq!(message.answers.last(), Some(&soa));
+        assert_eq!(message.answers.len(), 5);
+        assert!(message.answers.contains(&www(3)));
+
+        // only for those allowed, and only for our zones
+        let other = "203.0.113.1".parse().unwrap();
+        let refused = answer_tcp(&request, &server, other);
+        assert_eq!(refused[0].header.rescode, ResultCode::Refused);
+        assert!(refused[0].answers.is_empty());
+        let request = DnsPackets::query(1, "other", QueryType::AXFR);
+        let refused = answer_tcp(&request, &server, PEER);
+        assert_eq!(refused[0].header.rescode, ResultCode::Refused);
+    }
+
+    #[test]
+    fn splits_transfers_over_messages() {
+        let first = DnsPackets::response_to(&DnsPackets::query(1, "example", QueryType::AXFR));
+        let records: Vec<DnsRecord> = (0..2000)
+            .map(|i| DnsRecord::TXT {
+                domain: format!("host{}.example", i),
+                data: vec![vec![b'x'; 100]],
+                ttl: 300,
+            })
+            .collect();
+
+        let messages = split(first, records.clone());
+        assert!(messages.len() > 1);
+        assert_eq!(messages[0].questions.len(), 1);
+        for mut message in messages.clone() {
+            assert!(!message.answers.is_empty());
+            assert!(message.questions.len() <= 1);
+            let buffer = message.write_with_limit(MAX_MESSAGE_SIZE).unwrap();
+            let read =
+                DnsPackets::from_buffer(&mut VectorPacketBuffer::from_bytes(buffer.as_bytes()))
+                    .unwrap();
+            assert!(!read.header.truncated_msg);
+            assert_eq!(read.answers.len(), message.answers.len());
+        }
+        assert!(messages[1..].iter().all(|m| m.questions.is_empty()));
+        assert_eq!(answers(messages), records);
+    }
+
+    #[test]
+    fn sends_the_changes_since_the_client_serial() {
+        let server = server();
+        let soa = |serial| version(serial).soa().clone();
+
+        assert_eq!(
+            answers(answer_tcp(&ixfr(Some(1)), &server, PEER)),
+            [
+                soa(3),
+                soa(1),
+                www(1),
+                soa(2),
+                www(2),
+                soa(2),
+                www(2),
+                soa(3),
+                www(3),
+                soa(3),
+            ]
+        );
+        assert_eq!(
+            answers(answer_tcp(&ixfr(Some(2)), &server, PEER)),
+            [soa(3), soa(2), www(2), soa(3), www(3), soa(3)]
+        );
+
+        // up to date, only our SOA
+        assert_eq!(answers(answer_tcp(&ixfr(Some(3)), &server, PEER)), [soa(3)]);
+
+        // a version we dont remember gets the whole zone
+        let mut server = server;
+        server.zones = RwLock::new(Catalog::new());
+        server.zones.write().unwrap().insert(version(3));
+        let records = answers(answer_tcp(&ixfr(Some(2)), &server, PEER));
+        assert_eq!(records.len(), 5);
+        assert_eq!(
+            (records.first(), records.last()),
+            (Some(&soa(3)), Some(&soa(3)))
+        );
+        assert!(records.contains(&www(3)));
+    }
+
+    #[test]
+    fn ixfr_needs_the_client_soa() {
+        let messages = answer_tcp(&ixfr(None), &server(), PEER);
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].header.rescode, ResultCode::FormerR);
+        assert!(messages[0].answers.is_empty());
+    }
+
+    #[test]
+    fn answers_over_udp_with_the_soa_only() {
+        let server = server();
+        let request = DnsPackets::query(1, "example", QueryType::AXFR);
+        let response = answer_udp(&request, &server, PEER);
+        assert_eq!(response.header.rescode, ResultCode::FormerR);
+        assert!(response.answers.is_empty());
+
+        let response = answer_udp(&ixfr(Some(1)), &server, PEER);
+        assert_eq!(response.header.rescode, ResultCode::NoError);
+        assert!(response.header.authorative_answer);
+        assert_eq!(response.answers, [version(3).soa().clone()]);
+    }
+}
@@ -0,0 +1,802 @@
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+
+use dns::{
+    dnsmsg::{is_subdomain, parent, substitute_dname, DnsPackets},
+    header::ResultCode,
+    question::QueryType,
+    record::{serial_newer, DnsRecord},
+    zonefile::read_zone_file,
+};
+
+/// how many changes of a zone we remember for IXFR
+const MAX_JOURNAL: usize = 64;
+
+/// the change from one version of a zone to the next (RFC 1995 4)
+#[derive(Debug, Clone)]
+pub struct Diff {
+    pub old_soa: DnsRecord,
+    pub removed: Vec<DnsRecord>,
+    pub new_soa: DnsRecord,
+    pub added: Vec<DnsRecord>,
+}
+
+/// a zone we are authoritative for, held in memory
+#[derive(Debug, Clone)]
+pub struct Zone {
+    origin: String,
+    soa: DnsRecord,
+    /// records by owner name, glue below delegations included
+    records: HashMap<String, Vec<DnsRecord>>,
+    /// every name in the zone, including the empty non-terminals between
+    /// the origin and the owners (RFC 4592 2.2.2)
+    names: HashSet<String>,
+    /// the changes that led up to this version, oldest first
+    journal: Vec<Diff>,
+}
+
+impl Zone {
+    /// a zone from its records. there has to be exactly one SOA, at `origin`,
+    /// every record has to be at or below it and a CNAME has to be alone at its name
+    pub fn new(origin: &str, records: Vec<DnsRecord>) -> Result<Zone, Box<dyn std::error::Error>> {
+        let origin = origin.trim_end_matches('.').to_lowercase();
+        let mut soa = None;
+        let mut by_name: HashMap<String, Vec<DnsRecord>> = HashMap::new();
+        let mut names = HashSet::new();
+
+        for rec in records {
+            let owner = rec.domain().to_string();
+            if !is_subdomain(&owner, &origin) {
+                return Err(format!("{} is outside of zone {}", owner, origin).into());
+            }
+            if let DnsRecord::SOA { .. } = rec {
+                if owner != origin {
+                    return Err(format!("SOA at {} instead of the zone apex", owner).into());
+                }
+                if soa.replace(rec.clone()).is_some() {
+                    return Err(format!("more than one SOA for {}", origin).into());
+                }
+            }
+
+            let mut name = owner.as_str();
+            while names.insert(name.to_string()) && name != origin {
+                name = parent(name);
+            }
+            by_name.entry(owner).or_default().push(rec);
+        }
+
+        let soa = soa.ok_or_else(|| format!("no SOA for {}", origin))?;
+
+        // an alias cant have data of its own besides what DNSSEC needs (RFC 2181 10.1)
+        for (owner, records) in &by_name {
+            let cnames = records.iter().filter(|rec| rec.qtype() == QueryType::CNAME);
+            let others = records.iter().filter(|rec| {
+                !matches!(
+                    rec,
+                    DnsRecord::CNAME { .. } | DnsRecord::RRSIG { .. } | DnsRecord::NSEC { .. }
+                )
+            });
+            match (cnames.count(), others.count()) {
+                (0, _) | (1, 0) => {}
+                (1, _) => return Err(format!("CNAME and other data at {}", owner).into()),
+                _ => return Err(format!("more than one CNAME at {}", owner).into()),
+            }
+        }
+
+        Ok(Zone {
+            origin,
+            soa,
+            records: by_name,
+            names,
+            journal: Vec::new(),
+        })
+    }
+
+    /// reads a zone from a master file
+    pub fn load(path: &Path, origin: &str) -> Result<Zone, Box<dyn std::error::Error>> {
+        Zone::new(origin, read_zone_file(path, origin)?)
+    }
+
+    pub fn origin(&self) -> &str {
+        &self.origin
+    }
+
+    pub fn soa(&self) -> &DnsRecord {
+        &self.soa
+    }
+
+    pub fn serial(&self) -> u32 {
+        soa_serial(&self.soa)
+    }
+
+    /// every record of the zone besides the SOA
+    pub fn records(&self) -> impl Iterator<Item = &DnsRecord> {
+        self.records
+            .values()
+            .flatten()
+            .filter(|rec| !matches!(rec, DnsRecord::SOA { .. }))
+    }
+
+    /// the changes from version `serial` up to this one, `None` if we dont remember
+    /// that far back
+    pub fn changes_since(&self, serial: u32) -> Option<&[Diff]> {
+        let start = self
+            .journal
+            .iter()
+            .position(|diff| soa_serial(&diff.old_soa) == serial)?;
+        Some(&self.journal[start..])
+    }
+
+    /// takes over the journal of `old`, the version of the zone this one replaces, and
+    /// adds the change between the two. history is lost when the serial didnt go up
+    fn succeed(&mut self, old: Zone) {
+        if !serial_newer(self.serial(), old.serial()) {
+            return;
+        }
+
+        // records compare by their wire form, which also tells TTL changes apart
+        let wire = |zone: &Zone| -> HashSet<Vec<u8>> {
+            zone.records()
+                .filter_map(|rec| rec.canonical_form(rec.ttl()).ok())
+                .collect()
+        };
+        let (old_wire, new_wire) = (wire(&old), wire(self));
+        let changed = |zone: &Zone, other: &HashSet<Vec<u8>>| -> Vec<DnsRecord> {
+            zone.records()
+                .filter(|rec| {
+                    rec.canonical_form(rec.ttl())
+                        .is_ok_and(|wire| !other.contains(&wire))
+                })
+                .cloned()
+                .collect()
+        };
+        let diff = Diff {
+            removed: changed(&old, &new_wire),
+            added: changed(self, &old_wire),
+            old_soa: old.soa.clone(),
+            new_soa: self.soa.clone(),
+        };
+
+        self.journal = old.journal;
+        self.journal.push(diff);
+        if self.journal.len() > MAX_JOURNAL {
+            self.journal.remove(0);
+        }
+    }
+
+    /// answers a question for a name in this zone following RFC 1034 4.3.2: the records
+    /// themselves, possibly synthesized from a wildcard, a referral to a delegated subzone,
+    /// or NODATA / NXDOMAIN with the SOA. CNAMEs are followed as long as they stay in the
+    /// zone, the chain goes into the answer section ahead of whatever it leads to
+    pub fn lookup(&self, qname: &str, qtype: QueryType) -> DnsPackets {
+        let mut name = qname.trim_end_matches('.').to_lowercase();
+        let mut packet = DnsPackets::new();
+        packet.header.response = true;
+        let mut seen = HashSet::new();
+
+        loop {
+            seen.insert(name.clone());
+            let detour = self.detour(&name, qtype);
+            if let Some(Detour::Referral(cut)) = detour {
+                self.refer(&mut packet, cut);
+                return packet;
+            }
+            // only the first owner of a chain decides whether we are authoritative
+            if packet.answers.is_empty() {
+                packet.header.authorative_answer = true;
+            }
+
+            if let Some(Detour::Dname(dname)) = detour {
+                match self.synthesize(&mut packet, &name, dname) {
+                    Some(host)
+                        if qtype != QueryType::CNAME
+                            && is_subdomain(&host, &self.origin)
+                            && !seen.contains(&host) =>
+                    {
+                        name = host;
+                        continue;
+                    }
+                    _ => return packet,
+                }
+            }
+
+            let Some(records) = self.node(&name) else {
+                packet.header.rescode = ResultCode::NXDomain;
+                packet.authoritiees = self.negative_soa();
+                return packet;
+            };
+
+            let answers: Vec<DnsRecord> = records
+                .iter()
+                .filter(|rec| covers(rec, qtype))
+                .cloned()
+                .collect();
+            if !answers.is_empty() {
+                self.add_addresses_for(&mut packet, &answers);
+                packet.answers.extend(answers);
+                return packet;
+            }
+
+            let target = records.iter().find_map(|rec| match rec {
+                DnsRecord::CNAME { host, .. } => Some(host.clone()),
+                _ => None,
+            });
+            let Some(target) = target else {
+                packet.authoritiees = self.negative_soa();
+                return packet;
+            };
+            packet.answers.extend(
+                records
+                    .iter()
+                    .filter(|rec| covers(rec, QueryType::CNAME))
+                    .cloned(),
+            );
+
+            // a chain leaving the zone is for the client to follow, one going
+            // in circles ends where it starts repeating
+            if !is_subdomain(&target, &self.origin) || seen.contains(&target) {
+                return packet;
+            }
+            name = target;
+        }
+    }
+
+    /// the records at `name`, synthesized from the source of synthesis when only a wildcard
+    /// matches (RFC 4592 3.3.1). empty for empty non-terminals, `None` if there is no such name
+    fn node(&self, name: &str) -> Option<Cow<'_, [DnsRecord]>> {
+        if self.names.contains(name) {
+            return Some(
+                self.records
+                    .get(name)
+                    .map_or(Cow::Borrowed(&[][..]), |records| {
+                        Cow::Borrowed(records.as_slice())
+                    }),
+            );
+        }
+
+        // the closest encloser is the longest existing ancestor, and only
+        // a wildcard right below it may match (RFC 4592 3.3.1)
+        let mut encloser = parent(name);
+        while !self.names.contains(encloser) {
+            if encloser.is_empty() {
+                return None;
+            }
+            encloser = parent(encloser);
+        }
+        let wildcard = if encloser.is_empty() {
+            "*".to_string()
+        } else {
+            format!("*.{}", encloser)
+        };
+        if !self.names.contains(&wildcard) {
+            return None;
+        }
+
+        let synthesized = self
+            .records
+            .get(&wildcard)
+            .into_iter()
+            .flatten()
+            .map(|rec| {
+                let mut rec = rec.clone();
+                rec.set_domain(name);
+                rec
+            })
+            .collect();
+        Some(Cow::Owned(synthesized))
+    }
+
+    /// what takes a query for `qname` elsewhere on the way down from the apex, whichever
+    /// comes first: a zone cut at or above it, the apex itself doesnt count, or a DNAME
+    /// strictly above it (RFC 6672 3.2). DS records live on the parent side of a cut
+    /// (RFC 4035 2.4)
+    fn detour<'a>(&'a self, qname: &'a str, qtype: QueryType) -> Option<Detour<'a>> {
+        let mut names = Vec::new();
+        let mut name = qname;
+        while name != self.origin && !name.is_empty() {
+            names.push(name);
+            name = parent(name);
+        }
+        names.push(&self.origin);
+
+        for name in names.into_iter().rev() {
+            let Some(records) = self.records.get(name) else {
+                continue;
+            };
+            if name != qname {
+                let dname = records
+                    .iter()
+                    .find(|rec| matches!(rec, DnsRecord::DNAME { .. }));
+                if let Some(dname) = dname {
+                    return Some(Detour::Dname(dname));
+                }
+            }
+            let has_ns = records.iter().any(|rec| rec.qtype() == QueryType::NS);
+            if has_ns && name != self.origin && !(name == qname && qtype == QueryType::DS) {
+                return Some(Detour::Referral(name));
+            }
+        }
+        None
+    }
+
+    /// answers for `name` below a DNAME with the DNAME and the CNAME it stands for,
+    /// returns the name the CNAME points at (RFC 6672 3.2)
+    fn synthesize(&self, packet: &mut DnsPackets, name: &str, dname: &DnsRecord) -> Option<String> {
+        let DnsRecord::DNAME {
+            domain,
+            target,
+            ttl,
+        } = dname
+        else {
+            return None;
+        };
+        packet.answers.extend(
+            self.records[domain]
+                .iter()
+                .filter(|rec| covers(rec, QueryType::DNAME))
+                .cloned(),
+        );
+
+        let Some(host) = substitute_dname(name, domain, target) else {
+            packet.header.rescode = ResultCode::YXDomain;
+            return None;
+        };
+        packet.answers.push(DnsRecord::CNAME {
+            domain: name.to_string(),
+            host: host.clone(),
+            ttl: *ttl,
+        });
+        Some(host)
+    }
+
+    /// points at the name servers of `cut`, with the addresses of those inside this zone
+    /// since nobody could look them up otherwise
+    fn refer(&self, packet: &mut DnsPackets, cut: &str) {
+        let ns: Vec<DnsRecord> = self.records[cut]
+            .iter()
+            .filter(|rec| rec.qtype() == QueryType::NS)
+            .cloned()
+            .collect();
+        self.add_addresses_for(packet, &ns);
+        packet.authoritiees.extend(ns);
+    }
+
+    /// additional section processing (RFC 1034 4.3.2 step 6): the addresses we have
+    /// of the hosts `records` point at
+    fn add_addresses_for(&self, packet: &mut DnsPackets, records: &[DnsRecord]) {
+        for rec in records {
+            let host = match rec {
+                DnsRecord::NS { host, .. }
+                | DnsRecord::MX { host, .. }
+                | DnsRecord::SRV { host, .. } => host,
+                _ => continue,
+            };
+            let addresses = self
+                .records
+                .get(host)
+                .into_iter()
+                .flatten()
+                .filter(|rec| matches!(rec, DnsRecord::A { .. } | DnsRecord::AAAA { .. }));
+            for rec in addresses {
+                if !packet.resources.contains(rec) {
+                    packet.resources.push(rec.clone());
+                }
+            }
+        }
+    }
+
+    /// the SOA that goes with negative answers, its TTL capped by the minimum
+    /// field since that is how long they may be cached (RFC 2308 3)
+    fn negative_soa(&self) -> Vec<DnsRecord> {
+        let mut soa = self.soa.clone();
+        if let DnsRecord::SOA { minimum, ttl, .. } = soa {
+            soa.set_ttl(minimum.min(ttl));
+        }
+        vec![soa]
+    }
+}
+
+/// how the way down to a name is cut short
+enum Detour<'a> {
+    /// a delegation to the name servers at this name
+    Referral(&'a str),
+    /// the DNAME record of an ancestor
+    Dname(&'a DnsRecord),
+}
+
+/// whether `rec` belongs in the answer to a question for `qtype`,
+/// signatures come along with the set they cover
+fn covers(rec: &DnsRecord, qtype: QueryType) -> bool {
+    match rec {
+        DnsRecord::RRSIG { type_covered, .. } => *type_covered == qtype,
+        _ => rec.qtype() == qtype,
+    }
+}
+
+pub fn soa_serial(soa: &DnsRecord) -> u32 {
+    match soa {
+        DnsRecord::SOA { serial, .. } => *serial,
+        _ => 0,
+    }
+}
+
+/// the zones we are authoritative for
+#[derive(Debug, Default, Clone)]
+pub struct Catalog {
+    zones: HashMap<String, Zone>,
+    /// secondary zones we couldnt refresh in time, we answer nothing from them
+    expired: HashSet<String>,
+}
+
+impl Catalog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// adds `zone`. one with the same origin is replaced, and what changed
+    /// between the two goes into the journal
+    pub fn insert(&mut self, mut zone: Zone) {
+        if let Some(old) = self.zones.remove(&zone.origin) {
+            zone.succeed(old);
+        }
+        self.expired.remove(&zone.origin);
+        self.zones.insert(zone.origin.clone(), zone);
+    }
+
+    /// the zone at `origin`
+    pub fn get(&self, origin: &str) -> Option<&Zone> {
+        self.zones
+            .get(origin.trim_end_matches('.').to_lowercase().as_str())
+    }
+
+    /// the deepest zone `qname` is in. a DS at the apex of one of our zones
+    /// is answered by its parent, if we have that one too
+    pub fn find(&self, qname: &str, qtype: QueryType) -> Option<&Zone> {
+        let qname = qname.trim_end_matches('.').to_lowercase();
+        let mut name = qname.as_str();
+        let mut skip_apex = qtype == QueryType::DS;
+        loop {
+            if let Some(zone) = self.zones.get(name) {
+                if !skip_apex || name.is_empty() {
+                    return Some(zone);
+                }
+                if let Some(parent_zone) = self.find(parent(name), QueryType::SOA) {
+                    return Some(parent_zone);
+                }
+                return Some(zone);
+            }
+            if name.is_empty() {
+                return None;
+            }
+            skip_apex = false;
+            name = parent(name);
+        }
+    }
+
+    /// the authoritative answer to a question, `None` if it isnt for any of our zones
+    pub fn lookup(&self, qname: &str, qtype: QueryType) -> Option<DnsPackets> {
+        let zone = self.find(qname, qtype)?;
+        if self.expired.contains(&zone.origin) {
+            let mut packet = DnsPackets::new();
+            packet.header.response = true;
+            packet.header.rescode = ResultCode::ServFail;
+            return Some(packet);
+        }
+        Some(zone.lookup(qname, qtype))
+    }
+
+    /// stops or resumes answering from the zone at `origin`, a secondary stops once its
+    /// copy is too old to be trusted (RFC 1034 4.3.5). inserting a fresh copy resumes too
+    pub fn set_expired(&mut self, origin: &str, expired: bool) {
+        let Some(origin) = self.get(origin).map(|zone| zone.origin.clone()) else {
+            return;
+        };
+        if expired {
+            self.expired.insert(origin);
+        } else {
+            self.expired.remove(&origin);
+        }
+    }
+
+    /// whether the zone at `origin` expired, see [`Catalog::set_expired`]
+    pub fn is_expired(&self, origin: &str) -> bool {
+        self.expired
+            .contains(origin.trim_end_matches('.').to_lowercase().as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dns::zonefile::parse_zone;
+
+    /// a zone at `origin` with a SOA, whose minimum is below its TTL, and one
+    /// name server besides the records in `body`
+    fn zone(origin: &str, body: &str) -> Zone {
+        let text = format!(
+            "$TTL 300\n\
+             @ SOA ns hostmaster 1 3600 600 86400 60\n\
+             @ NS ns\n\
+             ns A 192.0.2.53\n\
+             {}",
+            body
+        );
+        Zone::new(origin, parse_zone(&text, origin).unwrap()).unwrap()
+    }
+
+    /// owner and type of each record, enough to tell sections apart
+    fn owners(records: &[DnsRecord]) -> Vec<(&str, QueryType)> {
+        records
+            .iter()
+            .map(|rec| (rec.domain(), rec.qtype()))
+            .collect()
+    }
+
+    #[test]
+    fn answers_authoritatively() {
+        let zone = zone("example", "www A 192.0.2.1\nmail MX 10 www\n");
+        let packet = zone.lookup("WWW.example.", QueryType::A);
+        assert!(packet.header.authorative_answer);
+        assert_eq!(packet.header.rescode, ResultCode::NoError);
+        assert_eq!(owners(&packet.answers), [("www.example", QueryType::A)]);
+
+        // the address of the mail exchanger comes along
+        let packet = zone.lookup("mail.example", QueryType::MX);
+        assert_eq!(owners(&packet.answers), [("mail.example", QueryType::MX)]);
+        assert_eq!(owners(&packet.resources), [("www.example", QueryType::A)]);
+    }
+
+    #[test]
+    fn refers_to_delegated_zones_with_glue() {
+        let zone = zone("example", "sub NS ns.sub\nns.sub A 192.0.2.54\n");
+        for qname in ["sub.example", "www.sub.example"] {
+            let packet = zone.lookup(qname, QueryType::A);
+            assert!(!packet.header.authorative_answer);
+            assert_eq!(packet.header.rescode, ResultCode::NoError);
+            assert!(packet.answers.is_empty());
+            assert_eq!(
+                owners(&packet.authoritiees),
+                [("sub.example", QueryType::NS)]
+            );
+            assert_eq!(
+                owners(&packet.resources),
+                [("ns.sub.example", QueryType::A)]
+            );
+        }
+    }
+
+    #[test]
+    fn negative_answers_carry_the_soa() {
+        let zone = zone("example", "www A 192.0.2.1\n");
+        let expect_soa = |packet: &DnsPackets| {
+            assert!(packet.header.authorative_answer);
+            assert!(packet.answers.is_empty());
+            assert_eq!(owners(&packet.authoritiees), [("example", QueryType::SOA)]);
+            assert_eq!(packet.authoritiees[0].ttl(), 60);
+        };
+
+        let nxdomain = zone.lookup("nothing.example", QueryType::A);
+        assert_eq!(nxdomain.header.rescode, ResultCode::NXDomain);
+        expect_soa(&nxdomain);
+
+        let nodata = zone.lookup("www.example", QueryType::AAAA);
+        assert_eq!(nodata.header.rescode, ResultCode::NoError);
+        expect_soa(&nodata);
+    }
+
+    #[test]
+    fn answers_ds_from_the_parent_zone() {
+        let mut catalog = Catalog::new();
+        catalog.insert(zone(
+            "example",
+            "sub NS ns.sub\nsub DS 12345 8 2 0123456789abcdef\nns.sub A 192.0.2.54\n",
+        ));
+        catalog.insert(zone("sub.example", "www A 192.0.2.1\n"));
+
+        assert_eq!(
+            catalog.find("sub.example", QueryType::DS).unwrap().origin(),
+            "example"
+        );
+        assert_eq!(
+            catalog.find("sub.example", QueryType::A).unwrap().origin(),
+            "sub.example"
+        );
+        assert_eq!(
+            catalog
+                .find("www.sub.example", QueryType::DS)
+                .unwrap()
+                .origin(),
+            "sub.example"
+        );
+        assert!(catalog.find("other", QueryType::A).is_none());
+
+        let packet = catalog.lookup("sub.example", QueryType::DS).unwrap();
+        assert!(packet.header.authorative_answer);
+        assert_eq!(owners(&packet.answers), [("sub.example", QueryType::DS)]);
+
+        // without the parent the child answers, even if it cant know
+        let mut catalog = Catalog::new();
+        catalog.insert(zone("sub.example", ""));
+        assert_eq!(
+            catalog.find("sub.example", QueryType::DS).unwrap().origin(),
+            "sub.example"
+        );
+    }
+
+    #[test]
+    fn expired_zones_fail() {
+        let mut catalog = Catalog::new();
+        catalog.insert(zone("example", "www A 192.0.2.1\n"));
+        catalog.set_expired("example.", true);
+        assert!(catalog.is_expired("example"));
+        let packet = catalog.lookup("www.example", QueryType::A).unwrap();
+        assert_eq!(packet.header.rescode, ResultCode::ServFail);
+        assert!(packet.answers.is_empty());
+
+        // a fresh copy brings it back
+        catalog.insert(zone("example", "www A 192.0.2.1\n"));
+        assert!(!catalog.is_expired("example"));
+        let packet = catalog.lookup("www.example", QueryType::A).unwrap();
+        assert_eq!(packet.header.rescode, ResultCode::NoError);
+        assert_eq!(packet.answers.len(), 1);
+    }
+
+    #[test]
+    fn synthesizes_answers_from_wildcards() {
+        let zone = zone(
+            "example",
+            "* TXT \"wild\"\nwww A 192.0.2.1\nhost.empty A 192.0.2.2\n",
+        );
+
+        // whatever doesnt exist below the apex, however deep
+        for qname in ["anything.example", "deeper.anything.example"] {
+            let packet = zone.lookup(qname, QueryType::TXT);
+            assert!(packet.header.authorative_answer);
+            assert_eq!(packet.header.rescode, ResultCode::NoError);
+            assert_eq!(owners(&packet.answers), [(qname, QueryType::TXT)]);
+        }
+        let packet = zone.lookup("anything.example", QueryType::A);
+        assert_eq!(packet.header.rescode, ResultCode::NoError);
+        assert!(packet.answers.is_empty());
+        assert_eq!(owners(&packet.authoritiees), [("example", QueryType::SOA)]);
+
+        // names that exist, empty non-terminals too, keep the wildcard from matching
+        // them or anything below them
+        let packet = zone.lookup("www.example", QueryType::TXT);
+        assert_eq!(packet.header.rescode, ResultCode::NoError);
+        assert!(packet.answers.is_empty());
+        let packet = zone.lookup("empty.example", QueryType::TXT);
+        assert_eq!(packet.header.rescode, ResultCode::NoError);
+        assert!(packet.answers.is_empty());
+        let packet = zone.lookup("other.empty.example", QueryType::TXT);
+        assert_eq!(packet.header.rescode, ResultCode::NXDomain);
+        assert_eq!(owners(&packet.authoritiees), [("example", QueryType::SOA)]);
+
+        // asked for by name, the wildcard is a name like any other
+        let packet = zone.lookup("*.example", QueryType::TXT);
+        assert_eq!(owners(&packet.answers), [("*.example", QueryType::TXT)]);
+    }
+
+    #[test]
+    fn follows_cnames_within_the_zone() {
+        let zone = zone(
+            "example",
+            "first CNAME alias\n\
+             alias CNAME www\n\
+             www A 192.0.2.1\n\
+             away CNAME www.elsewhere.test.\n\
+             ping CNAME pong\n\
+             pong CNAME ping\n\
+             * CNAME www\n",
+        );
+
+        let packet = zone.lookup("first.example", QueryType::A);
+        assert!(packet.header.authorative_answer);
+        assert_eq!(packet.header.rescode, ResultCode::NoError);
+        assert_eq!(
+            owners(&packet.answers),
+            [
+                ("first.example", QueryType::CNAME),
+                ("alias.example", QueryType::CNAME),
+                ("www.example", QueryType::A),
+            ]
+        );
+
+        // the CNAME itself when that is what was asked for
+        let packet = zone.lookup("first.example", QueryType::CNAME);
+        assert_eq!(
+            owners(&packet.answers),
+            [("first.example", QueryType::CNAME)]
+        );
+
+        // leaving the zone, the rest of the chain is for the client to follow
+        let packet = zone.lookup("away.example", QueryType::A);
+        assert_eq!(
+            owners(&packet.answers),
+            [("away.example", QueryType::CNAME)]
+        );
+
+        // a loop ends where it comes back around
+        let packet = zone.lookup("ping.example", QueryType::A);
+        assert_eq!(
+            owners(&packet.answers),
+            [
+                ("ping.example", QueryType::CNAME),
+                ("pong.example", QueryType::CNAME),
+            ]
+        );
+
+        // a CNAME synthesized from a wildcard is followed too
+        let packet = zone.lookup("wild.example", QueryType::A);
+        assert_eq!(
+            owners(&packet.answers),
+            [
+                ("wild.example", QueryType::CNAME),
+                ("www.example", QueryType::A),
+            ]
+        );
+    }
+
+    #[test]
+    fn synthesizes_cnames_below_dnames() {
+        let label = "x".repeat(63);
+        let zone = zone(
+            "example",
+            &format!(
+                "old 600 DNAME new\n\
+                 www.new A 192.0.2.1\n\
+                 away DNAME elsewhere.test.\n\
+                 long DNAME {0}.{0}.{0}.\n",
+                label
+            ),
+        );
+
+        let packet = zone.lookup("www.old.example", QueryType::A);
+        assert!(packet.header.authorative_answer);
+        assert_eq!(packet.header.rescode, ResultCode::NoError);
+        assert_eq!(
+            owners(&packet.answers),
+            [
+                ("old.example", QueryType::DNAME),
+                ("www.old.example", QueryType::CNAME),
+                ("www.new.example", QueryType::A),
+            ]
+        );
+        assert_eq!(
+            packet.answers[1],
+            DnsRecord::CNAME {
+                domain: "www.old.example".to_string(),
+                host: "www.new.example".to_string(),
+                ttl: 600,
+            }
+        );
+
+        // out of the zone the chain is the client's to follow
+        let packet = zone.lookup("a.b.away.example", QueryType::A);
+        assert_eq!(
+            owners(&packet.answers),
+            [
+                ("away.example", QueryType::DNAME),
+                ("a.b.away.example", QueryType::CNAME),
+            ]
+        );
+
+        // the DNAME only redirects what is below it
+        let packet = zone.lookup("old.example", QueryType::DNAME);
+        assert_eq!(owners(&packet.answers), [("old.example", QueryType::DNAME)]);
+        let packet = zone.lookup("old.example", QueryType::A);
+        assert!(packet.answers.is_empty());
+        assert_eq!(owners(&packet.authoritiees), [("example", QueryType::SOA)]);
+
+        // a name the substitution would make too long
+        let packet = zone.lookup(&format!("{}.long.example", "y".repeat(62)), QueryType::A);
+        assert_eq!(packet.header.rescode, ResultCode::YXDomain);
+        assert_eq!(
+            owners(&packet.answers),
+            [("long.example", QueryType::DNAME)]
+        );
+    }
+}
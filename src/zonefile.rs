@@ -0,0 +1,868 @@
+use std::{
+    fmt, fs,
+    net::{Ipv4Addr, Ipv6Addr},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::{
+    dnssec::parse_timestamp,
+    encoding::{base32hex_decode, base64_decode, hex_decode},
+    packet::{PacketBuffer, VectorPacketBuffer},
+    question::QueryType,
+    record::{parse_character_strings, parse_escape, DnsRecord},
+    svcb::parse_svc_params,
+};
+
+// master files (RFC 1035 5.1)
+//
+//  <domain-name> [<TTL>] [<class>] <type> <RDATA>
+//  $ORIGIN <domain-name>
+//  $INCLUDE <file-name> [<domain-name>]
+//  $TTL <TTL>                                                    (RFC 2308 4)
+//
+//  a blank owner repeats the previous one, "@" stands for the origin and names
+//  without a trailing dot are relative to it. parentheses continue an entry over
+//  several lines, ";" starts a comment
+
+/// how deep $INCLUDE may nest, a file that includes itself would never end
+const MAX_INCLUDE_DEPTH: usize = 8;
+
+/// where and why a zone file couldnt be read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZoneError {
+    /// the file the error is in, none for text parsed from memory
+    pub file: Option<PathBuf>,
+    /// the line of the record, counting from 1
+    pub line: usize,
+    /// what is wrong with it
+    pub reason: String,
+}
+
+impl fmt::Display for ZoneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.file {
+            Some(file) => write!(f, "{}:{}: {}", file.display(), self.line, self.reason),
+            None => write!(f, "line {}: {}", self.line, self.reason),
+        }
+    }
+}
+
+impl std::error::Error for ZoneError {}
+
+/// parses the records of a zone file, relative names are completed with `origin`.
+/// $INCLUDE paths are taken relative to the working directory
+pub fn parse_zone(text: &str, origin: &str) -> Result<Vec<DnsRecord>, ZoneError> {
+    let mut parser = Parser::default();
+    parser.parse(text, &name(origin, "").map_err(error(None, 0))?, None, 0)?;
+    Ok(parser.records)
+}
+
+/// reads and parses a zone file, $INCLUDE paths are taken relative to its directory
+pub fn read_zone_file(path: &Path, origin: &str) -> Result<Vec<DnsRecord>, ZoneError> {
+    let text = fs::read_to_string(path).map_err(|e| ZoneError {
+        file: Some(path.to_path_buf()),
+        line: 0,
+        reason: e.to_string(),
+    })?;
+    let origin = name(origin, "").map_err(error(Some(path), 0))?;
+
+    let mut parser = Parser::default();
+    parser.parse(&text, &origin, Some(path), 0)?;
+    Ok(parser.records)
+}
+
+fn error(file: Option<&Path>, line: usize) -> impl Fn(String) -> ZoneError + '_ {
+    move |reason| ZoneError {
+        file: file.map(Path::to_path_buf),
+        line,
+        reason,
+    }
+}
+
+#[derive(Debug, Default)]
+struct Parser {
+    records: Vec<DnsRecord>,
+    /// set by $TTL
+    default_ttl: Option<u32>,
+    /// the last TTL written out, used when there is no $TTL (RFC 1035 5.1)
+    last_ttl: Option<u32>,
+}
+
+impl Parser {
+    /// `origin`, $TTL and the owner to repeat are local to each file, an $INCLUDE doesnt
+    /// change them for the file that includes it
+    fn parse(
+        &mut self,
+        text: &str,
+        origin: &str,
+        file: Option<&Path>,
+        depth: usize,
+    ) -> Result<(), ZoneError> {
+        let mut origin = origin.to_string();
+        let mut owner = None;
+
+        for entry in entries(text).map_err(|(line, reason)| error(file, line)(reason.into()))? {
+            let fail = error(file, entry.line);
+            let first = entry.tokens[0].as_str();
+            if entry.indented || !first.starts_with('$') {
+                let record = self.record(&entry, &origin, &mut owner).map_err(fail)?;
+                self.records.push(record);
+                continue;
+            }
+
+            let args = &entry.tokens[1..];
+            match (first.to_ascii_uppercase().as_str(), args) {
+                ("$ORIGIN", [name_text]) => origin = name(name_text, &origin).map_err(fail)?,
+                ("$TTL", [ttl]) => self.default_ttl = Some(parse_ttl(ttl).map_err(fail)?),
+                ("$INCLUDE", [path] | [path, _]) => {
+                    if depth >= MAX_INCLUDE_DEPTH {
+                        return Err(fail("$INCLUDE nested too deep".into()));
+                    }
+                    let include_origin = match args.get(1) {
+                        Some(name_text) => name(name_text, &origin).map_err(&fail)?,
+                        None => origin.clone(),
+                    };
+                    let path = match file.and_then(Path::parent) {
+                        Some(dir) => dir.join(path),
+                        None => PathBuf::from(path),
+                    };
+                    let text = fs::read_to_string(&path)
+                        .map_err(|e| fail(format!("{}: {}", path.display(), e)))?;
+                    let default_ttl = self.default_ttl;
+                    self.parse(&text, &include_origin, Some(&path), depth + 1)?;
+                    self.default_ttl = default_ttl;
+                }
+                _ => return Err(fail(format!("bad directive {}", first))),
+            }
+        }
+        Ok(())
+    }
+
+    fn record(
+        &mut self,
+        entry: &Entry,
+        origin: &str,
+        owner: &mut Option<String>,
+    ) -> Result<DnsRecord, String> {
+        let mut tokens = entry.tokens.iter().map(String::as_str);
+        let domain = if entry.indented {
+            owner.clone().ok_or("no previous owner to repeat")?
+        } else {
+            name(tokens.next().ok_or("missing owner")?, origin)?
+        };
+        *owner = Some(domain.clone());
+
+        // TTL and class may come in either order, and both are optional
+        let mut ttl = None;
+        let mut class = false;
+        let qtype = loop {
+            let token = tokens.next().ok_or("missing type")?;
+            if ttl.is_none() && token.starts_with(|c: char| c.is_ascii_digit()) {
+                ttl = Some(parse_ttl(token)?);
+            } else if !class && (token.eq_ignore_ascii_case("IN") || token == "CLASS1") {
+                class = true;
+            } else if let Some(qtype) = QueryType::from_name(token) {
+                break qtype;
+            } else if token.eq_ignore_ascii_case("CH")
+                || token.eq_ignore_ascii_case("HS")
+                || token.starts_with("CLASS")
+            {
+                return Err("only class IN is supported".into());
+            } else {
+                return Err(format!("unknown type {}", token));
+            }
+        };
+        let fields: Vec<&str> = tokens.collect();
+
+        if ttl.is_some() {
+            self.last_ttl = ttl;
+        }
+        let ttl = match ttl.or(self.default_ttl).or(self.last_ttl) {
+            Some(ttl) => ttl,
+            // the first SOA may go without, its minimum field used to be the default
+            None if qtype == QueryType::SOA => {
+                let mut record = parse_rdata(domain, 0, qtype, &fields, origin)?;
+                if let DnsRecord::SOA { minimum, .. } = record {
+                    record.set_ttl(minimum);
+                    self.last_ttl = Some(minimum);
+                }
+                return Ok(record);
+            }
+            None => return Err("no TTL given and no $TTL before".into()),
+        };
+        parse_rdata(domain, ttl, qtype, &fields, origin)
+    }
+}
+
+/// one record or directive, with its parentheses joined up
+#[derive(Debug)]
+struct Entry {
+    /// the line it starts on
+    line: usize,
+    /// whether it starts with whitespace, it has no owner of its own then
+    indented: bool,
+    /// quoted strings and escapes are kept as written
+    tokens: Vec<String>,
+}
+
+fn entries(text: &str) -> Result<Vec<Entry>, (usize, &'static str)> {
+    let mut entries = Vec::new();
+    let mut current: Option<Entry> = None;
+    let mut depth = 0;
+
+    for (i, line) in text.lines().enumerate() {
+        let bytes = line.as_bytes();
+        if depth == 0 {
+            entries.extend(current.take().filter(|entry| !entry.tokens.is_empty()));
+            current = Some(Entry {
+                line: i + 1,
+                indented: matches!(bytes.first(), Some(b' ' | b'\t')),
+                tokens: Vec::new(),
+            });
+        }
+        let Some(entry) = current.as_mut() else {
+            continue;
+        };
+
+        let mut j = 0;
+        while j < bytes.len() {
+            match bytes[j] {
+                b' ' | b'\t' | b'\r' => j += 1,
+                b';' => break,
+                b'(' => {
+                    depth += 1;
+                    j += 1;
+                }
+                b')' => {
+                    if depth == 0 {
+                        return Err((i + 1, "unbalanced parentheses"));
+                    }
+                    depth -= 1;
+                    j += 1;
+                }
+                _ => {
+                    let start = j;
+                    let mut quoted = false;
+                    while j < bytes.len() {
+                        match bytes[j] {
+                            // whatever is escaped, which may take up more than a byte
+                            b'\\' => {
+                                j += 1 + line[j + 1..].chars().next().map_or(0, char::len_utf8)
+                            }
+                            b'"' => {
+                                quoted = !quoted;
+                                j += 1;
+                            }
+                            b' ' | b'\t' | b'\r' | b';' | b'(' | b')' if !quoted => break,
+                            _ => j += 1,
+                        }
+                    }
+                    if quoted {
+                        return Err((i + 1, "unterminated quote"));
+                    }
+                    j = j.min(bytes.len());
+                    entry.tokens.push(line[start..j].to_string());
+                }
+            }
+        }
+    }
+
+    if depth > 0 {
+        let line = current.as_ref().map_or(0, |entry| entry.line);
+        return Err((line, "unbalanced parentheses"));
+    }
+    entries.extend(current.filter(|entry| !entry.tokens.is_empty()));
+    Ok(entries)
+}
+
+/// a domain name as written in zone files: "@" for the origin, relative to it unless it
+/// ends in a dot. escapes are resolved and names kept lowercase like everywhere else.
+/// labels may hold any UTF-8 but no dots, a name has nowhere to keep those
+fn name(text: &str, origin: &str) -> Result<String, String> {
+    if text == "@" {
+        return Ok(origin.to_string());
+    }
+    if text == "." {
+        return Ok(String::new());
+    }
+
+    let bytes = text.as_bytes();
+    let mut name = Vec::with_capacity(text.len() + origin.len() + 1);
+    let mut label_len = 0;
+    let mut absolute = false;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => {
+                let (b, used) = parse_escape(&bytes[i + 1..]).ok_or("bad escape in a name")?;
+                if b == b'.' {
+                    return Err(format!("escaped dot in {}", text));
+                }
+                name.push(b);
+                label_len += 1;
+                i += used;
+            }
+            b'.' => {
+                if label_len == 0 {
+                    return Err(format!("empty label in {}", text));
+                }
+                if i == bytes.len() - 1 {
+                    absolute = true;
+                } else {
+                    name.push(b'.');
+                }
+                label_len = 0;
+            }
+            b => {
+                name.push(b);
+                label_len += 1;
+            }
+        }
+        if label_len > 63 {
+            return Err(format!("label longer than 63 bytes in {}", text));
+        }
+        i += 1;
+    }
+
+    // the same lowercase a name read off the wire gets
+    let mut name = String::from_utf8(name)
+        .map_err(|_| format!("{} is not UTF-8", text))?
+        .to_lowercase();
+    if !absolute && !origin.is_empty() {
+        name.push('.');
+        name.push_str(origin);
+    }
+    // 255 bytes on the wire, counting the length bytes and the root
+    if name.len() > 253 {
+        return Err(format!("{} is longer than 255 bytes", text));
+    }
+    Ok(name)
+}
+
+/// a TTL in seconds, or with units like 1h30m the way BIND writes them
+fn parse_ttl(text: &str) -> Result<u32, String> {
+    if let Ok(ttl) = text.parse() {
+        return Ok(ttl);
+    }
+
+    let bad = || format!("bad TTL {}", text);
+    let mut total = 0u64;
+    let mut value = None;
+    for c in text.chars() {
+        if let Some(digit) = c.to_digit(10) {
+            value = Some(value.unwrap_or(0u64).saturating_mul(10) + digit as u64);
+            continue;
+        }
+        let unit = match c.to_ascii_lowercase() {
+            's' => 1,
+            'm' => 60,
+            'h' => 3600,
+            'd' => 86400,
+            'w' => 604800,
+            _ => return Err(bad()),
+        };
+        total = total.saturating_add(value.take().ok_or_else(bad)?.saturating_mul(unit));
+    }
+    if value.is_some() {
+        return Err(bad());
+    }
+    u32::try_from(total).map_err(|_| bad())
+}
+
+/// the RDATA fields of an entry, taken one after another
+struct Fields<'a> {
+    fields: std::slice::Iter<'a, &'a str>,
+    origin: &'a str,
+}
+
+impl<'a> Fields<'a> {
+    fn next(&mut self) -> Result<&'a str, String> {
+        self.fields
+            .next()
+            .copied()
+            .ok_or_else(|| "missing RDATA field".to_string())
+    }
+
+    fn number<N: FromStr>(&mut self) -> Result<N, String> {
+        let text = self.next()?;
+        text.parse().map_err(|_| format!("bad number {}", text))
+    }
+
+    fn name(&mut self) -> Result<String, String> {
+        name(self.next()?, self.origin)
+    }
+
+    fn ttl(&mut self) -> Result<u32, String> {
+        parse_ttl(self.next()?)
+    }
+
+    fn qtype(&mut self) -> Result<QueryType, String> {
+        let text = self.next()?;
+        QueryType::from_name(text).ok_or_else(|| format!("unknown type {}", text))
+    }
+
+    fn timestamp(&mut self) -> Result<u32, String> {
+        let text = self.next()?;
+        parse_timestamp(text).ok_or_else(|| format!("bad time {}", text))
+    }
+
+    /// an NSEC3 salt, "-" when there is none
+    fn salt(&mut self) -> Result<Vec<u8>, String> {
+        match self.next()? {
+            "-" => Ok(Vec::new()),
+            text => hex_decode(text).ok_or_else(|| format!("bad salt {}", text)),
+        }
+    }
+
+    /// whatever fields are left, joined up again
+    fn rest(&mut self, separator: &str) -> String {
+        self.fields
+            .by_ref()
+            .copied()
+            .collect::<Vec<_>>()
+            .join(separator)
+    }
+
+    fn types(&mut self) -> Result<Vec<QueryType>, String> {
+        let mut types = Vec::new();
+        while self.fields.len() > 0 {
+            types.push(self.qtype()?);
+        }
+        Ok(types)
+    }
+
+    fn end(&mut self) -> Result<(), String> {
+        match self.fields.next() {
+            Some(extra) => Err(format!("unexpected {} after the RDATA", extra)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// the record for the RDATA `fields` of an entry, in the presentation format of `qtype`
+/// or the generic `\# <length> <hex>` form that works for every type (RFC 3597 5)
+fn parse_rdata(
+    domain: String,
+    ttl: u32,
+    qtype: QueryType,
+    fields: &[&str],
+    origin: &str,
+) -> Result<DnsRecord, String> {
+    if fields.first() == Some(&"\\#") {
+        return parse_generic(domain, ttl, qtype, &fields[1..]);
+    }
+
+    let mut f = Fields {
+        fields: fields.iter(),
+        origin,
+    };
+    let record = match qtype {
+        QueryType::A => {
+            let text = f.next()?;
+            DnsRecord::A {
+                domain,
+                addr: Ipv4Addr::from_str(text).map_err(|_| format!("bad IPv4 address {}", text))?,
+                ttl,
+            }
+        }
+        QueryType::AAAA => {
+            let text = f.next()?;
+            DnsRecord::AAAA {
+                domain,
+                addr: Ipv6Addr::from_str(text).map_err(|_| format!("bad IPv6 address {}", text))?,
+                ttl,
+            }
+        }
+        QueryType::NS => DnsRecord::NS {
+            domain,
+            host: f.name()?,
+            ttl,
+        },
+        QueryType::CNAME => DnsRecord::CNAME {
+            domain,
+            host: f.name()?,
+            ttl,
+        },
+        QueryType::PTR => DnsRecord::PTR {
+            domain,
+            host: f.name()?,
+            ttl,
+        },
+        QueryType::DNAME => DnsRecord::DNAME {
+            domain,
+            target: f.name()?,
+            ttl,
+        },
+        QueryType::MX => DnsRecord::MX {
+            domain,
+            priority: f.number()?,
+            host: f.name()?,
+            ttl,
+        },
+        QueryType::SOA => DnsRecord::SOA {
+            domain,
+            mname: f.name()?,
+            rname: f.name()?,
+            serial: f.number()?,
+            refresh: f.ttl()?,
+            retry: f.ttl()?,
+            expire: f.ttl()?,
+            minimum: f.ttl()?,
+            ttl,
+        },
+        QueryType::TXT => {
+            let data = parse_character_strings(&f.rest(" ")).map_err(|e| e.to_string())?;
+            if data.is_empty() {
+                return Err("TXT needs at least one string".into());
+            }
+            DnsRecord::TXT { domain, data, ttl }
+        }
+        QueryType::SRV => DnsRecord::SRV {
+            domain,
+            priority: f.number()?,
+            weight: f.number()?,
+            port: f.number()?,
+            host: f.name()?,
+            ttl,
+        },
+        QueryType::SVCB | QueryType::HTTPS => {
+            let priority = f.number()?;
+            let target = f.name()?;
+            let params = parse_svc_params(&f.rest(" ")).map_err(|e| e.to_string())?;
+            if qtype == QueryType::SVCB {
+                DnsRecord::SVCB {
+                    domain,
+                    priority,
+                    target,
+                    params,
+                    ttl,
+                }
+            } else {
+                DnsRecord::HTTPS {
+                    domain,
+                    priority,
+                    target,
+                    params,
+                    ttl,
+                }
+            }
+        }
+        QueryType::CAA => {
+            let flags = f.number()?;
+            let tag = f.next()?;
+            if tag.is_empty() || !tag.bytes().all(|b| b.is_ascii_alphanumeric()) {
+                return Err(format!("bad CAA tag {}", tag));
+            }
+            let value = match parse_character_strings(f.next()?).map_err(|e| e.to_string())? {
+                strings if strings.len() == 1 => strings.into_iter().next().unwrap_or_default(),
+                _ => return Err("CAA value has to be a single string".into()),
+            };
+            DnsRecord::CAA {
+                domain,
+                flags,
+                tag: tag.to_string(),
+                value,
+                ttl,
+            }
+        }
+        QueryType::DS => {
+            let (key_tag, algorithm, digest_type) = (f.number()?, f.number()?, f.number()?);
+            let digest = hex_decode(&f.rest("")).ok_or("bad DS digest")?;
+            DnsRecord::DS {
+                domain,
+                key_tag,
+                algorithm,
+                digest_type,
+                digest,
+                ttl,
+            }
+        }
+        QueryType::DNSKEY => {
+            let (flags, protocol, algorithm) = (f.number()?, f.number()?, f.number()?);
+            let public_key = base64_decode(&f.rest("")).ok_or("bad DNSKEY public key")?;
+            DnsRecord::DNSKEY {
+                domain,
+                flags,
+                protocol,
+                algorithm,
+                public_key,
+                ttl,
+            }
+        }
+        QueryType::RRSIG => DnsRecord::RRSIG {
+            domain,
+            type_covered: f.qtype()?,
+            algorithm: f.number()?,
+            labels: f.number()?,
+            original_ttl: f.ttl()?,
+            expiration: f.timestamp()?,
+            inception: f.timestamp()?,
+            key_tag: f.number()?,
+            signer: f.name()?,
+            signature: base64_decode(&f.rest("")).ok_or("bad RRSIG signature")?,
+            ttl,
+        },
+        QueryType::NSEC => DnsRecord::NSEC {
+            domain,
+            next: f.name()?,
+            types: f.types()?,
+            ttl,
+        },
+        QueryType::NSEC3 => DnsRecord::NSEC3 {
+            domain,
+            hash_algorithm: f.number()?,
+            flags: f.number()?,
+            iterations: f.number()?,
+            salt: f.salt()?,
+            next_hashed: base32hex_decode(f.next()?).ok_or("bad NSEC3 next hashed owner")?,
+            types: f.types()?,
+            ttl,
+        },
+        QueryType::NSEC3PARAM => DnsRecord::NSEC3PARAM {
+            domain,
+            hash_algorithm: f.number()?,
+            flags: f.number()?,
+            iterations: f.number()?,
+            salt: f.salt()?,
+            ttl,
+        },
+        QueryType::OPT | QueryType::IXFR | QueryType::AXFR => {
+            return Err(format!("{} has no place in a zone file", qtype))
+        }
+        QueryType::Unknown(_) => return Err(format!("{} needs the \\# form", qtype)),
+    };
+    f.end()?;
+    Ok(record)
+}
+
+/// `<length> <hex>...`, the RDATA in hex. types we know are decoded from it
+fn parse_generic(
+    domain: String,
+    ttl: u32,
+    qtype: QueryType,
+    fields: &[&str],
+) -> Result<DnsRecord, String> {
+    let (len, hex) = fields.split_first().ok_or("missing RDATA length")?;
+    let len: usize = len
+        .parse()
+        .map_err(|_| format!("bad RDATA length {}", len))?;
+    let data = hex_decode(&hex.concat()).ok_or("bad RDATA hex")?;
+    if data.len() != len {
+        return Err(format!("RDATA is {} bytes, not {}", data.len(), len));
+    }
+
+    if let QueryType::Unknown(code) = qtype {
+        return Ok(DnsRecord::Unknown {
+            domain,
+            qtype: code,
+            class: 1,
+            data,
+            ttl,
+        });
+    }
+
+    // the record on the wire and read back, so the usual checks apply
+    let rdlength = u16::try_from(len).map_err(|_| "RDATA too long")?;
+    let mut buffer = VectorPacketBuffer::uncompressed();
+    let wire = (|| {
+        buffer.write_qname(&domain)?;
+        buffer.write_u16(qtype.into())?;
+        buffer.write_u16(1)?;
+        buffer.write_u32(ttl)?;
+        buffer.write_u16(rdlength)?;
+        for b in &data {
+            buffer.write(*b)?;
+        }
+        DnsRecord::read(&mut VectorPacketBuffer::from_bytes(buffer.as_bytes()))
+    })();
+    wire.map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn txt(domain: &str, data: &[&[u8]]) -> DnsRecord {
+        DnsRecord::TXT {
+            domain: domain.to_string(),
+            data: data.iter().map(|s| s.to_vec()).collect(),
+            ttl: 300,
+        }
+    }
+
+    #[test]
+    fn reads_back_what_display_writes() {
+        let records = vec![
+            txt("with space.example", &[b"two words", b"semi;colon"]),
+            txt("semi;colon.example", &[b"(paren)", b"\"quoted\""]),
+            txt("paren(open).example", &[b"back\\slash", b"\x00\x7f\xff"]),
+            txt("\"quote\".example", &[b"caf\xc3\xa9"]),
+            txt("back\\slash.example", &[b""]),
+            txt("caf\u{e9}.example", &[b"@ $ORIGIN"]),
+            txt("tab\tand\u{7f}.example", &[b"x"]),
+            DnsRecord::CNAME {
+                domain: "@.example".to_string(),
+                host: "$origin.example".to_string(),
+                ttl: 300,
+            },
+        ];
+        let text: String = records.iter().map(|rec| format!("{}\n", rec)).collect();
+        assert!(text.starts_with("with\\032space.example.\t"));
+        assert!(text.contains("caf\\195\\169.example."));
+
+        assert_eq!(parse_zone(&text, "example").unwrap(), records);
+    }
+
+    #[test]
+    fn resolves_escapes_in_names() {
+        assert_eq!(name("a\\066c", "example").unwrap(), "abc.example");
+        assert_eq!(name("\\(x\\).", "example").unwrap(), "(x)");
+        assert_eq!(name("Caf\u{c9}", "").unwrap(), "caf\u{e9}");
+        assert!(name("a\\.b", "example").is_err());
+        assert!(name("\\255", "example").is_err());
+        assert!(name("a\\", "example").is_err());
+    }
+
+    /// owner, type and TTL of each record
+    fn summary(records: &[DnsRecord]) -> Vec<(&str, QueryType, u32)> {
+        records
+            .iter()
+            .map(|rec| (rec.domain(), rec.qtype(), rec.ttl()))
+            .collect()
+    }
+
+    #[test]
+    fn follows_origin_and_ttl_directives() {
+        let text = "$ORIGIN Example.\n\
+                    $TTL 1h\n\
+                    @ IN SOA ns hostmaster.example. ( 1 ; serial\n\
+                    \t3600 600 ; refresh, retry\n\
+                    \t86400 60 )\n\
+                    \tNS ns ; the owner of the line before\n\
+                    ; nothing but a comment\n\
+                    \n\
+                    ns 60 A 192.0.2.53\n\
+                    www IN A 192.0.2.1\n\
+                    $ORIGIN sub\n\
+                    host A 192.0.2.2\n\
+                    elsewhere.test. 2m A 192.0.2.3\n";
+        let records = parse_zone(text, "ignored").unwrap();
+        assert_eq!(
+            summary(&records),
+            [
+                ("example", QueryType::SOA, 3600),
+                ("example", QueryType::NS, 3600),
+                ("ns.example", QueryType::A, 60),
+                ("www.example", QueryType::A, 3600),
+                ("host.sub.example", QueryType::A, 3600),
+                ("elsewhere.test", QueryType::A, 120),
+            ]
+        );
+        let DnsRecord::SOA {
+            mname,
+            rname,
+            serial,
+            minimum,
+            ..
+        } = &records[0]
+        else {
+            unreachable!()
+        };
+        assert_eq!(
+            (mname.as_str(), rname.as_str(), *serial, *minimum),
+            ("ns.example", "hostmaster.example", 1, 60)
+        );
+        assert_eq!(
+            records[1],
+            DnsRecord::NS {
+                domain: "example".to_string(),
+                host: "ns.example".to_string(),
+                ttl: 3600,
+            }
+        );
+    }
+
+    #[test]
+    fn ttls_default_to_the_last_one_without_a_ttl_directive() {
+        let text = "@ SOA ns hostmaster 1 3600 600 86400 60\n\
+                    www A 192.0.2.1\n\
+                    mail 30 A 192.0.2.2\n\
+                    \tAAAA 2001:db8::2\n";
+        assert_eq!(
+            summary(&parse_zone(text, "example.").unwrap()),
+            [
+                ("example", QueryType::SOA, 60),
+                ("www.example", QueryType::A, 60),
+                ("mail.example", QueryType::A, 30),
+                ("mail.example", QueryType::AAAA, 30),
+            ]
+        );
+        assert_eq!(
+            parse_zone("www A 192.0.2.1\n", "example").unwrap_err(),
+            ZoneError {
+                file: None,
+                line: 1,
+                reason: "no TTL given and no $TTL before".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn reports_where_entries_go_wrong() {
+        let error = |text: &str| parse_zone(text, "example").unwrap_err();
+        assert_eq!(error("$TTL 60\n\n\tA 192.0.2.1\n").line, 3);
+        assert_eq!(error("$TTL 60\nwww A ( 192.0.2.1\n").line, 2);
+        assert_eq!(error("$TTL 60\nwww A 192.0.2.1 )\n").line, 2);
+        assert_eq!(error("$TTL 60\n$GENERATE 1-2 x A 192.0.2.1\n").line, 2);
+        assert_eq!(error("$TTL 60\nwww CH A 192.0.2.1\n").line, 2);
+    }
+
+    #[test]
+    fn includes_files_relative_to_the_including_one() {
+        let dir = std::env::temp_dir().join(format!("dns-include-{}", std::process::id()));
+        fs::create_dir_all(dir.join("hosts")).unwrap();
+        fs::write(
+            dir.join("example.zone"),
+            "$TTL 300\n\
+             @ SOA ns hostmaster 1 3600 600 86400 60\n\
+             $INCLUDE hosts/www.zone www\n\
+             \tTXT \"back in the including file\"\n\
+             mail A 192.0.2.2\n",
+        )
+        .unwrap();
+        // origin and $TTL of an included file dont leak out of it
+        fs::write(
+            dir.join("hosts/www.zone"),
+            "$TTL 60\n@ A 192.0.2.1\n$ORIGIN test.\n$INCLUDE more.zone\n",
+        )
+        .unwrap();
+        fs::write(dir.join("hosts/more.zone"), "other A 192.0.2.3\n").unwrap();
+
+        let records = read_zone_file(&dir.join("example.zone"), "example").unwrap();
+        assert_eq!(
+            summary(&records),
+            [
+                ("example", QueryType::SOA, 300),
+                ("www.example", QueryType::A, 60),
+                ("other.test", QueryType::A, 60),
+                ("example", QueryType::TXT, 300),
+                ("mail.example", QueryType::A, 300),
+            ]
+        );
+
+        // a file including itself stops at the limit, with the error where it gave up
+        let looping = dir.join("loop.zone");
+        fs::write(&looping, "$INCLUDE loop.zone\n").unwrap();
+        assert_eq!(
+            read_zone_file(&looping, "example").unwrap_err(),
+            ZoneError {
+                file: Some(looping),
+                line: 1,
+                reason: "$INCLUDE nested too deep".to_string(),
+            }
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}